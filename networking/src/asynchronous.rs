@@ -0,0 +1,5 @@
+mod proxy;
+mod socket;
+
+pub use proxy::{AsyncProxy, AsyncProxyController};
+pub use socket::AsyncSocket;
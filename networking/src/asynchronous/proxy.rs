@@ -0,0 +1,656 @@
+use crate::proxy::{
+    rpc::Outgoing, DisconnectReason, OverflowPolicy, ProxyConfig, ProxyError, ProxyMessage,
+    SendError,
+};
+
+// How long the other side has to answer the handshake
+const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+// How many times per second the stats are updated when the config has no run_tps
+const DEFAULT_STATS_TPS: u64 = 10;
+
+// The messages of the controller wait in its queue while that much is still to be written,
+// so the overflow policy applies to a peer that doesn't read
+const MAX_PENDING_BYTES: usize = 256 * 1024;
+
+// How long what is still queued has to be written once the proxy stops
+const EXIT_FLUSH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Same as [`crate::Proxy`], but it's a tokio task instead of a thread
+///
+/// It waits on the socket and on the controller instead of polling them,
+/// the run_tps of the config is only the rate at which the stats are updated and pings are sent (10 by default),
+/// the heartbeat is checked at that rate too.
+/// The queue of the config bounds the messages of the controller, but [`OverflowPolicy::DropOldest`] is not supported.
+/// The frames are handled as they come, everything is sent on channel 0 and there is no TLS yet,
+/// so a config with a batch, channels or TLS that are not the default makes the proxy exit with a [`ProxyError::Config`].
+/// It can answer requests with [`AsyncProxyController::respond`] but not send them, the responses it gets are dropped
+pub struct AsyncProxy<
+    SRCW: crate::Message,
+    SWCR: crate::Message,
+    C: crate::Codec = crate::codec::Bincode,
+> {
+    cfg: ProxyConfig,
+    socket_opt: Option<super::AsyncSocket<SRCW, SWCR, C>>,
+    // The address the socket is connected to, one of the addresses cfg.addr resolves to
+    peer_addr_opt: Option<std::net::SocketAddr>,
+    // Some while the proxy is trying to reconnect
+    reconnection_opt: Option<crate::proxy::reconnect::Reconnection>,
+    heartbeat: crate::proxy::heartbeat::Heartbeat,
+    receiver: tokio::sync::mpsc::Receiver<Outgoing<SWCR>>,
+    sender: tokio::sync::mpsc::UnboundedSender<ProxyMessage<SRCW>>,
+    // By the DropNewest policy of the controller
+    dropped: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    running: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    connected: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    stats: crate::NetworkStats<SRCW, SWCR>,
+    stats_in: triple_buffer::Input<crate::NetworkStats<SRCW, SWCR>>,
+}
+
+// What woke the proxy up
+enum Event<SRCW: crate::Message, SWCR: crate::Message> {
+    Distant(
+        Result<(crate::socket::Header, crate::socket::Frame<SRCW>), crate::socket::SocketError>,
+    ),
+    Local(Option<Outgoing<SWCR>>),
+    Tick,
+}
+
+impl<SRCW: crate::Message + 'static, SWCR: crate::Message + 'static, C: crate::Codec>
+    AsyncProxy<SRCW, SWCR, C>
+{
+    /// Has to be called from a tokio runtime
+    pub fn start_new(
+        cfg: ProxyConfig,
+        stream_opt: Option<tokio::net::TcpStream>,
+    ) -> AsyncProxyController<SRCW, SWCR, C> {
+        use {
+            std::sync::{
+                atomic::{AtomicBool, AtomicU64},
+                Arc,
+            },
+            triple_buffer::TripleBuffer,
+        };
+
+        // A channel can't be empty, the proxy exits right away with a capacity of 0 anyway, see check
+        let (local_sender, receiver) = tokio::sync::mpsc::channel(cfg.queue.capacity.max(1));
+        let (sender, local_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let dropped = Arc::new(AtomicU64::new(0));
+        let overflow = cfg.queue.overflow;
+
+        let running = Arc::new(AtomicBool::new(true));
+        // Set once the handshake is done, in the proxy's task
+        let connected = Arc::new(AtomicBool::new(false));
+
+        let stats = crate::NetworkStats::new(cfg.stat_cfg);
+        let (stats_in, stats_out) = TripleBuffer::new(&stats).split();
+
+        let (socket_opt, peer_addr_opt, socket_error_opt) = match Self::check(&cfg).and_then(|()| {
+            stream_opt
+                .map(|stream| Self::accept(&cfg, stream))
+                .transpose()
+        }) {
+            Ok(Some((socket, addr))) => (Some(socket), Some(addr), None),
+            Ok(None) => (None, None, None),
+            Err(e) => (None, None, Some(e)),
+        };
+
+        let heartbeat = crate::proxy::heartbeat::Heartbeat::new(cfg.heartbeat);
+        let mut proxy = AsyncProxy::<SRCW, SWCR, C> {
+            cfg,
+            socket_opt,
+            peer_addr_opt,
+            reconnection_opt: None,
+            heartbeat,
+            receiver,
+            sender,
+            dropped: dropped.clone(),
+            running: running.clone(),
+            connected: connected.clone(),
+            stats,
+            stats_in,
+        };
+
+        if let Some(e) = socket_error_opt {
+            proxy.handle_error(e);
+        }
+        let task_handle = tokio::spawn(proxy.run());
+
+        AsyncProxyController {
+            stats: stats_out,
+            sender: local_sender,
+            overflow,
+            dropped,
+            receiver: local_receiver,
+            running,
+            connected,
+            task_handle,
+            codec: std::marker::PhantomData,
+        }
+    }
+
+    // The config of the sync proxy, without what this one can't do
+    fn check(cfg: &ProxyConfig) -> Result<(), ProxyError> {
+        cfg.check()?;
+
+        let unsupported = if cfg.queue.overflow == OverflowPolicy::DropOldest {
+            "The DropOldest overflow policy"
+        } else if cfg.batch != Default::default() {
+            "A batch config"
+        } else if cfg.channels != Default::default() {
+            "A channel config"
+        } else if cfg.tls.is_some() {
+            "TLS"
+        } else {
+            return Ok(());
+        };
+
+        Err(ProxyError::Config(format!(
+            "{unsupported} is not supported by the async proxy"
+        )))
+    }
+
+    // The given stream gets the same options as the ones the proxy connects
+    fn accept(
+        cfg: &ProxyConfig,
+        stream: tokio::net::TcpStream,
+    ) -> Result<(super::AsyncSocket<SRCW, SWCR, C>, std::net::SocketAddr), ProxyError> {
+        let addr = stream.peer_addr().map_err(|e| {
+            ProxyError::Config(format!(
+                "Could not get the address of the given stream: {e}"
+            ))
+        })?;
+        cfg.tcp
+            .tune(socket2::SockRef::from(&stream))
+            .map_err(|e| ProxyError::Config(format!("Could not set up the given stream: {e}")))?;
+        Ok((Self::new_socket(cfg, stream), addr))
+    }
+
+    fn new_socket(
+        cfg: &ProxyConfig,
+        stream: tokio::net::TcpStream,
+    ) -> super::AsyncSocket<SRCW, SWCR, C> {
+        let mut socket = super::AsyncSocket::new(stream);
+        socket.set_max_frame_size(cfg.max_frame_size);
+        socket.set_compression(cfg.compression);
+        socket.set_write_timeout(cfg.tcp.write_timeout);
+        socket
+    }
+
+    async fn handshake(
+        socket: &mut super::AsyncSocket<SRCW, SWCR, C>,
+        addr: std::net::SocketAddr,
+    ) -> Result<(), ProxyError> {
+        socket
+            .handshake(HANDSHAKE_TIMEOUT)
+            .await
+            .map_err(|error| ProxyError::Handshake { addr, error })
+    }
+
+    async fn try_connect(&mut self) -> Result<(), ProxyError> {
+        trace!("Trying to reconnect");
+        let addrs = self.cfg.addr.resolve_async().await?;
+        let (stream, addr) = self.cfg.tcp.connect_async(&addrs).await?;
+
+        if let Err(e) = self.cfg.tcp.tune(socket2::SockRef::from(&stream)) {
+            error!("Could not set up the created stream: {e}");
+            return Err(ProxyError::Config(format!(
+                "Could not set up the stream due to: {e}"
+            )));
+        }
+        let mut socket = Self::new_socket(&self.cfg, stream);
+        Self::handshake(&mut socket, addr).await?;
+
+        self.socket_opt = Some(socket);
+        if !self.cfg.keep_msg_while_disconnected {
+            while let Ok(value) = self.receiver.try_recv() {
+                drop(value)
+            }
+        }
+        self.on_connect(addr);
+        Ok(())
+    }
+
+    // Same as the one of the sync proxy
+    async fn reconnect(&mut self) -> bool {
+        use std::sync::atomic::Ordering;
+
+        let Some(policy) = &self.cfg.reconnect else {
+            return false;
+        };
+        let reconnection = self
+            .reconnection_opt
+            .get_or_insert_with(|| crate::proxy::reconnect::Reconnection::new(policy.clone()));
+
+        let Some(delay) = reconnection.next_attempt() else {
+            warn!(
+                "Gave up reconnecting to {} after {} attempts",
+                self.cfg.addr,
+                reconnection.attempt()
+            );
+            self.notify(ProxyMessage::GaveUp);
+            return false;
+        };
+        let attempt = reconnection.attempt();
+        self.notify(ProxyMessage::Reconnecting { attempt });
+
+        tokio::time::sleep(delay).await;
+        if !self.running.load(Ordering::Acquire) {
+            return false;
+        }
+
+        if let Err(e) = self.try_connect().await {
+            self.handle_error(e);
+        }
+
+        if self.socket_opt.is_some() {
+            debug!("Reconnected to {} after {attempt} attempts", self.cfg.addr);
+            self.reconnection_opt = None;
+            self.stats.on_reconnect();
+            self.notify(ProxyMessage::Reconnected);
+        }
+
+        true
+    }
+
+    fn set_connected(&mut self, val: bool) {
+        use std::sync::atomic::Ordering;
+        self.connected.store(val, Ordering::Release)
+    }
+
+    fn set_running(&mut self, val: bool) {
+        use std::sync::atomic::Ordering;
+        self.running.store(val, Ordering::Release)
+    }
+
+    fn on_connect(&mut self, addr: std::net::SocketAddr) {
+        self.peer_addr_opt = Some(addr);
+        self.heartbeat.reset();
+        self.set_connected(true);
+        self.notify(ProxyMessage::Connected { addr });
+    }
+
+    fn reset_connection(&mut self, reason: DisconnectReason) {
+        self.set_connected(false);
+        self.socket_opt = None;
+        self.stats.on_disconnect();
+        self.notify(ProxyMessage::Disconnected { reason });
+    }
+
+    fn notify(&mut self, msg: ProxyMessage<SRCW>) {
+        if let Err(e) = self.sender.send(msg) {
+            error!("Could not send {:?} message to the controller", e.0);
+            self.set_running(false);
+        }
+    }
+
+    // Same as the one of the sync proxy
+    fn handle_error(&mut self, error: ProxyError) {
+        use crate::socket::{HandshakeError, SocketError};
+
+        match error {
+            ProxyError::ChannelSend | ProxyError::ChannelRecv => {
+                error!("{error}");
+                self.set_running(false);
+            }
+            ProxyError::Config(_) => {
+                warn!("{error}");
+                self.set_running(false);
+                self.notify(ProxyMessage::Error(error));
+            }
+            ProxyError::Resolve { .. } | ProxyError::Connect { .. } => {
+                match &self.reconnection_opt {
+                    Some(reconnection) => {
+                        debug!("Reconnection attempt {}: {error}", reconnection.attempt())
+                    }
+                    None => error!("{error}"),
+                }
+                self.notify(ProxyMessage::Error(error));
+            }
+            ProxyError::Handshake {
+                error:
+                    SocketError::Handshake(
+                        HandshakeError::Magic
+                        | HandshakeError::Version { .. }
+                        | HandshakeError::Messages { .. },
+                    ),
+                ..
+            } => {
+                error!("{error}");
+                self.socket_opt = None;
+                self.set_running(false);
+                self.notify(ProxyMessage::Error(error));
+            }
+            ProxyError::Handshake { .. } => {
+                warn!("{error}");
+                self.reset_connection(DisconnectReason::Error(error));
+            }
+            ProxyError::SocketRecv(SocketError::Exited) => {
+                debug!(
+                    "socket {addr} has closed the connection",
+                    addr = self.cfg.addr
+                );
+                self.reset_connection(DisconnectReason::Closed);
+            }
+            ProxyError::SocketSend(_) | ProxyError::SocketRecv(_) => {
+                error!("Error with socket {addr}: {error}", addr = self.cfg.addr);
+                self.reset_connection(DisconnectReason::Error(error));
+            }
+            ProxyError::Disconnected => {}
+        }
+    }
+
+    async fn run(mut self) {
+        use std::sync::atomic::Ordering;
+
+        let mut ticker = tokio::time::interval(std::time::Duration::from_secs_f64(
+            1. / self.cfg.run_tps.unwrap_or(DEFAULT_STATS_TPS) as f64,
+        ));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+        match (&mut self.socket_opt, self.peer_addr_opt) {
+            // The stream was given by the user, the handshake is still needed
+            (Some(socket), Some(addr)) => match Self::handshake(socket, addr).await {
+                Ok(()) => self.on_connect(addr),
+                Err(e) => self.handle_error(e),
+            },
+            (None, _) if self.running.load(Ordering::Acquire) => {
+                if let Err(e) = self.try_connect().await {
+                    self.handle_error(e)
+                }
+            }
+            _ => (),
+        }
+
+        while self.running.load(Ordering::Acquire) {
+            let Some(socket) = &mut self.socket_opt else {
+                if !self.reconnect().await {
+                    break;
+                }
+
+                continue;
+            };
+
+            // The controller's messages wait while the peer is slow to take what is already queued
+            let can_queue = socket.pending_bytes() < MAX_PENDING_BYTES;
+
+            // recv_frame is cancel safe, the frame will still be there next time, and it writes what is queued
+            let event = tokio::select! {
+                res = socket.recv_frame() => Event::Distant(res),
+                msg = self.receiver.recv(), if can_queue => Event::Local(msg),
+                _ = ticker.tick() => Event::Tick,
+            };
+
+            let res = match event {
+                Event::Distant(res) => self.handle_distant(res),
+                Event::Local(msg) => self.handle_local(msg),
+                Event::Tick => self.update_stats(),
+            };
+
+            if let Err(e) = res {
+                self.handle_error(e);
+            }
+        }
+
+        // Before the exit message, so the controller sees it as stopped once it gets it
+        self.set_running(false);
+        self.set_connected(false);
+
+        if let Err(e) = self.sender.send(ProxyMessage::Exit) {
+            error!("Could not send exit message to the controller: {e}")
+        }
+
+        if let Some(mut socket) = self.socket_opt.take() {
+            if let Err(e) = tokio::time::timeout(EXIT_FLUSH_TIMEOUT, socket.flush())
+                .await
+                .unwrap_or_else(|_| {
+                    Err(crate::socket::SocketError::StreamWrite(
+                        std::io::ErrorKind::TimedOut.into(),
+                    ))
+                })
+            {
+                debug!("Could not write what was still queued: {e}");
+            }
+            socket.shutdown().await;
+        }
+
+        debug!("Async proxy for ({}) has exited", self.cfg.addr);
+    }
+
+    fn update_stats(&mut self) -> Result<(), ProxyError> {
+        use std::sync::atomic::Ordering;
+
+        if self.socket_opt.is_some() && self.heartbeat.is_dead() {
+            warn!(
+                "Nothing received from {} for {:?}, resetting the connection",
+                self.cfg.addr,
+                self.heartbeat.silence()
+            );
+            self.reset_connection(DisconnectReason::TimedOut);
+        }
+
+        if let Some(socket) = &mut self.socket_opt {
+            // Same as the sync proxy, a single ping is in flight so its pong can't be taken for another's
+            if self.heartbeat.needs_ping() {
+                if !self.stats.is_waiting_pong() {
+                    let header = socket
+                        .queue_frame(crate::socket::FrameKind::Ping, 0, &[], None)
+                        .map_err(ProxyError::SocketSend)?;
+                    self.stats.on_ping_send();
+                    self.stats.on_bytes_send(&header);
+                }
+                self.heartbeat.on_ping();
+            }
+            if self.stats.needs_ping() {
+                let header = socket
+                    .queue_frame(crate::socket::FrameKind::Ping, 0, &[], None)
+                    .map_err(ProxyError::SocketSend)?;
+                self.stats.on_ping_send();
+                self.stats.on_bytes_send(&header);
+            }
+        }
+
+        self.stats
+            .on_queue_update(self.receiver.len(), self.dropped.load(Ordering::Relaxed));
+        self.stats.update_windows();
+        self.stats_in.write(self.stats.clone());
+
+        Ok(())
+    }
+
+    /// here you receive the message sent by the controller
+    fn handle_local(&mut self, outgoing: Option<Outgoing<SWCR>>) -> Result<(), ProxyError> {
+        let Some(outgoing) = outgoing else {
+            // The controller has been dropped
+            return Err(ProxyError::ChannelRecv);
+        };
+
+        let Some(socket) = &mut self.socket_opt else {
+            return Err(ProxyError::Disconnected);
+        };
+
+        // Written by recv_frame
+        let (name_opt, res) = match outgoing {
+            Outgoing::Message(_, msg) => {
+                (self.stats.message_type_name_opt(&msg), socket.queue(msg))
+            }
+            Outgoing::Request(id, msg) => (
+                self.stats.message_type_name_opt(&msg),
+                socket.queue_data(crate::socket::FrameKind::Request, id, msg),
+            ),
+            Outgoing::Response(id, msg) => (
+                self.stats.message_type_name_opt(&msg),
+                socket.queue_data(crate::socket::FrameKind::Response, id, msg),
+            ),
+        };
+
+        match res {
+            Ok(header) => {
+                self.stats.on_bytes_send(&header);
+                self.stats.on_message_send(name_opt, &header);
+                Ok(())
+            }
+            Err(e) => {
+                error!(
+                    "Proxy encountered an error while forwarding a message to the server: {e:?}"
+                );
+                Err(ProxyError::SocketSend(e))
+            }
+        }
+    }
+
+    /// here you receive the frames sent by the socket
+    fn handle_distant(
+        &mut self,
+        res: Result<
+            (crate::socket::Header, crate::socket::Frame<SRCW>),
+            crate::socket::SocketError,
+        >,
+    ) -> Result<(), ProxyError> {
+        use crate::socket::{Frame, FrameKind};
+
+        let Some(socket) = &mut self.socket_opt else {
+            return Err(ProxyError::Disconnected);
+        };
+
+        if res.is_ok() {
+            self.heartbeat.on_recv();
+        }
+
+        match res {
+            Ok((header, Frame::Data(msg))) => {
+                self.stats.on_bytes_recv(&header);
+                self.stats
+                    .on_message_recv(self.stats.message_type_name_opt(&msg), &header);
+
+                let proxy_msg = match header.kind {
+                    // Nothing waits for it, see the doc of AsyncProxy
+                    FrameKind::Response => {
+                        debug!(
+                            "Dropped the response to request {}, the async proxy can't send requests",
+                            header.request_id
+                        );
+                        return Ok(());
+                    }
+                    FrameKind::Request => ProxyMessage::Request {
+                        id: header.request_id,
+                        msg,
+                    },
+                    _ => ProxyMessage::Forward(msg),
+                };
+
+                self.sender
+                    .send(proxy_msg)
+                    .map_err(|_| ProxyError::ChannelSend)
+            }
+            Ok((header, Frame::Ping)) => {
+                self.stats.on_bytes_recv(&header);
+
+                let header = socket
+                    .queue_frame(FrameKind::Pong, 0, &[], None)
+                    .map_err(ProxyError::SocketSend)?;
+                self.stats.on_bytes_send(&header);
+
+                Ok(())
+            }
+            Ok((header, Frame::Pong)) => {
+                self.stats.on_bytes_recv(&header);
+                self.stats.on_pong_recv();
+
+                Ok(())
+            }
+            // A close frame too, handle_error drops the socket
+            Err(e) => Err(ProxyError::SocketRecv(e)),
+        }
+    }
+}
+
+/// The controller of an [`AsyncProxy`], same as [`crate::proxy::ProxyController`] but the receiving is async
+pub struct AsyncProxyController<
+    R: crate::Message,
+    W: crate::Message,
+    C: crate::Codec = crate::codec::Bincode,
+> {
+    stats: triple_buffer::Output<crate::NetworkStats<R, W>>,
+    sender: tokio::sync::mpsc::Sender<Outgoing<W>>,
+    overflow: OverflowPolicy,
+    dropped: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    receiver: tokio::sync::mpsc::UnboundedReceiver<ProxyMessage<R>>,
+    running: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    connected: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    task_handle: tokio::task::JoinHandle<()>,
+    codec: std::marker::PhantomData<C>,
+}
+
+impl<R: crate::Message, W: crate::Message, C: crate::Codec> AsyncProxyController<R, W, C> {
+    /// Waits for some room in the queue, or follows the [`OverflowPolicy`] of the config
+    pub async fn send(&self, msg: W) -> Result<(), SendError<W>> {
+        self.push(Outgoing::Message(0, msg)).await
+    }
+
+    /// Same as [`AsyncProxyController::send`], but never waits or drops, a full queue is an error
+    pub fn try_send(&self, msg: W) -> Result<(), SendError<W>> {
+        self.try_push(Outgoing::Message(0, msg))
+    }
+
+    /// Answers a [`ProxyMessage::Request`] with its id
+    pub async fn respond(&self, id: u64, msg: W) -> Result<(), SendError<W>> {
+        self.push(Outgoing::Response(id, msg)).await
+    }
+
+    async fn push(&self, outgoing: Outgoing<W>) -> Result<(), SendError<W>> {
+        use std::sync::atomic::Ordering;
+
+        match self.overflow {
+            OverflowPolicy::Block => self
+                .sender
+                .send(outgoing)
+                .await
+                .map_err(|e| SendError::Disconnected(e.0.into_inner())),
+            OverflowPolicy::DropNewest => match self.try_push(outgoing) {
+                Err(SendError::Full(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    Ok(())
+                }
+                res => res,
+            },
+            // DropOldest makes the proxy exit, the queue is closed then
+            OverflowPolicy::Error | OverflowPolicy::DropOldest => self.try_push(outgoing),
+        }
+    }
+
+    fn try_push(&self, outgoing: Outgoing<W>) -> Result<(), SendError<W>> {
+        use tokio::sync::mpsc::error::TrySendError;
+
+        self.sender.try_send(outgoing).map_err(|e| match e {
+            TrySendError::Full(outgoing) => SendError::Full(outgoing.into_inner()),
+            TrySendError::Closed(outgoing) => SendError::Disconnected(outgoing.into_inner()),
+        })
+    }
+
+    /// None once the proxy has exited and everything it sent has been received
+    pub async fn recv(&mut self) -> Option<ProxyMessage<R>> {
+        self.receiver.recv().await
+    }
+
+    pub fn try_recv(&mut self) -> Result<ProxyMessage<R>, tokio::sync::mpsc::error::TryRecvError> {
+        self.receiver.try_recv()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    // Needs mut because it's updating before returning the data
+    pub fn stats(&mut self) -> &crate::NetworkStats<R, W> {
+        self.stats.read()
+    }
+
+    pub fn task_handle(&self) -> &tokio::task::JoinHandle<()> {
+        &self.task_handle
+    }
+}
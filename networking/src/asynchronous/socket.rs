@@ -0,0 +1,436 @@
+use crate::socket::{frame, Frame, FrameKind, Header, SocketError};
+
+// How much is read from the stream at once
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Same as [`crate::Socket`], over a tokio stream
+///
+/// The frames are the same, an AsyncSocket can talk to a Socket.
+/// The queued frames are written while [`AsyncSocket::recv_frame`] waits, so both sides can send a lot at once
+/// without waiting on each other.
+/// Nothing is sent when it's dropped, call [`AsyncSocket::close`] to let the other side know
+pub struct AsyncSocket<
+    R: crate::Message,
+    W: crate::Message,
+    C: crate::Codec = crate::codec::Bincode,
+> {
+    stream: tokio::net::TcpStream,
+    read_type: std::marker::PhantomData<R>,
+    write_type: std::marker::PhantomData<W>,
+    codec: std::marker::PhantomData<C>,
+
+    // Bytes received but not parsed yet are in recv_buffer[recv_start..]
+    recv_buffer: Vec<u8>,
+    recv_start: usize,
+
+    // Bytes of the frames that the stream did not accept yet are in send_buffer[send_start..]
+    send_buffer: Vec<u8>,
+    send_start: usize,
+
+    // The chunks of the messages that are not fully received yet, this socket sends everything on channel 0 in one frame
+    reassembly: crate::socket::channel::Reassembly,
+
+    max_frame_size: u64,
+    compression: Option<crate::Compression>,
+    // The compressions the other side said it reads in its handshake
+    peer_compressions: u32,
+    write_timeout: Option<std::time::Duration>,
+    // Same as the one of Socket, the stream has not accepted anything since then
+    write_stalled_since: Option<std::time::Instant>,
+}
+
+impl<R: crate::Message, W: crate::Message, C: crate::Codec> AsyncSocket<R, W, C> {
+    pub fn new(stream: tokio::net::TcpStream) -> Self {
+        Self {
+            stream,
+            read_type: std::marker::PhantomData,
+            write_type: std::marker::PhantomData,
+            codec: std::marker::PhantomData,
+            recv_buffer: Vec::new(),
+            recv_start: 0,
+            send_buffer: Vec::new(),
+            send_start: 0,
+            reassembly: Default::default(),
+            max_frame_size: crate::socket::DEFAULT_MAX_FRAME_SIZE,
+            compression: None,
+            peer_compressions: 0,
+            write_timeout: None,
+            write_stalled_since: None,
+        }
+    }
+
+    /// See [`crate::Socket::set_max_frame_size`]
+    pub fn set_max_frame_size(&mut self, max_frame_size: u64) {
+        self.max_frame_size = max_frame_size
+    }
+
+    pub fn max_frame_size(&self) -> u64 {
+        self.max_frame_size
+    }
+
+    /// See [`crate::Socket::set_compression`]
+    pub fn set_compression(&mut self, compression: Option<crate::Compression>) {
+        self.compression = compression
+    }
+
+    pub fn compression(&self) -> Option<crate::Compression> {
+        self.compression
+    }
+
+    /// The sends and [`AsyncSocket::recv_frame`] fail with a TimedOut error when the stream has not accepted
+    /// any of the queued bytes for that long, None to wait forever
+    pub fn set_write_timeout(&mut self, timeout: Option<std::time::Duration>) {
+        self.write_timeout = timeout
+    }
+
+    pub fn write_timeout(&self) -> Option<std::time::Duration> {
+        self.write_timeout
+    }
+
+    /// When the write timeout is reached if the stream still doesn't accept anything, None if nothing is queued
+    pub fn write_deadline(&self) -> Option<std::time::Instant> {
+        Some(self.write_stalled_since? + self.write_timeout?)
+    }
+
+    /// Queues the message and waits for everything queued to be written
+    ///
+    /// Cancel safe, the frame stays queued
+    pub async fn send(&mut self, message: W) -> Result<Header, SocketError> {
+        let header = self.queue(message)?;
+        self.flush().await?;
+        Ok(header)
+    }
+
+    /// See [`crate::Socket::send_request`]
+    pub async fn send_request(
+        &mut self,
+        request_id: u64,
+        message: W,
+    ) -> Result<Header, SocketError> {
+        let header = self.queue_data(FrameKind::Request, request_id, message)?;
+        self.flush().await?;
+        Ok(header)
+    }
+
+    /// See [`crate::Socket::send_response`]
+    pub async fn send_response(
+        &mut self,
+        request_id: u64,
+        message: W,
+    ) -> Result<Header, SocketError> {
+        let header = self.queue_data(FrameKind::Response, request_id, message)?;
+        self.flush().await?;
+        Ok(header)
+    }
+
+    /// Same as [`AsyncSocket::send`], but nothing is written until [`AsyncSocket::flush`]
+    /// or [`AsyncSocket::recv_frame`] is called
+    pub fn queue(&mut self, message: W) -> Result<Header, SocketError> {
+        self.queue_data(FrameKind::Data, 0, message)
+    }
+
+    pub(crate) fn queue_data(
+        &mut self,
+        kind: FrameKind,
+        request_id: u64,
+        message: W,
+    ) -> Result<Header, SocketError> {
+        let compression = self
+            .compression
+            .filter(|compression| compression.is_in(self.peer_compressions));
+        let (payload, uncompressed_size) = frame::encode_data::<W, C>(&message, compression)?;
+
+        self.queue_frame(kind, request_id, &payload, uncompressed_size)
+    }
+
+    /// Asks the other side for a pong, used for the round trip time
+    pub async fn ping(&mut self) -> Result<Header, SocketError> {
+        let header = self.queue_frame(FrameKind::Ping, 0, &[], None)?;
+        self.flush().await?;
+        Ok(header)
+    }
+
+    /// Answers a ping, [`AsyncSocket::recv`] does it for you
+    pub async fn pong(&mut self) -> Result<Header, SocketError> {
+        let header = self.queue_frame(FrameKind::Pong, 0, &[], None)?;
+        self.flush().await?;
+        Ok(header)
+    }
+
+    /// Tells the other side that we are closing the communication, after what is still queued
+    pub async fn close(&mut self) -> Result<Header, SocketError> {
+        let header = self.queue_frame(FrameKind::Close, 0, &[], None)?;
+        self.flush().await?;
+        Ok(header)
+    }
+
+    // The frames without a payload (ping, pong) go through it too
+    pub(crate) fn queue_frame(
+        &mut self,
+        kind: FrameKind,
+        request_id: u64,
+        payload: &[u8],
+        uncompressed_size: Option<u64>,
+    ) -> Result<Header, SocketError> {
+        let header = frame::write_frame(
+            &mut self.send_buffer,
+            kind,
+            0,
+            request_id,
+            payload,
+            uncompressed_size,
+            self.max_frame_size,
+        )?;
+
+        if self.write_stalled_since.is_none() {
+            self.write_stalled_since = Some(std::time::Instant::now());
+        }
+
+        Ok(header)
+    }
+
+    /// Waits for every queued frame to be written
+    ///
+    /// Cancel safe, what was not written yet stays queued
+    pub async fn flush(&mut self) -> Result<(), SocketError> {
+        while self.pending_bytes() != 0 {
+            let writable = self.stream.writable();
+            match self.write_deadline() {
+                Some(deadline) => tokio::time::timeout_at(deadline.into(), writable)
+                    .await
+                    .unwrap_or_else(|_| Err(std::io::Error::from(std::io::ErrorKind::TimedOut))),
+                None => writable.await,
+            }
+            .map_err(SocketError::StreamWrite)?;
+
+            self.write_queued()?;
+        }
+
+        Ok(())
+    }
+
+    // Writes what the stream takes without waiting
+    fn write_queued(&mut self) -> Result<(), SocketError> {
+        while self.send_start != self.send_buffer.len() {
+            match self.stream.try_write(&self.send_buffer[self.send_start..]) {
+                Ok(0) => {
+                    return Err(SocketError::StreamWrite(std::io::Error::from(
+                        std::io::ErrorKind::WriteZero,
+                    )))
+                }
+                Ok(n) => {
+                    self.send_start += n;
+                    self.write_stalled_since = Some(std::time::Instant::now());
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(SocketError::StreamWrite(e)),
+            }
+        }
+
+        self.send_buffer.clear();
+        self.send_start = 0;
+        self.write_stalled_since = None;
+
+        // Don't keep the memory of a big message around
+        if self.send_buffer.capacity() > READ_CHUNK_SIZE * 4 {
+            self.send_buffer = Vec::new();
+        }
+
+        Ok(())
+    }
+
+    /// The bytes of the queued frames that the stream has not accepted yet
+    pub fn pending_bytes(&self) -> usize {
+        self.send_buffer.len() - self.send_start
+    }
+
+    /// Receives the next message, answering the pings that were received before it
+    ///
+    /// Not cancel safe as it might be writing a pong, see [`AsyncSocket::recv_frame`]
+    pub async fn recv(&mut self) -> Result<(Header, R), SocketError> {
+        loop {
+            match self.recv_frame().await? {
+                (header, Frame::Data(message)) => return Ok((header, message)),
+                (_, Frame::Ping) => {
+                    self.pong().await?;
+                }
+                (_, Frame::Pong) => (),
+            }
+        }
+    }
+
+    /// Receives the next frame, without answering pings
+    ///
+    /// The queued frames are written while it waits for the stream.
+    /// Cancel safe, so it can be used in a `tokio::select!`
+    ///
+    /// A close frame is reported as [`SocketError::Exited`]
+    pub async fn recv_frame(&mut self) -> Result<(Header, Frame<R>), SocketError> {
+        // The chunks are kept until the last piece of their message
+        loop {
+            if let Some(frame) = self.recv_piece().await? {
+                return Ok(frame);
+            }
+        }
+    }
+
+    // Still cancel safe, a chunk is stored before anything else is awaited
+    async fn recv_piece(&mut self) -> Result<Option<(Header, Frame<R>)>, SocketError> {
+        let mut header = loop {
+            match frame::buffered_frame(&self.recv_buffer[self.recv_start..], self.max_frame_size) {
+                Ok(Some(header)) => break header,
+                Ok(None) => self.fill_recv_buffer().await?,
+                Err(e) => {
+                    // We can't know where the next frame starts, nothing else can be read
+                    error!(
+                        "Closing the connection with {:?}: {e}",
+                        self.stream.peer_addr()
+                    );
+                    self.shutdown().await;
+                    return Err(e);
+                }
+            }
+        };
+
+        let payload_start = self.recv_start + crate::socket::HEADER_SIZE as usize;
+        let payload_end = payload_start + header.size as usize;
+
+        // The frame is consumed before decoding, even if the decoding fails the next frame is still readable
+        self.recv_start = payload_end;
+
+        let frame = frame::read_frame::<R, C>(
+            &mut self.reassembly,
+            &mut header,
+            &self.recv_buffer[payload_start..payload_end],
+            self.max_frame_size,
+        )?;
+
+        Ok(frame.map(|frame| (header, frame)))
+    }
+
+    // The awaits are the readiness of the stream, what is read is stored right away
+    async fn fill_recv_buffer(&mut self) -> Result<(), SocketError> {
+        // Drop what has already been parsed
+        if self.recv_start != 0 {
+            self.recv_buffer.drain(..self.recv_start);
+            self.recv_start = 0;
+
+            // Don't keep the memory of a big message around
+            if self.recv_buffer.capacity() > READ_CHUNK_SIZE * 4
+                && self.recv_buffer.len() < READ_CHUNK_SIZE
+            {
+                self.recv_buffer.shrink_to(READ_CHUNK_SIZE * 2);
+            }
+        }
+
+        self.recv_buffer.reserve(READ_CHUNK_SIZE);
+
+        loop {
+            if self.pending_bytes() == 0 {
+                self.stream
+                    .readable()
+                    .await
+                    .map_err(SocketError::StreamRead)?;
+            } else if !self.wait_readable_or_write().await? {
+                continue;
+            }
+
+            match self.stream.try_read_buf(&mut self.recv_buffer) {
+                // A read of 0 bytes means that the other side has shut the stream down
+                Ok(0) => return Err(SocketError::Exited),
+                Ok(read_len) => {
+                    trace!(
+                        "Read {read_len} bytes, {} are buffered",
+                        self.recv_buffer.len()
+                    );
+                    return Ok(());
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => (),
+                Err(e) => return Err(SocketError::StreamRead(e)),
+            }
+        }
+    }
+
+    // Writes the queued frames as the stream takes them, returns true once it can be read
+    // A peer that doesn't read before it's done writing can't block this side then
+    async fn wait_readable_or_write(&mut self) -> Result<bool, SocketError> {
+        let deadline_opt = self.write_deadline();
+        let stalled = async {
+            match deadline_opt {
+                Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            res = self.stream.readable() => res.map_err(SocketError::StreamRead).map(|()| true),
+            res = self.stream.writable() => {
+                res.map_err(SocketError::StreamWrite)?;
+                self.write_queued()?;
+                Ok(false)
+            }
+            () = stalled => Err(SocketError::StreamWrite(std::io::Error::from(
+                std::io::ErrorKind::TimedOut,
+            ))),
+        }
+    }
+
+    /// See [`crate::Socket::handshake`]
+    pub async fn handshake(&mut self, timeout: std::time::Duration) -> Result<(), SocketError> {
+        use {
+            crate::socket::{Handshake, HandshakeError, HANDSHAKE_SIZE},
+            tokio::io::AsyncWriteExt as _,
+        };
+
+        let local = Handshake::new::<R, W>();
+
+        let exchange = async {
+            let bytes = bincode::serialize(&local).map_err(|e| SocketError::Serialization(e))?;
+            self.stream
+                .write_all(&bytes)
+                .await
+                .map_err(SocketError::StreamWrite)?;
+
+            while ((self.recv_buffer.len() - self.recv_start) as u64) < HANDSHAKE_SIZE {
+                self.fill_recv_buffer().await?;
+            }
+
+            Ok::<(), SocketError>(())
+        };
+
+        tokio::time::timeout(timeout, exchange)
+            .await
+            .map_err(|_| HandshakeError::Timeout)??;
+
+        let handshake_end = self.recv_start + HANDSHAKE_SIZE as usize;
+
+        let remote: Handshake =
+            bincode::deserialize(&self.recv_buffer[self.recv_start..handshake_end])
+                .map_err(|e| SocketError::Deserialization(e))?;
+        self.recv_start = handshake_end;
+
+        local.check::<R, W>(&remote)?;
+        self.peer_compressions = remote.compressions;
+
+        debug!("Handshake with {:?} done", self.stream.peer_addr());
+
+        Ok(())
+    }
+
+    pub fn local_addr(&self) -> std::net::SocketAddr {
+        self.stream.local_addr().unwrap()
+    }
+
+    pub fn remote_addr(&self) -> std::net::SocketAddr {
+        self.stream.peer_addr().unwrap()
+    }
+
+    /// Shuts the writing side down, the other side reads it as the end of the connection
+    pub async fn shutdown(&mut self) {
+        use tokio::io::AsyncWriteExt as _;
+
+        // Don't care about the error, it's probably disconnected already
+        let _ = self.stream.shutdown().await;
+    }
+}
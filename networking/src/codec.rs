@@ -0,0 +1,30 @@
+mod bincode;
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+#[cfg(feature = "postcard")]
+mod postcard;
+
+pub use self::bincode::Bincode;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub use self::json::Json;
+#[cfg(feature = "msgpack")]
+#[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
+pub use self::msgpack::MessagePack;
+#[cfg(feature = "postcard")]
+#[cfg_attr(docsrs, doc(cfg(feature = "postcard")))]
+pub use self::postcard::Postcard;
+
+/// Whatever error the codec gave, see [`crate::socket::SocketError`]
+pub type CodecError = Box<dyn std::error::Error + Send + Sync>;
+
+/// How messages are turned into bytes (and back) by a [`crate::Socket`]
+///
+/// Only the payload goes through the codec, the frame header is always the same so both sides can read it.
+/// Both sides of a connection have to use the same codec
+pub trait Codec: Send + 'static {
+    fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, CodecError>;
+    fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError>;
+}
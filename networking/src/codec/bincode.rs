@@ -0,0 +1,12 @@
+/// bincode 1 with its default options, this is the default codec
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Bincode;
+
+impl super::Codec for Bincode {
+    fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, super::CodecError> {
+        Ok(::bincode::serialize(value)?)
+    }
+    fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, super::CodecError> {
+        Ok(::bincode::deserialize(bytes)?)
+    }
+}
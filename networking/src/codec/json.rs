@@ -0,0 +1,12 @@
+/// Human readable, usefull to debug the traffic
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Json;
+
+impl super::Codec for Json {
+    fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, super::CodecError> {
+        Ok(::serde_json::to_vec(value)?)
+    }
+    fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, super::CodecError> {
+        Ok(::serde_json::from_slice(bytes)?)
+    }
+}
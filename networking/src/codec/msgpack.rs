@@ -0,0 +1,12 @@
+/// MessagePack, structs are written as maps so other implementations can read them by field name
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MessagePack;
+
+impl super::Codec for MessagePack {
+    fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, super::CodecError> {
+        Ok(::rmp_serde::to_vec_named(value)?)
+    }
+    fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, super::CodecError> {
+        Ok(::rmp_serde::from_slice(bytes)?)
+    }
+}
@@ -0,0 +1,12 @@
+/// Compact varint based format
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Postcard;
+
+impl super::Codec for Postcard {
+    fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, super::CodecError> {
+        Ok(::postcard::to_allocvec(value)?)
+    }
+    fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, super::CodecError> {
+        Ok(::postcard::from_bytes(bytes)?)
+    }
+}
@@ -0,0 +1,92 @@
+/// How the payload of the sent frames is compressed, see [`crate::Socket::set_compression`]
+///
+/// Compressed frames are flagged in their header, so the receiving side doesn't need any config to read them.
+/// The handshake tells which compressions the other side was built with, the frames are sent uncompressed
+/// when it can't read the one that is set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Payloads smaller than `threshold` bytes are sent uncompressed, it's not worth it for small messages
+    #[cfg(feature = "lz4")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "lz4")))]
+    Lz4 { threshold: u64 },
+}
+
+// The bits of Handshake::compressions
+const LZ4: u32 = 1 << 0;
+
+impl Compression {
+    // The compressions this side can read, sent in the handshake
+    pub(crate) fn supported() -> u32 {
+        if cfg!(feature = "lz4") {
+            LZ4
+        } else {
+            0
+        }
+    }
+
+    // If the compressions sent in the other side's handshake has this one
+    pub(crate) fn is_in(&self, compressions: u32) -> bool {
+        // Without any compression feature there is no variant to match
+        let _ = compressions;
+
+        match *self {
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 { .. } => compressions & LZ4 != 0,
+        }
+    }
+
+    // None when the payload is left as it is, either it's too small or compressing it doesn't make it smaller
+    pub(crate) fn compress(&self, payload: &[u8]) -> Option<Vec<u8>> {
+        // Without any compression feature there is no variant to match
+        let _ = payload;
+
+        match *self {
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 { threshold } => {
+                if (payload.len() as u64) < threshold {
+                    return None;
+                }
+
+                let compressed = lz4_flex::block::compress_prepend_size(payload);
+
+                if compressed.len() >= payload.len() {
+                    return None;
+                }
+
+                Some(compressed)
+            }
+        }
+    }
+}
+
+// Refuses payloads that would be bigger than max_size once decompressed, before allocating anything
+pub(crate) fn decompress(
+    payload: &[u8],
+    max_size: u64,
+) -> Result<Vec<u8>, crate::socket::SocketError> {
+    use crate::socket::SocketError;
+
+    #[cfg(feature = "lz4")]
+    {
+        let (size, compressed) = lz4_flex::block::uncompressed_size(payload)
+            .map_err(|e| SocketError::Decompression(e.into()))?;
+
+        if size as u64 > max_size {
+            return Err(SocketError::FrameTooLarge {
+                size: size as u64,
+                max: max_size,
+            });
+        }
+
+        lz4_flex::block::decompress(compressed, size)
+            .map_err(|e| SocketError::Decompression(e.into()))
+    }
+
+    #[cfg(not(feature = "lz4"))]
+    {
+        let _ = (payload, max_size);
+        Err(SocketError::Decompression(
+            "Received a compressed frame, but the lz4 feature is disabled".into(),
+        ))
+    }
+}
@@ -0,0 +1,15 @@
+mod config;
+mod error;
+mod link;
+mod tcp;
+mod udp;
+
+pub use config::{ConditionerConfig, LinkConfig};
+pub use error::ConditionerError;
+pub use tcp::TcpConditioner;
+pub use udp::UdpConditioner;
+
+// How long the relays sleep at most, it's also how fast they see that they have to stop
+const POLL_DELAY: std::time::Duration = std::time::Duration::from_millis(5);
+// A relay never sleeps less than that, what is due in less is delivered a bit late
+const MIN_POLL_DELAY: std::time::Duration = std::time::Duration::from_micros(100);
@@ -0,0 +1,32 @@
+/// Where a conditioner listens and forwards to, and how bad the link is in each direction
+///
+/// The client connects to (or sends to) the conditioner instead of the target
+#[derive(Clone, Debug)]
+pub struct ConditionerConfig {
+    // 127.0.0.1:0 picks a free port, see local_addr on the conditioners
+    pub addr: std::net::SocketAddr,
+    pub target: std::net::SocketAddr,
+    // From the client to the target
+    pub upstream: LinkConfig,
+    // From the target to the client
+    pub downstream: LinkConfig,
+    // The same seed gives the same losses, duplicates and jitter for the same datagrams, None for a random one
+    // The reads of a tcp stream depend on the timing, so the same seed doesn't give exactly the same stream
+    pub seed: Option<u64>,
+}
+
+/// How one direction of a conditioner behaves, the default is a perfect link
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinkConfig {
+    // Added to everything that goes through
+    pub latency: std::time::Duration,
+    // Up to that much more latency, picked for every read or datagram, the bytes of a stream stay in order
+    pub jitter: std::time::Duration,
+    // In bytes per second, what is sent faster waits in the link, None for no limit
+    pub bandwidth: Option<u64>,
+    // The chances (0. to 1.) of a datagram to be lost, to be sent twice and to be sent after the next one
+    // A tcp stream ignores them, it would be broken otherwise
+    pub loss: f64,
+    pub duplication: f64,
+    pub reordering: f64,
+}
@@ -0,0 +1,10 @@
+#[derive(thiserror::Error, Debug)]
+pub enum ConditionerError {
+    #[error("Could not bind the conditioner on {addr}: {error}")]
+    Bind {
+        addr: std::net::SocketAddr,
+        error: std::io::Error,
+    },
+    #[error("Could not set up the conditioner's socket: {0}")]
+    Config(std::io::Error),
+}
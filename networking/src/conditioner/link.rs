@@ -0,0 +1,84 @@
+// One direction of a conditioner, it tells when what went in comes out
+pub(crate) struct Link {
+    cfg: super::LinkConfig,
+    // When the bandwidth is free again, what is sent before waits for it
+    free_at: std::time::Instant,
+    // The bytes of a stream can't overtake each other
+    last_delivery: std::time::Instant,
+}
+
+impl Link {
+    pub(crate) fn new(cfg: super::LinkConfig) -> Self {
+        let now = std::time::Instant::now();
+        Self {
+            cfg,
+            free_at: now,
+            last_delivery: now,
+        }
+    }
+
+    // The most that is delivered at once, 10ms worth of bandwidth so a big read is spread over time
+    pub(crate) fn chunk_size(&self) -> Option<usize> {
+        self.cfg
+            .bandwidth
+            .map(|bandwidth| (bandwidth / 100).max(1) as usize)
+    }
+
+    // How much can wait in the link before the reads stop, enough to keep the bandwidth busy for the whole latency
+    // plus the read that goes over it, None when there is no bandwidth limit
+    pub(crate) fn max_queued(&self, read_size: usize) -> Option<usize> {
+        let bandwidth = self.cfg.bandwidth?;
+        let delay = (self.cfg.latency + self.cfg.jitter).as_secs_f64();
+        Some((bandwidth as f64 * delay) as usize + read_size)
+    }
+
+    // For a datagram, the jitter can make it overtake the ones sent before it
+    pub(crate) fn transmit(&mut self, len: usize) -> std::time::Instant {
+        let start = self.free_at.max(std::time::Instant::now());
+        self.free_at = start
+            + match self.cfg.bandwidth {
+                Some(bandwidth) if bandwidth > 0 => {
+                    std::time::Duration::from_secs_f64(len as f64 / bandwidth as f64)
+                }
+                _ => std::time::Duration::ZERO,
+            };
+
+        self.free_at + self.cfg.latency + self.jitter()
+    }
+
+    // For the bytes of a stream, never before the ones sent before them
+    pub(crate) fn transmit_ordered(&mut self, len: usize) -> std::time::Instant {
+        let at = self.transmit(len).max(self.last_delivery);
+        self.last_delivery = at;
+        at
+    }
+
+    pub(crate) fn lose(&self) -> bool {
+        chance(self.cfg.loss)
+    }
+    pub(crate) fn duplicate(&self) -> bool {
+        chance(self.cfg.duplication)
+    }
+    pub(crate) fn reorder(&self) -> bool {
+        chance(self.cfg.reordering)
+    }
+
+    fn jitter(&self) -> std::time::Duration {
+        if self.cfg.jitter.is_zero() {
+            return std::time::Duration::ZERO;
+        }
+        std::time::Duration::from_nanos(random::get_inc(0, self.cfg.jitter.as_nanos() as u64))
+    }
+}
+
+// Nothing is drawn for a chance of 0, so a link without losses doesn't change what the others draw
+fn chance(probability: f64) -> bool {
+    probability > 0. && random::get(0., 1.) < probability
+}
+
+// The thread's rng is used, each thread of a conditioner gets its own seed from the config's
+pub(crate) fn seed_thread(seed_opt: Option<u64>, thread: u64) {
+    if let Some(seed) = seed_opt {
+        random::set_seed(seed.wrapping_add(thread));
+    }
+}
@@ -0,0 +1,219 @@
+// The most that is read from a stream at once
+const READ_SIZE: usize = 16 * 1024;
+
+/// A tcp relay that delays what goes through it, and caps its bandwidth
+///
+/// Every accepted client gets its own connection to the target, with a thread for each direction.
+/// The clients are disconnected and the relay stops when the conditioner is dropped
+pub struct TcpConditioner {
+    running: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    local_addr: std::net::SocketAddr,
+    thread_handle: Option<std::thread::JoinHandle<()>>,
+}
+
+impl TcpConditioner {
+    pub fn start_new(cfg: super::ConditionerConfig) -> Result<Self, super::ConditionerError> {
+        use std::sync::{atomic::AtomicBool, Arc};
+
+        let listener = std::net::TcpListener::bind(cfg.addr).map_err(|error| {
+            super::ConditionerError::Bind {
+                addr: cfg.addr,
+                error,
+            }
+        })?;
+        let local_addr = listener
+            .local_addr()
+            .map_err(super::ConditionerError::Config)?;
+
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread_handle = {
+            let running = running.clone();
+            std::thread::spawn(move || accept(listener, cfg, running))
+        };
+
+        Ok(Self {
+            running,
+            local_addr,
+            thread_handle: Some(thread_handle),
+        })
+    }
+
+    /// What the client connects to instead of the target
+    pub fn local_addr(&self) -> std::net::SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for TcpConditioner {
+    fn drop(&mut self) {
+        use std::sync::atomic::Ordering;
+
+        self.running.store(false, Ordering::Release);
+
+        // The thread waits in accept, a connection wakes it up
+        let mut wake_addr = self.local_addr;
+        if wake_addr.ip().is_unspecified() {
+            wake_addr.set_ip(match wake_addr {
+                std::net::SocketAddr::V4(_) => std::net::Ipv4Addr::LOCALHOST.into(),
+                std::net::SocketAddr::V6(_) => std::net::Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+
+        match std::net::TcpStream::connect_timeout(&wake_addr, std::time::Duration::from_secs(1)) {
+            Ok(_) => {
+                if let Some(handle) = self.thread_handle.take() {
+                    if handle.join().is_err() {
+                        error!("The conditioner's thread panicked");
+                    }
+                }
+            }
+            // It's left behind rather than blocking the drop forever
+            Err(e) => warn!("Could not stop the conditioner's thread: {e}"),
+        }
+    }
+}
+
+fn accept(
+    listener: std::net::TcpListener,
+    cfg: super::ConditionerConfig,
+    running: std::sync::Arc<std::sync::atomic::AtomicBool>,
+) {
+    use std::sync::atomic::Ordering;
+
+    let mut connections = 0u64;
+
+    for client in listener.incoming() {
+        if !running.load(Ordering::Acquire) {
+            break;
+        }
+
+        let client = match client {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("Could not accept a client: {e}");
+                continue;
+            }
+        };
+
+        // The client is dropped if the target can't be reached, it sees it as a closed connection
+        let target = match std::net::TcpStream::connect(cfg.target) {
+            Ok(target) => target,
+            Err(e) => {
+                warn!("Could not connect to {}: {e}", cfg.target);
+                continue;
+            }
+        };
+
+        let pipes = client
+            .try_clone()
+            .and_then(|client_clone| Ok((client_clone, target.try_clone()?)));
+        let (client_clone, target_clone) = match pipes {
+            Ok(clones) => clones,
+            Err(e) => {
+                warn!("Could not set up the relay: {e}");
+                continue;
+            }
+        };
+
+        // The delays are the conditioner's, not nagle's
+        let _ = client.set_nodelay(true);
+        let _ = target.set_nodelay(true);
+
+        // Two threads per connection, each with its own seed
+        let thread = connections * 2;
+        connections += 1;
+
+        {
+            let (link, seed, running) = (cfg.upstream, cfg.seed, running.clone());
+            std::thread::spawn(move || pipe(client, target_clone, link, seed, thread, running));
+        }
+        {
+            let (link, seed, running) = (cfg.downstream, cfg.seed, running.clone());
+            std::thread::spawn(move || pipe(target, client_clone, link, seed, thread + 1, running));
+        }
+    }
+
+    debug!("Tcp conditioner for ({}) has exited", cfg.target);
+}
+
+// Reads from src and writes to dst once the link says so, an end of stream is forwarded once everything is delivered
+// src isn't read while the link is full, so a capped bandwidth slows the sender down instead of piling up here
+fn pipe(
+    mut src: std::net::TcpStream,
+    mut dst: std::net::TcpStream,
+    cfg: super::LinkConfig,
+    seed_opt: Option<u64>,
+    thread: u64,
+    running: std::sync::Arc<std::sync::atomic::AtomicBool>,
+) {
+    use std::{
+        io::{Read as _, Write as _},
+        sync::atomic::Ordering,
+    };
+
+    super::link::seed_thread(seed_opt, thread);
+
+    let mut link = super::link::Link::new(cfg);
+    let mut queue = std::collections::VecDeque::<(std::time::Instant, Vec<u8>)>::new();
+    let mut queued = 0;
+    let max_queued_opt = link.max_queued(READ_SIZE);
+    let mut buffer = vec![0; READ_SIZE];
+    let mut src_open = true;
+
+    'relay: while running.load(Ordering::Acquire) {
+        let now = std::time::Instant::now();
+        while queue.front().is_some_and(|(at, _)| *at <= now) {
+            let (_, bytes) = queue.pop_front().unwrap();
+            queued -= bytes.len();
+            if let Err(e) = dst.write_all(&bytes) {
+                debug!("Could not write to the relayed stream: {e}");
+                break 'relay;
+            }
+        }
+
+        if !src_open && queue.is_empty() {
+            let _ = dst.shutdown(std::net::Shutdown::Write);
+            return;
+        }
+
+        let wait = queue
+            .front()
+            .map(|(at, _)| at.saturating_duration_since(now))
+            .unwrap_or(super::POLL_DELAY)
+            .clamp(super::MIN_POLL_DELAY, super::POLL_DELAY);
+
+        if !src_open || max_queued_opt.is_some_and(|max_queued| queued >= max_queued) {
+            std::thread::sleep(wait);
+            continue;
+        }
+
+        if let Err(e) = src.set_read_timeout(Some(wait)) {
+            debug!("Could not set the read timeout of the relayed stream: {e}");
+            break;
+        }
+        match src.read(&mut buffer) {
+            Ok(0) => src_open = false,
+            Ok(read) => {
+                let chunk_size = link.chunk_size().unwrap_or(read);
+                for chunk in buffer[..read].chunks(chunk_size) {
+                    queue.push_back((link.transmit_ordered(chunk.len()), chunk.to_vec()));
+                }
+                queued += read;
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) => {}
+            Err(e) => {
+                debug!("Could not read the relayed stream: {e}");
+                break;
+            }
+        }
+    }
+
+    // Stopped or broken, both sides see it
+    let _ = src.shutdown(std::net::Shutdown::Both);
+    let _ = dst.shutdown(std::net::Shutdown::Both);
+}
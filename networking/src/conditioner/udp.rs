@@ -0,0 +1,276 @@
+// The biggest payload of an udp datagram
+const RECV_BUFFER_SIZE: usize = 65_535;
+
+// A datagram held back to be sent after the next one is sent anyway if nothing comes after it for that long
+const REORDER_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// An udp relay that delays, caps, loses, duplicates and reorders the datagrams that go through it
+///
+/// The client sends to [`UdpConditioner::local_addr`], the target sees the datagrams coming from
+/// [`UdpConditioner::target_side_addr`], so two connected sockets connect to those instead of each other.
+/// What the target sends goes to the last address the client sent from
+pub struct UdpConditioner {
+    running: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    local_addr: std::net::SocketAddr,
+    target_side_addr: std::net::SocketAddr,
+    thread_handle: Option<std::thread::JoinHandle<()>>,
+}
+
+impl UdpConditioner {
+    pub fn start_new(cfg: super::ConditionerConfig) -> Result<Self, super::ConditionerError> {
+        use std::sync::{atomic::AtomicBool, Arc};
+
+        let client_side =
+            std::net::UdpSocket::bind(cfg.addr).map_err(|error| super::ConditionerError::Bind {
+                addr: cfg.addr,
+                error,
+            })?;
+        // On the same interface, with any port
+        let target_side_addr = std::net::SocketAddr::new(cfg.addr.ip(), 0);
+        let target_side = std::net::UdpSocket::bind(target_side_addr).map_err(|error| {
+            super::ConditionerError::Bind {
+                addr: target_side_addr,
+                error,
+            }
+        })?;
+
+        target_side
+            .connect(cfg.target)
+            .map_err(super::ConditionerError::Config)?;
+        for socket in [&client_side, &target_side] {
+            socket
+                .set_nonblocking(true)
+                .map_err(super::ConditionerError::Config)?;
+        }
+
+        let local_addr = client_side
+            .local_addr()
+            .map_err(super::ConditionerError::Config)?;
+        let target_side_addr = target_side
+            .local_addr()
+            .map_err(super::ConditionerError::Config)?;
+
+        let running = Arc::new(AtomicBool::new(true));
+
+        let relay = Relay {
+            client_side,
+            target_side,
+            client_addr_opt: None,
+            upstream: Direction::new(cfg.upstream),
+            downstream: Direction::new(cfg.downstream),
+            queue: std::collections::BTreeMap::new(),
+            next_id: 0,
+        };
+        let thread_handle = {
+            let running = running.clone();
+            std::thread::spawn(move || relay.run(cfg.seed, running))
+        };
+
+        Ok(Self {
+            running,
+            local_addr,
+            target_side_addr,
+            thread_handle: Some(thread_handle),
+        })
+    }
+
+    /// What the client sends to instead of the target
+    pub fn local_addr(&self) -> std::net::SocketAddr {
+        self.local_addr
+    }
+
+    /// Where the target receives the client's datagrams from
+    pub fn target_side_addr(&self) -> std::net::SocketAddr {
+        self.target_side_addr
+    }
+}
+
+impl Drop for UdpConditioner {
+    fn drop(&mut self) {
+        use std::sync::atomic::Ordering;
+
+        self.running.store(false, Ordering::Release);
+
+        // The relay never blocks, it sees it on its next loop
+        if let Some(handle) = self.thread_handle.take() {
+            if handle.join().is_err() {
+                error!("The conditioner's thread panicked");
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Side {
+    Client,
+    Target,
+}
+
+struct Direction {
+    link: super::link::Link,
+    // The datagram that waits for the next one to be sent before it
+    held_opt: Option<(std::time::Instant, Vec<u8>)>,
+}
+
+impl Direction {
+    fn new(cfg: super::LinkConfig) -> Self {
+        Self {
+            link: super::link::Link::new(cfg),
+            held_opt: None,
+        }
+    }
+}
+
+struct Relay {
+    client_side: std::net::UdpSocket,
+    target_side: std::net::UdpSocket,
+    // Where the target's datagrams go, None until the client has sent something
+    client_addr_opt: Option<std::net::SocketAddr>,
+    upstream: Direction,
+    downstream: Direction,
+    // By delivery time, the id keeps the order of the datagrams that are due at the same time
+    queue: std::collections::BTreeMap<(std::time::Instant, u64), (Side, Vec<u8>)>,
+    next_id: u64,
+}
+
+impl Relay {
+    fn run(
+        mut self,
+        seed_opt: Option<u64>,
+        running: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    ) {
+        use std::sync::atomic::Ordering;
+
+        super::link::seed_thread(seed_opt, 0);
+
+        let mut buffer = vec![0; RECV_BUFFER_SIZE];
+
+        while running.load(Ordering::Acquire) {
+            loop {
+                match self.client_side.recv_from(&mut buffer) {
+                    Ok((len, addr)) => {
+                        self.client_addr_opt = Some(addr);
+                        self.schedule(Side::Target, buffer[..len].to_vec());
+                    }
+                    Err(e) => {
+                        log_recv_error(e);
+                        break;
+                    }
+                }
+            }
+            loop {
+                match self.target_side.recv(&mut buffer) {
+                    Ok(len) => self.schedule(Side::Client, buffer[..len].to_vec()),
+                    Err(e) => {
+                        log_recv_error(e);
+                        break;
+                    }
+                }
+            }
+
+            self.release_held();
+            self.deliver();
+
+            let wait = self
+                .queue
+                .keys()
+                .next()
+                .map(|(at, _)| at.saturating_duration_since(std::time::Instant::now()))
+                .unwrap_or(super::POLL_DELAY)
+                .clamp(super::MIN_POLL_DELAY, super::POLL_DELAY);
+            std::thread::sleep(wait);
+        }
+
+        debug!(
+            "Udp conditioner for ({:?}) has exited",
+            self.target_side.peer_addr()
+        );
+    }
+
+    // Decides if, how many times and when a datagram that goes to `to` is sent
+    fn schedule(&mut self, to: Side, bytes: Vec<u8>) {
+        let direction = match to {
+            Side::Target => &mut self.upstream,
+            Side::Client => &mut self.downstream,
+        };
+
+        if direction.link.lose() {
+            return;
+        }
+        let copies = if direction.link.duplicate() { 2 } else { 1 };
+
+        for _ in 0..copies {
+            if direction.held_opt.is_none() && direction.link.reorder() {
+                direction.held_opt = Some((std::time::Instant::now(), bytes.clone()));
+                continue;
+            }
+
+            let at = direction.link.transmit(bytes.len());
+            let held_opt = direction.held_opt.take();
+
+            push(&mut self.queue, &mut self.next_id, at, to, bytes.clone());
+            // Right after the one that overtook it
+            if let Some((_, held)) = held_opt {
+                push(&mut self.queue, &mut self.next_id, at, to, held);
+            }
+        }
+    }
+
+    fn release_held(&mut self) {
+        for (to, direction) in [
+            (Side::Target, &mut self.upstream),
+            (Side::Client, &mut self.downstream),
+        ] {
+            if direction
+                .held_opt
+                .as_ref()
+                .is_some_and(|(since, _)| since.elapsed() >= REORDER_TIMEOUT)
+            {
+                let (_, held) = direction.held_opt.take().unwrap();
+                let at = direction.link.transmit(held.len());
+                push(&mut self.queue, &mut self.next_id, at, to, held);
+            }
+        }
+    }
+
+    fn deliver(&mut self) {
+        let now = std::time::Instant::now();
+
+        while self
+            .queue
+            .first_key_value()
+            .is_some_and(|((at, _), _)| *at <= now)
+        {
+            let (_, (to, bytes)) = self.queue.pop_first().unwrap();
+
+            let res = match (to, self.client_addr_opt) {
+                (Side::Target, _) => self.target_side.send(&bytes),
+                (Side::Client, Some(addr)) => self.client_side.send_to(&bytes, addr),
+                (Side::Client, None) => continue,
+            };
+            // Lost, like it would be on a real network
+            if let Err(e) = res {
+                debug!("Could not relay a datagram: {e}");
+            }
+        }
+    }
+}
+
+fn push(
+    queue: &mut std::collections::BTreeMap<(std::time::Instant, u64), (Side, Vec<u8>)>,
+    next_id: &mut u64,
+    at: std::time::Instant,
+    to: Side,
+    bytes: Vec<u8>,
+) {
+    queue.insert((at, *next_id), (to, bytes));
+    *next_id += 1;
+}
+
+// Nothing to read is the usual case, a refused datagram is the target that isn't there yet
+fn log_recv_error(e: std::io::Error) {
+    match e.kind() {
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::ConnectionRefused => (),
+        _ => debug!("Could not receive a datagram: {e}"),
+    }
+}
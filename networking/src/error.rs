@@ -4,4 +4,8 @@ pub enum NetworkError {
     Socket(#[from] crate::socket::SocketError),
     #[error("{0}")]
     Proxy(#[from] crate::proxy::ProxyError),
+    #[error("{0}")]
+    Server(#[from] crate::server::ServerError),
+    #[error("{0}")]
+    Udp(#[from] crate::udp::UdpError),
 }
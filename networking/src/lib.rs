@@ -1,15 +1,42 @@
 #![allow(dead_code)]
+#![cfg_attr(docsrs, feature(doc_cfg))]
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub mod asynchronous;
+pub mod codec;
+pub mod compression;
+#[cfg(feature = "conditioner")]
+#[cfg_attr(docsrs, doc(cfg(feature = "conditioner")))]
+pub mod conditioner;
 pub mod error;
 pub mod message;
+#[cfg(feature = "metrics")]
+#[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+pub mod metrics;
 pub mod proxy;
+pub mod server;
 pub mod socket;
 pub mod stats;
+pub mod tls;
+pub mod udp;
 
+pub use codec::Codec;
+pub use compression::Compression;
 pub use error::NetworkError;
 pub use message::Message;
 pub use proxy::Proxy;
+pub use server::Server;
 pub use socket::Socket;
 pub use stats::NetworkStats;
+pub use udp::UdpSocket;
+
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub use asynchronous::{AsyncProxy, AsyncSocket};
+
+#[cfg(feature = "tls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+pub use rustls;
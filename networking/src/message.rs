@@ -1,3 +1,7 @@
+/// Anything that can go through a Socket or a Proxy
+///
+/// Protocol frames (ping, pong, close) are handled by the crate, see [`crate::socket::FrameKind`],
+/// so this is implemented for every type that has the right bounds
 pub trait Message:
     serde::Serialize
     + serde::de::DeserializeOwned
@@ -6,38 +10,14 @@ pub trait Message:
     + std::clone::Clone
     + std::marker::Send
 {
-    /// Used to make disconnection faster
-    fn is_exit(&self) -> bool {
-        panic!("The networing::Message::is_exit method is not implemented for {self:?}")
-    }
-    // For the network stats, it keeps track of the sent & received ping
-    fn is_ping(&self) -> bool {
-        panic!("The networing::Message::is_ping method is not implemented for {self:?}")
-    }
-    // For the network stats, it keeps track of the sent & received pong
-    fn is_pong(&self) -> bool {
-        panic!("The networing::Message::is_pong method is not implemented for {self:?}")
-    }
+}
 
-    // Constructor for a default exit message (The variant given by this method has to return true on Message::is_exit)
-    fn default_exit() -> Self {
-        panic!(
-            "The networing::Message::default_exit method is not implemented for {}",
-            std::any::type_name::<Self>()
-        )
-    }
-    // Constructor for a default ping message (The variant given by this method has to return true on Message::is_ping)
-    fn default_ping() -> Self {
-        panic!(
-            "The networing::Message::default_ping method is not implemented for {}",
-            std::any::type_name::<Self>()
-        )
-    }
-    // Constructor for a default pong message (The variant given by this method has to return true on Message::is_pong)
-    fn default_pong() -> Self {
-        panic!(
-            "The networing::Message::default_pong method is not implemented for {}",
-            std::any::type_name::<Self>()
-        )
-    }
+impl<T> Message for T where
+    T: serde::Serialize
+        + serde::de::DeserializeOwned
+        + PartialEq
+        + std::fmt::Debug
+        + std::clone::Clone
+        + std::marker::Send
+{
 }
@@ -0,0 +1,10 @@
+mod encoder;
+mod error;
+mod exporter;
+
+pub use encoder::Metrics;
+pub use error::MetricsError;
+pub use exporter::{MetricsConfig, MetricsExporter};
+
+// What the exporter answers with, the version of the prometheus text format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
@@ -0,0 +1,197 @@
+// A metric, its samples are written under its help and type lines
+struct Family {
+    name: &'static str,
+    kind: &'static str,
+    help: &'static str,
+}
+
+macro_rules! families {
+    ($($id:ident: $name:literal, $kind:literal, $help:literal;)*) => {
+        $(
+            static $id: Family = Family {
+                name: $name,
+                kind: $kind,
+                help: $help,
+            };
+        )*
+    };
+}
+
+families! {
+    SENT_BYTES: "networking_sent_bytes_total", "counter", "Bytes written to the connection, headers included";
+    RECEIVED_BYTES: "networking_received_bytes_total", "counter", "Bytes read from the connection, headers included";
+    SENT_RATE: "networking_sent_bytes_per_second", "gauge", "Bytes written per second over the window of the bps stat";
+    RECEIVED_RATE: "networking_received_bytes_per_second", "gauge", "Bytes read per second over the window of the bps stat";
+    SENT_MESSAGES: "networking_sent_messages_total", "counter", "Messages sent, a message sent in chunks counts once";
+    RECEIVED_MESSAGES: "networking_received_messages_total", "counter", "Messages received, a message sent in chunks counts once";
+    SENT_PACKETS: "networking_sent_packets_total", "counter", "Frames or datagrams sent, pings and pongs included";
+    RECEIVED_PACKETS: "networking_received_packets_total", "counter", "Frames or datagrams received, pings and pongs included";
+    RTT: "networking_rtt_seconds", "gauge", "Latest round trip time";
+    SMOOTHED_RTT: "networking_rtt_smoothed_seconds", "gauge", "Smoothed round trip time";
+    RTT_JITTER: "networking_rtt_jitter_seconds", "gauge", "Variation of the round trip time";
+    MIN_RTT: "networking_rtt_min_seconds", "gauge", "Lowest round trip time over the window of the rtt stat";
+    MAX_RTT: "networking_rtt_max_seconds", "gauge", "Highest round trip time over the window of the rtt stat";
+    QUEUE_DEPTH: "networking_queue_depth", "gauge", "Messages waiting to be sent";
+    QUEUE_PEAK_DEPTH: "networking_queue_peak_depth", "gauge", "Highest number of messages that waited to be sent";
+    DROPPED_MESSAGES: "networking_dropped_messages_total", "counter", "Messages dropped by the overflow policy of the queue";
+    RECONNECTS: "networking_reconnects_total", "counter", "Successful reconnections";
+    TYPE_SENT_MESSAGES: "networking_message_type_sent_messages_total", "counter", "Messages sent, by message type";
+    TYPE_RECEIVED_MESSAGES: "networking_message_type_received_messages_total", "counter", "Messages received, by message type";
+    TYPE_SENT_BYTES: "networking_message_type_sent_bytes_total", "counter", "Payload bytes sent, by message type";
+    TYPE_RECEIVED_BYTES: "networking_message_type_received_bytes_total", "counter", "Payload bytes received, by message type";
+}
+
+struct Sample {
+    family: &'static Family,
+    // Already formatted, `{connection="eu-1"}` or nothing
+    labels: String,
+    value: f64,
+}
+
+/// The stats of one or many connections, in the prometheus text exposition format
+///
+/// The stats are copied when they are added, a scrape renders what they were at that time.
+/// Each connection needs its own labels, prometheus rejects the samples that are the same metric with the same labels
+#[derive(Default)]
+pub struct Metrics {
+    samples: Vec<Sample>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<R: crate::Message, W: crate::Message>(
+        &mut self,
+        labels: &[(&str, &str)],
+        stats: &crate::NetworkStats<R, W>,
+    ) {
+        let base = format_labels(labels);
+
+        let samples = [
+            (&SENT_BYTES, stats.total_sent() as f64),
+            (&RECEIVED_BYTES, stats.total_received() as f64),
+            (&SENT_RATE, stats.bps_sent() as f64),
+            (&RECEIVED_RATE, stats.bps_received() as f64),
+            (&SENT_MESSAGES, stats.messages_sent() as f64),
+            (&RECEIVED_MESSAGES, stats.messages_received() as f64),
+            (&SENT_PACKETS, stats.packets_sent() as f64),
+            (&RECEIVED_PACKETS, stats.packets_received() as f64),
+            (&RTT, stats.get_rtt().as_secs_f64()),
+            (&SMOOTHED_RTT, stats.smoothed_rtt().as_secs_f64()),
+            (&RTT_JITTER, stats.rtt_jitter().as_secs_f64()),
+            (&MIN_RTT, stats.min_rtt().as_secs_f64()),
+            (&MAX_RTT, stats.max_rtt().as_secs_f64()),
+            (&QUEUE_DEPTH, stats.queue_depth() as f64),
+            (&QUEUE_PEAK_DEPTH, stats.queue_peak_depth() as f64),
+            (&DROPPED_MESSAGES, stats.dropped_messages() as f64),
+            (&RECONNECTS, stats.reconnects() as f64),
+        ];
+        self.samples
+            .extend(samples.into_iter().map(|(family, value)| Sample {
+                family,
+                labels: base.clone(),
+                value,
+            }));
+
+        // Empty unless StatConfig::message_types is enabled
+        for row in stats.message_types().rows {
+            let labels = format_labels(
+                &labels
+                    .iter()
+                    .copied()
+                    .chain(std::iter::once(("type", row.name)))
+                    .collect::<Vec<_>>(),
+            );
+            let samples = [
+                (&TYPE_SENT_MESSAGES, row.messages_sent),
+                (&TYPE_RECEIVED_MESSAGES, row.messages_received),
+                (&TYPE_SENT_BYTES, row.bytes_sent),
+                (&TYPE_RECEIVED_BYTES, row.bytes_received),
+            ];
+            self.samples
+                .extend(samples.into_iter().map(|(family, value)| Sample {
+                    family,
+                    labels: labels.clone(),
+                    value: value as f64,
+                }));
+        }
+    }
+
+    /// Adds every client of a server, with a `client` label set to the id of the client
+    pub fn add_server<R: crate::Message, W: crate::Message>(
+        &mut self,
+        labels: &[(&str, &str)],
+        stats: &crate::server::StatsMap<R, W>,
+    ) {
+        let mut clients = stats.iter().collect::<Vec<_>>();
+        clients.sort_by_key(|(id, _)| **id);
+
+        for (id, stats) in clients {
+            let id = id.get().to_string();
+            let labels = labels
+                .iter()
+                .copied()
+                .chain(std::iter::once(("client", id.as_str())))
+                .collect::<Vec<_>>();
+            self.add(&labels, stats);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn render(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl std::fmt::Display for Metrics {
+    // The samples of a metric have to be together, in the order the connections were added
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut families = Vec::<&'static Family>::new();
+        for sample in &self.samples {
+            if !families
+                .iter()
+                .any(|family| family.name == sample.family.name)
+            {
+                families.push(sample.family);
+            }
+        }
+
+        for family in families {
+            writeln!(f, "# HELP {} {}", family.name, family.help)?;
+            writeln!(f, "# TYPE {} {}", family.name, family.kind)?;
+            for sample in self
+                .samples
+                .iter()
+                .filter(|sample| sample.family.name == family.name)
+            {
+                writeln!(f, "{}{} {}", family.name, sample.labels, sample.value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+
+    let labels = labels
+        .iter()
+        .map(|(name, value)| format!("{name}=\"{}\"", escape(value)))
+        .collect::<Vec<_>>();
+    format!("{{{}}}", labels.join(","))
+}
+
+// The escapes the text format wants in a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,8 @@
+#[derive(thiserror::Error, Debug)]
+pub enum MetricsError {
+    #[error("Could not bind the metrics listener on {addr}: {error}")]
+    Bind {
+        addr: std::net::SocketAddr,
+        error: std::io::Error,
+    },
+}
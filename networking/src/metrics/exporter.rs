@@ -0,0 +1,210 @@
+// The biggest request the exporter reads, a scrape is a few hundred bytes
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+// How long a scraper has to send its request and to read the answer
+const IO_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+#[derive(Clone, Debug)]
+pub struct MetricsConfig {
+    // Only reachable from this machine by default, 0.0.0.0 to let the network scrape it
+    pub addr: std::net::SocketAddr,
+    // Anything else is a 404
+    pub path: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            addr: std::net::SocketAddr::from(([127, 0, 0, 1], 9464)),
+            path: String::from("/metrics"),
+        }
+    }
+}
+
+/// A tiny http endpoint that serves the last [`super::Metrics`] that were published
+///
+/// It lives in its own thread, the scrapes are answered one after the other and the connection is closed after each of them.
+/// The thread stops when the exporter is dropped
+pub struct MetricsExporter {
+    // Already rendered, a scrape only copies it
+    text: std::sync::Arc<std::sync::Mutex<String>>,
+    running: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    local_addr: std::net::SocketAddr,
+    thread_handle: Option<std::thread::JoinHandle<()>>,
+}
+
+impl MetricsExporter {
+    pub fn start_new(cfg: MetricsConfig) -> Result<Self, super::MetricsError> {
+        use std::sync::{atomic::AtomicBool, Arc, Mutex};
+
+        let listener =
+            std::net::TcpListener::bind(cfg.addr).map_err(|error| super::MetricsError::Bind {
+                addr: cfg.addr,
+                error,
+            })?;
+        let local_addr = listener
+            .local_addr()
+            .map_err(|error| super::MetricsError::Bind {
+                addr: cfg.addr,
+                error,
+            })?;
+
+        let text = Arc::new(Mutex::new(String::new()));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread_handle = {
+            let text = text.clone();
+            let running = running.clone();
+            std::thread::spawn(move || run(listener, cfg, text, running))
+        };
+
+        debug!("Serving the metrics on {local_addr}");
+
+        Ok(Self {
+            text,
+            running,
+            local_addr,
+            thread_handle: Some(thread_handle),
+        })
+    }
+
+    /// Replaces what the next scrapes get
+    pub fn publish(&self, metrics: &super::Metrics) {
+        let text = metrics.render();
+        *self.text.lock().unwrap() = text;
+    }
+
+    pub fn local_addr(&self) -> std::net::SocketAddr {
+        self.local_addr
+    }
+
+    pub fn is_running(&self) -> bool {
+        use std::sync::atomic::Ordering;
+        self.running.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for MetricsExporter {
+    fn drop(&mut self) {
+        use std::sync::atomic::Ordering;
+
+        self.running.store(false, Ordering::Release);
+
+        // The thread waits in accept, a connection wakes it up
+        let mut wake_addr = self.local_addr;
+        if wake_addr.ip().is_unspecified() {
+            wake_addr.set_ip(match wake_addr {
+                std::net::SocketAddr::V4(_) => std::net::Ipv4Addr::LOCALHOST.into(),
+                std::net::SocketAddr::V6(_) => std::net::Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+
+        match std::net::TcpStream::connect_timeout(&wake_addr, IO_TIMEOUT) {
+            Ok(_) => {
+                if let Some(handle) = self.thread_handle.take() {
+                    if handle.join().is_err() {
+                        error!("The metrics thread panicked");
+                    }
+                }
+            }
+            // It's left behind rather than blocking the drop forever
+            Err(e) => warn!("Could not stop the metrics thread: {e}"),
+        }
+    }
+}
+
+fn run(
+    listener: std::net::TcpListener,
+    cfg: MetricsConfig,
+    text: std::sync::Arc<std::sync::Mutex<String>>,
+    running: std::sync::Arc<std::sync::atomic::AtomicBool>,
+) {
+    use std::sync::atomic::Ordering;
+
+    for stream in listener.incoming() {
+        if !running.load(Ordering::Acquire) {
+            break;
+        }
+
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Could not accept a metrics scrape: {e}");
+                continue;
+            }
+        };
+
+        if let Err(e) = serve(stream, &cfg, &text) {
+            debug!("Could not answer a metrics scrape: {e}");
+        }
+    }
+
+    debug!("Metrics exporter on ({}) has exited", cfg.addr);
+}
+
+fn serve(
+    mut stream: std::net::TcpStream,
+    cfg: &MetricsConfig,
+    text: &std::sync::Mutex<String>,
+) -> std::io::Result<()> {
+    use std::io::{Read as _, Write as _};
+
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    // Only the request line matters, the rest of the head is read so the scraper isn't reset
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        if request.len() >= MAX_REQUEST_SIZE {
+            return respond(
+                &mut stream,
+                "431 Request Header Fields Too Large",
+                "",
+                false,
+            );
+        }
+        match stream.read(&mut buffer)? {
+            0 => break,
+            read => request.extend_from_slice(&buffer[..read]),
+        }
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("");
+    let target = request_line.next().unwrap_or("");
+    // A query string is ignored, some scrapers add one
+    let path = target.split('?').next().unwrap_or("");
+
+    match method {
+        "GET" | "HEAD" if path == cfg.path => {
+            let body = text.lock().unwrap().clone();
+            respond(&mut stream, "200 OK", &body, method == "HEAD")
+        }
+        "GET" | "HEAD" => respond(&mut stream, "404 Not Found", "", false),
+        _ => respond(&mut stream, "405 Method Not Allowed", "", false),
+    }?;
+
+    stream.flush()
+}
+
+fn respond(
+    stream: &mut std::net::TcpStream,
+    status: &str,
+    body: &str,
+    head_only: bool,
+) -> std::io::Result<()> {
+    use std::io::Write as _;
+
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        super::CONTENT_TYPE,
+        body.len()
+    )?;
+    if !head_only {
+        stream.write_all(body.as_bytes())?;
+    }
+    Ok(())
+}
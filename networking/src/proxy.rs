@@ -1,89 +1,328 @@
+mod address;
+mod batch;
 mod config;
 mod controller;
 mod error;
+pub(crate) mod heartbeat;
 mod message;
-
+mod queue;
+pub(crate) mod reconnect;
+pub(crate) mod rpc;
+mod shutdown;
+mod tcp;
+
+pub use address::Address;
+pub use batch::BatchConfig;
 pub use config::ProxyConfig;
 pub use controller::ProxyController;
 pub use error::ProxyError;
-pub use message::ProxyMessage;
+pub use heartbeat::HeartbeatConfig;
+pub use message::{DisconnectReason, ProxyMessage};
+pub use queue::{OverflowPolicy, QueueConfig, SendError};
+pub use reconnect::{Backoff, ReconnectPolicy};
+pub use rpc::{Request, RpcError};
+pub use shutdown::ShutdownSummary;
+pub use tcp::TcpConfig;
+
+// How long the other side has to answer the handshake
+const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+// A batch is written to the stream every time that much is queued in the socket, see Proxy::handle_local
+const BATCH_FLUSH_SIZE: usize = 64 * 1024;
+
+// The poll tokens, the socket is registered as SOCKET_TOKEN and the controller wakes the proxy with WAKER_TOKEN
+const SOCKET_TOKEN: mio::Token = mio::Token(0);
+const WAKER_TOKEN: mio::Token = mio::Token(1);
 
 // as args, do i say that Read is the local or distant
 // Socket Read Channel Write
 // Socket Write Channel Read
-pub struct Proxy<SRCW: crate::Message, SWCR: crate::Message> {
+pub struct Proxy<
+    SRCW: crate::Message,
+    SWCR: crate::Message,
+    C: crate::Codec = crate::codec::Bincode,
+> {
     cfg: config::ProxyConfig,
-    socket_opt: Option<crate::Socket<SRCW, SWCR>>,
-    channel: threading::Channel<SWCR, message::ProxyMessage<SRCW>>,
+    socket_opt: Option<crate::Socket<SRCW, SWCR, C>>,
+    // The address the socket is connected to, one of the addresses cfg.addr resolves to
+    peer_addr_opt: Option<std::net::SocketAddr>,
+    // Some while the proxy is trying to reconnect
+    reconnection_opt: Option<reconnect::Reconnection>,
+    heartbeat: heartbeat::Heartbeat,
+    // A clone of the socket's stream, registered in the poll
+    source_opt: Option<mio::net::TcpStream>,
+    poll: mio::Poll,
+    // The controller's waker is closed with it, this one keeps its last wake in the poll
+    _waker: std::sync::Arc<mio::Waker>,
+    // What the controller wants to send, the proxy only takes from it when the socket can write
+    queue: std::sync::Arc<queue::Queue<rpc::Outgoing<SWCR>>>,
+    // The responses are given to the requests waiting for them instead of the sender
+    pending: std::sync::Arc<rpc::Pending<SRCW>>,
+    // Set by ProxyController::shutdown, the queue is flushed before exiting
+    shutdown: std::sync::Arc<shutdown::Shutdown>,
+    sender: std::sync::mpsc::Sender<message::ProxyMessage<SRCW>>,
+    // Woken up with every message sent to the controller, the server waits on it for its clients
+    notifier_opt: Option<std::sync::Arc<mio::Waker>>,
     running: std::sync::Arc<std::sync::atomic::AtomicBool>,
     connected: std::sync::Arc<std::sync::atomic::AtomicBool>,
     stats: triple_buffer::Input<super::NetworkStats<SRCW, SWCR>>,
 }
 
-impl<SRCW: crate::Message + 'static, SWCR: crate::Message + 'static> Proxy<SRCW, SWCR> {
+impl<SRCW: crate::Message + 'static, SWCR: crate::Message + 'static, C: crate::Codec>
+    Proxy<SRCW, SWCR, C>
+{
     pub fn start_new(
         cfg: config::ProxyConfig,
         stream_opt: Option<std::net::TcpStream>,
-    ) -> controller::ProxyController<SRCW, SWCR> {
+    ) -> controller::ProxyController<SRCW, SWCR, C> {
+        Self::start_notifying(cfg, stream_opt, None)
+    }
+
+    // Same as start_new, the notifier is woken up every time the proxy sends something to its controller
+    pub(crate) fn start_notifying(
+        cfg: config::ProxyConfig,
+        stream_opt: Option<std::net::TcpStream>,
+        notifier_opt: Option<std::sync::Arc<mio::Waker>>,
+    ) -> controller::ProxyController<SRCW, SWCR, C> {
         use {
-            crate::{NetworkStats, Socket},
+            crate::NetworkStats,
             std::{
                 sync::{atomic::AtomicBool, Arc},
                 thread,
             },
-            threading::Channel,
             triple_buffer::TripleBuffer,
         };
 
-        let (proxy_channel, main_channel) = Channel::<ProxyMessage<SRCW>, SWCR>::new_pair();
+        let queue = Arc::new(queue::Queue::new(cfg.queue));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let pending = Arc::new(rpc::Pending::new());
+        let shutdown = Arc::new(shutdown::Shutdown::default());
+        // Sent by the thread right before it ends, so the controller can wait for it with a timeout
+        let (summary_sender, summary_receiver) = std::sync::mpsc::channel();
+
+        // The errors are handled by the proxy's thread, like any other
+        let (poll_opt, waker_opt, poll_error_opt) = match Self::new_poll() {
+            Ok((poll, waker)) => (Some((poll, waker.clone())), Some(waker), None),
+            Err(e) => (None, None, Some(e)),
+        };
 
-        let socket_opt = stream_opt.map(Socket::new);
+        // A config that can't be used stops the proxy before it connects, like a stream that can't be used
+        let (socket_opt, peer_addr_opt, socket_error_opt) = match cfg.check().and_then(|()| {
+            stream_opt
+                .map(|stream| Self::accept(&cfg, stream))
+                .transpose()
+        }) {
+            Ok(Some((socket, addr))) => (Some(socket), Some(addr), None),
+            Ok(None) => (None, None, None),
+            Err(e) => (None, None, Some(e)),
+        };
 
         let running = Arc::new(AtomicBool::new(true));
-        let connected = Arc::new(AtomicBool::new(socket_opt.is_some()));
+        let running_controller = running.clone();
+        // Set once the handshake is done, in the proxy's thread
+        let connected = Arc::new(AtomicBool::new(false));
+        let connected_controller = connected.clone();
 
         let (stats_in, stats_out) = TripleBuffer::new(&NetworkStats::new(cfg.stat_cfg)).split();
 
-        let proxy = Proxy::<SRCW, SWCR> {
-            cfg,
-            socket_opt,
-            channel: proxy_channel,
-            running: running.clone(),
-            connected: connected.clone(),
-            stats: stats_in,
-        };
+        let controller_queue = queue.clone();
+        let controller_pending = pending.clone();
+        let controller_shutdown = shutdown.clone();
+        let channel_count = cfg.channels.priorities.len();
+        let thread_handle = thread::spawn(move || {
+            let Some((poll, waker)) = poll_opt else {
+                // Nothing can be done without it, the controller only gets the error and the exit message
+                if let Some(e) = poll_error_opt {
+                    error!("{e}");
+                    let _ = sender.send(ProxyMessage::Error(e));
+                }
+                running.store(false, std::sync::atomic::Ordering::Release);
+                queue.close();
+                pending.clear();
+                let _ = sender.send(ProxyMessage::Exit);
+                if let Some(notifier) = &notifier_opt {
+                    let _ = notifier.wake();
+                }
+                let _ = summary_sender.send(shutdown::ShutdownSummary {
+                    dropped: queue.len(),
+                    ..Default::default()
+                });
+                return;
+            };
+
+            let heartbeat = heartbeat::Heartbeat::new(cfg.heartbeat);
+            let mut proxy = Proxy::<SRCW, SWCR, C> {
+                cfg,
+                socket_opt,
+                peer_addr_opt,
+                reconnection_opt: None,
+                heartbeat,
+                source_opt: None,
+                poll,
+                _waker: waker,
+                queue,
+                pending,
+                shutdown,
+                sender,
+                notifier_opt,
+                running,
+                connected,
+                stats: stats_in,
+            };
 
-        let thread_handle = thread::spawn(move || proxy.run());
+            if let Some(e) = socket_error_opt {
+                proxy.handle_error(e);
+            }
+            let summary = proxy.run();
+            let _ = summary_sender.send(summary);
+        });
+
+        controller::ProxyController::new(
+            stats_out,
+            controller_queue,
+            receiver,
+            controller_pending,
+            channel_count,
+            running_controller,
+            connected_controller,
+            waker_opt,
+            controller_shutdown,
+            summary_receiver,
+            thread_handle,
+        )
+    }
 
-        controller::ProxyController::new(stats_out, main_channel, running, connected, thread_handle)
+    fn new_poll() -> Result<(mio::Poll, std::sync::Arc<mio::Waker>), error::ProxyError> {
+        let poll = mio::Poll::new()
+            .map_err(|e| error::ProxyError::Config(format!("Could not create the poll: {e}")))?;
+        let waker = mio::Waker::new(poll.registry(), WAKER_TOKEN)
+            .map_err(|e| error::ProxyError::Config(format!("Could not create the waker: {e}")))?;
+        Ok((poll, std::sync::Arc::new(waker)))
     }
 
     fn try_connect(&mut self) -> Result<(), error::ProxyError> {
-        use std::net::TcpStream;
         trace!("Trying to reconnect");
-        match TcpStream::connect(self.cfg.addr) {
-            Ok(stream) => {
-                if let Err(e) = stream.set_nonblocking(true) {
-                    error!("Could not set the created stream to non-blocking: {e}");
-                    return Err(error::ProxyError::Config(format!(
-                        "Could not set stream to non-blocking due to: {e}"
-                    )));
-                }
-                self.socket_opt = Some(crate::Socket::new(stream));
-                self.set_connected(true);
-                if !self.cfg.keep_msg_while_disconnected {
-                    while let Ok(value) = self.channel.try_recv() {
-                        drop(value)
-                    }
-                }
+        let addrs = self.cfg.addr.resolve()?;
+        let (stream, addr) = self.cfg.tcp.connect(&addrs)?;
+
+        if let Err(e) = self.cfg.tcp.apply(&stream) {
+            error!("Could not set up the created stream: {e}");
+            return Err(error::ProxyError::Config(format!(
+                "Could not set up the stream due to: {e}"
+            )));
+        }
+        let mut socket = Self::new_socket(&self.cfg, stream)?;
+        Self::handshake(&mut socket, addr)?;
+
+        self.socket_opt = Some(socket);
+        if !self.cfg.keep_msg_while_disconnected {
+            self.queue.clear();
+        }
+        self.on_connect(addr);
+        Ok(())
+    }
+
+    // Waits for the policy's delay and tries to connect again, false when the proxy should exit
+    fn reconnect(&mut self, events: &mut mio::Events) -> bool {
+        let Some(policy) = &self.cfg.reconnect else {
+            return false;
+        };
+        let reconnection = self
+            .reconnection_opt
+            .get_or_insert_with(|| reconnect::Reconnection::new(policy.clone()));
+
+        let Some(delay) = reconnection.next_attempt() else {
+            warn!(
+                "Gave up reconnecting to {} after {} attempts",
+                self.cfg.addr,
+                reconnection.attempt()
+            );
+            self.notify(ProxyMessage::GaveUp);
+            return false;
+        };
+        let attempt = reconnection.attempt();
+        self.notify(ProxyMessage::Reconnecting { attempt });
+
+        // The controller can still wake us up, what it sends stays in the channel but it might have been dropped
+        let deadline = std::time::Instant::now() + delay;
+        loop {
+            if self.is_stopping() {
+                return false;
             }
-            Err(e) => {
-                error!("Could not connect: {e}");
-                self.set_connected(false);
+            let left = deadline.saturating_duration_since(std::time::Instant::now());
+            if left.is_zero() {
+                break;
             }
+            self.wait(events, Some(left));
         }
-        Ok(())
+
+        if let Err(e) = self.try_connect() {
+            self.handle_error(e);
+        }
+
+        if self.socket_opt.is_some() {
+            debug!("Reconnected to {} after {attempt} attempts", self.cfg.addr);
+            self.reconnection_opt = None;
+
+            let mut stats = self.stats.read().clone();
+            stats.on_reconnect();
+            self.stats.write(stats);
+
+            self.notify(ProxyMessage::Reconnected);
+        }
+
+        true
+    }
+
+    // The given stream gets the same options as the ones the proxy connects
+    fn accept(
+        cfg: &config::ProxyConfig,
+        stream: std::net::TcpStream,
+    ) -> Result<(crate::Socket<SRCW, SWCR, C>, std::net::SocketAddr), error::ProxyError> {
+        let addr = stream.peer_addr().map_err(|e| {
+            error::ProxyError::Config(format!(
+                "Could not get the address of the given stream: {e}"
+            ))
+        })?;
+        cfg.tcp.apply(&stream).map_err(|e| {
+            error::ProxyError::Config(format!("Could not set up the given stream: {e}"))
+        })?;
+        Ok((Self::new_socket(cfg, stream)?, addr))
+    }
+
+    fn new_socket(
+        cfg: &config::ProxyConfig,
+        stream: std::net::TcpStream,
+    ) -> Result<crate::Socket<SRCW, SWCR, C>, error::ProxyError> {
+        let mut socket = match &cfg.tls {
+            None => crate::Socket::new(stream),
+            #[cfg(feature = "tls")]
+            Some(tls) => {
+                let connection = tls.connection().map_err(|e| {
+                    error::ProxyError::Config(format!("Could not create the tls session: {e}"))
+                })?;
+                crate::Socket::new_tls(stream, connection)
+            }
+            // TlsConfig has no variant without the tls feature
+            #[cfg(not(feature = "tls"))]
+            Some(tls) => match *tls {},
+        };
+        socket.set_max_frame_size(cfg.max_frame_size);
+        socket.set_compression(cfg.compression);
+        socket.set_channels(&cfg.channels);
+        socket.set_write_timeout(cfg.tcp.write_timeout);
+        Ok(socket)
+    }
+
+    fn handshake(
+        socket: &mut crate::Socket<SRCW, SWCR, C>,
+        addr: std::net::SocketAddr,
+    ) -> Result<(), error::ProxyError> {
+        socket
+            .handshake(HANDSHAKE_TIMEOUT)
+            .map_err(|error| error::ProxyError::Handshake { addr, error })
     }
+
     fn set_connected(&mut self, val: bool) {
         use std::sync::atomic::Ordering;
 
@@ -95,156 +334,470 @@ impl<SRCW: crate::Message + 'static, SWCR: crate::Message + 'static> Proxy<SRCW,
         self.running.store(val, Ordering::Release)
     }
 
-    fn reset_connection(&mut self) {
+    // The first connection is still made after a shutdown request, the queue can't be flushed without it
+    fn is_stopping(&self) -> bool {
+        use std::sync::atomic::Ordering;
+        !self.running.load(Ordering::Acquire) || self.shutdown.deadline().is_some()
+    }
+
+    // Once the handshake is done
+    fn on_connect(&mut self, addr: std::net::SocketAddr) {
+        self.peer_addr_opt = Some(addr);
+        self.heartbeat.reset();
+        self.set_connected(true);
+        self.notify(ProxyMessage::Connected { addr });
+    }
+
+    // The run loop then either reconnects or exits
+    fn reset_connection(&mut self, reason: message::DisconnectReason) {
         self.set_connected(false);
         self.socket_opt = None;
-        if let Err(e) = self.channel.send(ProxyMessage::ConnectionResetError) {
-            error!(
-                "Could not send {:?} message to main thread, {e}",
-                ProxyMessage::ConnectionResetError::<SRCW>
-            );
-            self.handle_error(ProxyError::ChannelSend(e.to_string()));
+        // The responses would be lost with the connection
+        self.pending.clear();
+        self.notify(ProxyMessage::Disconnected { reason });
+    }
+
+    fn notify(&mut self, msg: ProxyMessage<SRCW>) {
+        if let Err(e) = self.send_to_controller(msg) {
+            error!("Could not send {:?} message to main thread", e.0);
+            self.handle_error(ProxyError::ChannelSend);
         }
     }
 
+    fn send_to_controller(
+        &self,
+        msg: ProxyMessage<SRCW>,
+    ) -> Result<(), std::sync::mpsc::SendError<ProxyMessage<SRCW>>> {
+        Self::send_with(&self.sender, &self.notifier_opt, msg)
+    }
+
+    // Without borrowing the whole proxy, for when the socket is borrowed
+    fn send_with(
+        sender: &std::sync::mpsc::Sender<ProxyMessage<SRCW>>,
+        notifier_opt: &Option<std::sync::Arc<mio::Waker>>,
+        msg: ProxyMessage<SRCW>,
+    ) -> Result<(), std::sync::mpsc::SendError<ProxyMessage<SRCW>>> {
+        sender.send(msg)?;
+        if let Some(notifier) = notifier_opt {
+            // Only fails if the server has exited
+            let _ = notifier.wake();
+        }
+        Ok(())
+    }
+
     fn handle_error(&mut self, error: error::ProxyError) {
+        use crate::socket::{HandshakeError, SocketError};
+
         match error {
-            ProxyError::Config(e) => {
-                warn!("{e}");
+            // There is no one left to tell
+            ProxyError::ChannelSend | ProxyError::ChannelRecv => {
+                error!("{error}");
                 self.set_running(false);
             }
-            ProxyError::ChannelSend(e) => {
-                error!("{e}");
+            ProxyError::Config(_) => {
+                warn!("{error}");
                 self.set_running(false);
+                self.notify(ProxyMessage::Error(error));
+            }
+            ProxyError::Resolve { .. } | ProxyError::Connect { .. } => {
+                // Only the first failure is logged as an error, the policy says how many are expected
+                match &self.reconnection_opt {
+                    Some(reconnection) => {
+                        debug!("Reconnection attempt {}: {error}", reconnection.attempt())
+                    }
+                    None => error!("{error}"),
+                }
+                self.notify(ProxyMessage::Error(error));
             }
-            ProxyError::ChannelRecv(e) => {
-                error!("{e}");
+            // Retrying would not fix a version or message mismatch
+            ProxyError::Handshake {
+                error:
+                    SocketError::Handshake(
+                        HandshakeError::Magic
+                        | HandshakeError::Version { .. }
+                        | HandshakeError::Messages { .. },
+                    ),
+                ..
+            } => {
+                error!("{error}");
+                self.socket_opt = None;
                 self.set_running(false);
+                self.notify(ProxyMessage::Error(error));
             }
-            ProxyError::SocketSend(e) => {
-                error!("{e}");
-                if self.cfg.auto_reconnect {
-                    self.reset_connection();
-                } else {
-                    self.set_running(false);
-                }
+            // The other side was too slow or went away, the policy says whether to try again
+            ProxyError::Handshake { .. } => {
+                warn!("{error}");
+                self.reset_connection(message::DisconnectReason::Error(error));
+            }
+            ProxyError::SocketRecv(SocketError::Exited) => {
+                debug!(
+                    "socket {addr} has closed the connection",
+                    addr = self.cfg.addr
+                );
+                self.reset_connection(message::DisconnectReason::Closed);
             }
-            ProxyError::SocketRecv(e) => {
-                error!("{e}");
-                if self.cfg.auto_reconnect {
-                    self.reset_connection();
+            ProxyError::SocketSend(_) | ProxyError::SocketRecv(_) => {
+                if error.io_kind() == Some(std::io::ErrorKind::ConnectionReset) {
+                    warn!("socket {addr} disconnected", addr = self.cfg.addr);
                 } else {
-                    self.set_running(false);
+                    error!("Error with socket {addr}: {error}", addr = self.cfg.addr);
                 }
+                self.reset_connection(message::DisconnectReason::Error(error));
             }
             ProxyError::Disconnected => {}
         }
     }
 
-    fn run(mut self) {
+    // Registers a clone of the socket's stream, so the poll wakes up when it can be read or written
+    fn register(
+        registry: &mio::Registry,
+        socket: &crate::Socket<SRCW, SWCR, C>,
+        source_opt: &mut Option<mio::net::TcpStream>,
+    ) -> Result<(), error::ProxyError> {
+        let mut source = mio::net::TcpStream::from_std(socket.try_clone_stream().map_err(|e| {
+            error::ProxyError::Config(format!("Could not clone the stream for the poll: {e}"))
+        })?);
+
+        registry
+            .register(
+                &mut source,
+                SOCKET_TOKEN,
+                mio::Interest::READABLE | mio::Interest::WRITABLE,
+            )
+            .map_err(|e| {
+                error::ProxyError::Config(format!("Could not register the stream in the poll: {e}"))
+            })?;
+
+        *source_opt = Some(source);
+        Ok(())
+    }
+
+    fn deregister(&mut self) {
+        if let Some(mut source) = self.source_opt.take() {
+            let _ = self.poll.registry().deregister(&mut source);
+        }
+    }
+
+    // Sleeps until the socket or the controller has something for us, or until the timeout
+    // The events are edge-triggered, so everything has to be read before coming back here
+    fn wait(&mut self, events: &mut mio::Events, timeout: Option<std::time::Duration>) {
+        match self.poll.poll(events, timeout) {
+            Ok(()) => (),
+            // The loop comes back here anyway
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+            Err(e) => {
+                error!("Could not poll: {e}");
+                self.set_running(false);
+            }
+        }
+    }
+
+    fn run(mut self) -> shutdown::ShutdownSummary {
         use std::sync::atomic::Ordering;
-        let mut loop_helper = spin_sleep::LoopHelper::builder()
-            .report_interval_s(0.5)
-            .build_with_target_rate(self.cfg.run_tps as f64);
 
-        if self.socket_opt.is_none() {
-            if let Err(e) = self.try_connect() {
-                self.handle_error(e)
+        let mut events = mio::Events::with_capacity(16);
+
+        match (&mut self.socket_opt, self.peer_addr_opt) {
+            // The stream was given by the user (or the server), the handshake is still needed
+            (Some(socket), Some(addr)) => match Self::handshake(socket, addr) {
+                Ok(()) => self.on_connect(addr),
+                Err(e) => self.handle_error(e),
+            },
+            // Unless the given stream could not be used
+            (None, _) if self.running.load(Ordering::Acquire) => {
+                if let Err(e) = self.try_connect() {
+                    self.handle_error(e)
+                }
             }
+            _ => (),
         }
 
-        while self.running.load(Ordering::Acquire) {
-            loop_helper.loop_start();
+        while !self.is_stopping() {
+            let loop_start = std::time::Instant::now();
 
             let mut stats = self.stats.read().clone();
 
             let Some(socket) = &mut self.socket_opt else {
-                if !self.cfg.auto_reconnect {
-                    break;
-                }
+                self.deregister();
+                stats.on_disconnect();
+                self.stats.write(stats);
 
-                if let Err(e) = self.try_connect() {
-                    self.handle_error(e);
+                if !self.reconnect(&mut events) {
+                    break;
                 }
 
                 continue;
             };
 
-            if let Err(e) = stats.update(&mut self.channel, socket) {
+            if self.source_opt.is_none() {
+                if let Err(e) = Self::register(self.poll.registry(), socket, &mut self.source_opt) {
+                    self.handle_error(e);
+                    continue;
+                }
+            }
+
+            if let Err(e) = stats.update(socket) {
                 self.handle_error(e)
             }
 
-            if let Err(e) = self.handle_local(&mut stats) {
-                self.handle_error(e);
+            let local_left = match self.handle_local(&mut stats) {
+                Ok(left) => left,
+                Err(e) => {
+                    self.handle_error(e);
+                    continue;
+                }
+            };
+
+            let distant_left = match self.handle_distant(&mut stats) {
+                Ok(left) => left,
+                Err(e) => {
+                    self.handle_error(e);
+                    continue;
+                }
+            };
+
+            if self.heartbeat.is_dead() {
+                warn!(
+                    "Nothing received from {} for {:?}, resetting the connection",
+                    self.cfg.addr,
+                    self.heartbeat.silence()
+                );
+                self.reset_connection(message::DisconnectReason::TimedOut);
                 continue;
             }
-
-            if let Err(e) = self.handle_distant(&mut stats) {
+            if let Err(e) = self.heartbeat_ping(&mut stats) {
                 self.handle_error(e);
                 continue;
             }
 
+            // What the batch budget left behind won't wake the poll up again
+            // A full stream wakes it up once it's writable, or the write timeout has to be checked
+            let timeout = if local_left || distant_left {
+                Some(std::time::Duration::ZERO)
+            } else {
+                let write_left = self
+                    .socket_opt
+                    .as_ref()
+                    .and_then(|socket| socket.write_deadline())
+                    .map(|deadline| deadline.saturating_duration_since(std::time::Instant::now()));
+                stats
+                    .next_update()
+                    .into_iter()
+                    .chain(write_left)
+                    .chain(self.heartbeat.until_next_check())
+                    .min()
+            };
             self.stats.write(stats);
 
-            loop_helper.loop_sleep();
+            // Only there to limit the cpu usage of a very busy proxy
+            if let Some(run_tps) = self.cfg.run_tps {
+                let tick = std::time::Duration::from_secs_f64(1. / run_tps as f64);
+                spin_sleep::sleep(tick.saturating_sub(loop_start.elapsed()));
+            }
+
+            self.wait(&mut events, timeout);
         }
 
-        if let Err(e) = self.channel.send(ProxyMessage::Exit) {
+        // Only when the controller asked for it, the other reasons to stop don't wait for anything
+        let summary = match self.shutdown.deadline() {
+            Some(deadline) => self.drain(deadline, &mut events),
+            None => shutdown::ShutdownSummary {
+                dropped: self.queue.len(),
+                ..Default::default()
+            },
+        };
+
+        // The blocked senders get their message back, and the requests RpcError::Disconnected
+        self.queue.close();
+        self.pending.clear();
+
+        if let Err(e) = self.send_to_controller(ProxyMessage::Exit) {
             error!("Could not send exit message to main thread: {e}")
         }
 
         self.set_running(false);
         self.set_connected(false);
 
-        if let Some(socket) = self.socket_opt {
+        self.deregister();
+        if let Some(socket) = self.socket_opt.take() {
             socket.shutdown();
         }
 
-        // Give a bit of time to everything to synchronise, and exit cleanly
-        spin_sleep::sleep(std::time::Duration::from_secs(1));
-
         debug!("Proxy for ({}) has exited", self.cfg.addr);
+        summary
     }
-    /// here you receive the message sent by the channel
+
+    // Sends what is left in the queue and a close frame, then waits for the other side to close the connection
+    // Nothing is taken from the queue once the deadline is reached
+    fn drain(
+        &mut self,
+        deadline: std::time::Instant,
+        events: &mut mio::Events,
+    ) -> shutdown::ShutdownSummary {
+        use crate::socket::SocketError;
+
+        let queued = self.queue.len();
+        let mut summary = shutdown::ShutdownSummary {
+            dropped: queued,
+            ..Default::default()
+        };
+
+        let Some(socket) = &self.socket_opt else {
+            return summary;
+        };
+        if self.source_opt.is_none() {
+            if let Err(e) = Self::register(self.poll.registry(), socket, &mut self.source_opt) {
+                warn!("{e}");
+                return summary;
+            }
+        }
+
+        let mut stats = self.stats.read().clone();
+
+        // A message only counts as flushed once the socket has written all of it
+        loop {
+            if let Err(e) = self.handle_local(&mut stats) {
+                debug!("Could not flush the queue for {}: {e}", self.cfg.addr);
+                return summary;
+            }
+            let Some(socket) = &self.socket_opt else {
+                return summary;
+            };
+
+            if socket.pending_bytes() == 0 {
+                summary.flushed = queued - self.queue.len();
+                summary.dropped = self.queue.len();
+
+                if summary.dropped == 0 {
+                    break;
+                }
+                // The batch budget was spent, not the stream
+                continue;
+            }
+
+            let left = deadline.saturating_duration_since(std::time::Instant::now());
+            if left.is_zero() {
+                return summary;
+            }
+            self.wait(events, Some(left));
+        }
+
+        if let Some(socket) = &mut self.socket_opt {
+            if let Err(e) = socket.close() {
+                debug!("Could not send the close frame to {}: {e}", self.cfg.addr);
+                return summary;
+            }
+        }
+
+        // What comes before the close frame of the other side is still forwarded
+        loop {
+            match self.handle_distant(&mut stats) {
+                Ok(true) => continue,
+                Ok(false) => (),
+                Err(ProxyError::SocketRecv(SocketError::Exited)) => {
+                    summary.acknowledged = true;
+                    break;
+                }
+                Err(e) => {
+                    debug!("Stopped waiting for {} to close: {e}", self.cfg.addr);
+                    break;
+                }
+            }
+
+            let left = deadline.saturating_duration_since(std::time::Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            self.wait(events, Some(left));
+        }
+
+        self.stats.write(stats);
+        summary
+    }
+    /// here you receive the message sent by the controller
+    ///
+    /// Returns true if the batch budget ran out before the queue was empty
     fn handle_local(
         &mut self,
         stats: &mut super::NetworkStats<SRCW, SWCR>,
-    ) -> Result<(), error::ProxyError> {
+    ) -> Result<bool, error::ProxyError> {
         use std::sync::mpsc::TryRecvError;
 
         let Some(socket) = &mut self.socket_opt else {
             return Err(ProxyError::Disconnected);
         };
 
-        match self.channel.try_recv() {
-            Ok(local_msg) => {
-                stats.on_msg_send(&local_msg);
-                match socket.send(local_msg) {
-                    Ok(header) => {
-                        // Do something with the number of bytes sent in the stats
-                        stats.on_bytes_send(&header);
-                        Ok(())
-                    }
-                    Err(e) => {
-                        error!(
-                            "Proxy encountered an error while forwarding a message to the server: {e:?}"
-                        );
-                        Err(e).map_err(|e| ProxyError::SocketSend(format!("{e:?}")))
+        stats.on_queue_update(self.queue.len(), self.queue.dropped());
+
+        if let Err(e) = socket.flush() {
+            return Err(ProxyError::SocketSend(e));
+        }
+
+        // Everything that is waiting is sent, the controller only wakes us up once
+        // Unless the stream is full, the rest stays in the queue so the controller feels it
+        // The poll wakes us up once the stream can be written again
+        let mut budget = batch::Budget::new(self.cfg.batch);
+        let mut left = false;
+        let mut stream_full = socket.pending_bytes() != 0;
+
+        while !stream_full {
+            if budget.is_spent() {
+                left = true;
+                break;
+            }
+
+            match self.queue.try_pop() {
+                Ok(outgoing) => {
+                    let (kind, channel, request_id, local_msg) = outgoing.into_frame();
+                    let name_opt = stats.message_type_name_opt(&local_msg);
+
+                    // Queued in the socket, the whole batch is written by priority
+                    match socket.queue_data(kind, channel, request_id, local_msg) {
+                        Ok(header) => {
+                            // Do something with the number of bytes sent in the stats
+                            stats.on_bytes_send(&header);
+                            stats.on_message_send(name_opt, &header);
+                            budget.spend(&header);
+                        }
+                        Err(e) => {
+                            error!(
+                                "Proxy encountered an error while forwarding a message to the server: {e:?}"
+                            );
+                            return Err(ProxyError::SocketSend(e));
+                        }
                     }
                 }
+                Err(e) => match e {
+                    TryRecvError::Empty => break, // WouldBlock,
+                    TryRecvError::Disconnected => {
+                        error!("Proxy encountered an error while listening local channel: {e:?}");
+                        return Err(ProxyError::ChannelRecv);
+                    }
+                },
             }
-            Err(e) => match e {
-                TryRecvError::Empty => Ok(()), // WouldBlock,
-                TryRecvError::Disconnected => {
-                    error!("Proxy encountered an error while listening local channel: {e:?}");
-                    Err(ProxyError::ChannelRecv(e.to_string()))
+
+            // A big batch is written by chunks, so a slow peer stops it before it's all in memory
+            if socket.pending_bytes() >= BATCH_FLUSH_SIZE {
+                if let Err(e) = socket.flush() {
+                    return Err(ProxyError::SocketSend(e));
                 }
-            },
+                stream_full = socket.pending_bytes() != 0;
+            }
+        }
+
+        if let Err(e) = socket.flush() {
+            return Err(ProxyError::SocketSend(e));
         }
+
+        stats.on_queue_update(self.queue.len(), self.queue.dropped());
+
+        // If the stream is full, the poll wakes us up when it's not
+        Ok(left && socket.pending_bytes() == 0)
     }
 
-    /// here you receive message sent by the socket
-    fn handle_distant(
+    // Asks the other side for a pong when it has been quiet for a while
+    // Every pong stops the rtt's stopwatch, so the ping of the rtt is used when there is one and this one is timed too
+    fn heartbeat_ping(
         &mut self,
         stats: &mut super::NetworkStats<SRCW, SWCR>,
     ) -> Result<(), error::ProxyError> {
@@ -252,35 +805,89 @@ impl<SRCW: crate::Message + 'static, SWCR: crate::Message + 'static> Proxy<SRCW,
             return Err(ProxyError::Disconnected);
         };
 
-        match socket.try_recv() {
-            Ok((header, msg)) => {
-                stats.on_msg_recv(&msg, socket);
-                stats.on_bytes_recv(&header);
+        if self.heartbeat.needs_ping() {
+            if !stats.is_waiting_pong() {
+                let header = socket.ping().map_err(ProxyError::SocketSend)?;
+                stats.on_ping_send();
+                stats.on_bytes_send(&header);
+            }
+            self.heartbeat.on_ping();
+        }
+
+        Ok(())
+    }
+
+    /// here you receive message sent by the socket
+    ///
+    /// Returns true if the batch budget ran out before everything was read
+    fn handle_distant(
+        &mut self,
+        stats: &mut super::NetworkStats<SRCW, SWCR>,
+    ) -> Result<bool, error::ProxyError> {
+        use crate::socket::{Frame, FrameKind};
+
+        let Some(socket) = &mut self.socket_opt else {
+            return Err(ProxyError::Disconnected);
+        };
 
-                self.channel
-                    .send(ProxyMessage::Forward(msg))
-                    .map_err(|e| ProxyError::ChannelSend(format!("{e}")))?;
+        // Everything that is buffered is read, the poll only wakes us up on new data
+        let mut budget = batch::Budget::new(self.cfg.batch);
 
-                Ok(())
-                // .map_err(|e| super::NetworkError::Proxy(format!("{e:?}")))?;
+        loop {
+            if budget.is_spent() {
+                return Ok(true);
             }
-            Err(crate::socket::SocketError::StreamRead(ref io_e))
-                if io_e.kind() == std::io::ErrorKind::WouldBlock =>
-            {
-                Ok(())
+
+            let res = socket.try_recv_frame();
+            if res.is_ok() {
+                self.heartbeat.on_recv();
             }
-            Err(crate::socket::SocketError::Exited) => return Err(ProxyError::Disconnected),
-            Err(e) => {
-                // The error might just be that the socket disconnected
-                if let crate::socket::SocketError::StreamRead(ref io_e) = e {
-                    if io_e.kind() == std::io::ErrorKind::ConnectionReset {
-                        warn!("socket {addr} disconnected", addr = self.cfg.addr);
-                    }
-                } else {
-                    error!("Error while listening socket {}: {e}", socket.remote_addr());
+
+            match res {
+                Ok((header, Frame::Data(msg))) => {
+                    stats.on_bytes_recv(&header);
+                    stats.on_message_recv(stats.message_type_name_opt(&msg), &header);
+                    budget.spend(&header);
+
+                    let proxy_msg = match header.kind {
+                        FrameKind::Response => {
+                            if !self.pending.resolve(header.request_id, msg) {
+                                debug!(
+                                    "Dropped the response to request {}, it was cancelled or has timed out",
+                                    header.request_id
+                                );
+                            }
+                            continue;
+                        }
+                        FrameKind::Request => ProxyMessage::Request {
+                            id: header.request_id,
+                            msg,
+                        },
+                        _ => ProxyMessage::Forward(msg),
+                    };
+
+                    Self::send_with(&self.sender, &self.notifier_opt, proxy_msg)
+                        .map_err(|_| ProxyError::ChannelSend)?;
+                    // .map_err(|e| super::NetworkError::Proxy(format!("{e:?}")))?;
+                }
+                Ok((header, Frame::Ping)) => {
+                    stats.on_bytes_recv(&header);
+
+                    // Always answered, even when the rtt stat is disabled on this side
+                    let header = socket.pong().map_err(ProxyError::SocketSend)?;
+                    stats.on_bytes_send(&header);
+                }
+                Ok((header, Frame::Pong)) => {
+                    stats.on_bytes_recv(&header);
+                    stats.on_pong_recv();
+                }
+                Err(crate::socket::SocketError::StreamRead(ref io_e))
+                    if io_e.kind() == std::io::ErrorKind::WouldBlock =>
+                {
+                    return Ok(false)
                 }
-                self.reset_connection();
-                Err(ProxyError::SocketRecv(e.to_string()))
+                // A close frame too, handle_error drops the socket
+                Err(e) => return Err(ProxyError::SocketRecv(e)),
             }
         }
     }
@@ -0,0 +1,77 @@
+// Where the proxy connects to
+// A host name is resolved again before every attempt, so a server that moved is found after a reconnection
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Address {
+    Socket(std::net::SocketAddr),
+    // "host:port", every address it resolves to is tried in order until one accepts the connection
+    Host(String),
+}
+
+impl Address {
+    // Blocks on the os resolver for host names
+    pub(crate) fn resolve(&self) -> Result<Vec<std::net::SocketAddr>, super::ProxyError> {
+        use std::net::ToSocketAddrs as _;
+
+        match self {
+            Address::Socket(addr) => Ok(vec![*addr]),
+            Address::Host(host) => resolved(host, host.to_socket_addrs().map(Iterator::collect)),
+        }
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) async fn resolve_async(
+        &self,
+    ) -> Result<Vec<std::net::SocketAddr>, super::ProxyError> {
+        match self {
+            Address::Socket(addr) => Ok(vec![*addr]),
+            Address::Host(host) => resolved(
+                host,
+                tokio::net::lookup_host(host).await.map(Iterator::collect),
+            ),
+        }
+    }
+}
+
+// A host without any address is an error too, there would be nothing to connect to
+fn resolved(
+    host: &str,
+    res: std::io::Result<Vec<std::net::SocketAddr>>,
+) -> Result<Vec<std::net::SocketAddr>, super::ProxyError> {
+    let kind = match res {
+        Ok(addrs) if !addrs.is_empty() => return Ok(addrs),
+        Ok(_) => std::io::ErrorKind::NotFound,
+        Err(e) => e.kind(),
+    };
+
+    Err(super::ProxyError::Resolve {
+        host: host.to_string(),
+        kind,
+    })
+}
+
+impl From<std::net::SocketAddr> for Address {
+    fn from(addr: std::net::SocketAddr) -> Self {
+        Address::Socket(addr)
+    }
+}
+
+impl From<String> for Address {
+    fn from(host: String) -> Self {
+        Address::Host(host)
+    }
+}
+
+impl From<&str> for Address {
+    fn from(host: &str) -> Self {
+        Address::Host(host.to_string())
+    }
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Address::Socket(addr) => write!(f, "{addr}"),
+            Address::Host(host) => write!(f, "{host}"),
+        }
+    }
+}
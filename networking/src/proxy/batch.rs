@@ -0,0 +1,47 @@
+// How much the proxy handles in one direction before looking at the other one
+// Whatever is left is handled right after, without sleeping
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BatchConfig {
+    // The most messages per loop, None for no limit
+    pub max_messages: Option<usize>,
+    // The most bytes per loop (frame headers included), None for no limit
+    pub max_bytes: Option<u64>,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_messages: Some(1024),
+            max_bytes: Some(1024 * 1024),
+        }
+    }
+}
+
+// What one direction has used in the current loop
+pub(crate) struct Budget {
+    cfg: BatchConfig,
+    messages: usize,
+    bytes: u64,
+}
+
+impl Budget {
+    pub(crate) fn new(cfg: BatchConfig) -> Self {
+        Self {
+            cfg,
+            messages: 0,
+            bytes: 0,
+        }
+    }
+
+    pub(crate) fn spend(&mut self, header: &crate::socket::Header) {
+        self.messages += 1;
+        self.bytes += crate::socket::HEADER_SIZE + header.size;
+    }
+
+    pub(crate) fn is_spent(&self) -> bool {
+        self.cfg
+            .max_messages
+            .is_some_and(|max| self.messages >= max)
+            || self.cfg.max_bytes.is_some_and(|max| self.bytes >= max)
+    }
+}
@@ -1,9 +1,61 @@
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct ProxyConfig {
-    pub addr: std::net::SocketAddr,
-    pub run_tps: u64,
+    // A SocketAddr or a "host:port", see networking::proxy::Address
+    pub addr: super::Address,
+    // The most loops per second the proxy can do, None to not limit it
+    // The proxy sleeps until the socket or the controller has something for it, this only matters when it's very busy
+    pub run_tps: Option<u64>,
     pub stat_cfg: crate::stats::StatConfig,
     // https://github.com/Bowarc/Crates/issues/8
     pub keep_msg_while_disconnected: bool,
-    pub auto_reconnect: bool,
+    // How the proxy reconnects once the connection is lost, None to exit instead
+    pub reconnect: Option<super::ReconnectPolicy>,
+    // Pings the other side when it's quiet and resets the connection when it stays silent, None to wait forever
+    pub heartbeat: Option<super::HeartbeatConfig>,
+    // The messages waiting to be sent, see networking::proxy::OverflowPolicy for what happens when it's full
+    // The capacity has to be at least 1
+    pub queue: super::QueueConfig,
+    // How much is sent or received in one loop, see networking::proxy::BatchConfig
+    pub batch: super::BatchConfig,
+    // The channels that ProxyController::send_on can use and their priority, see networking::socket::ChannelConfig
+    pub channels: crate::socket::ChannelConfig,
+    // Biggest payload accepted from the remote, see networking::socket::DEFAULT_MAX_FRAME_SIZE
+    pub max_frame_size: u64,
+    // Compression of the sent messages, see networking::Socket::set_compression
+    pub compression: Option<crate::Compression>,
+    // Connect timeout and options of the stream, see networking::proxy::TcpConfig
+    pub tcp: super::TcpConfig,
+    // Needs the tls feature, see networking::tls::TlsConfig
+    pub tls: Option<crate::tls::TlsConfig>,
+}
+
+impl ProxyConfig {
+    /// Connects to addr with everything else at its default: no stats, no reconnection, no heartbeat, no tls
+    pub fn new(addr: impl Into<super::Address>) -> Self {
+        Self {
+            addr: addr.into(),
+            run_tps: None,
+            stat_cfg: Default::default(),
+            keep_msg_while_disconnected: false,
+            reconnect: None,
+            heartbeat: None,
+            queue: Default::default(),
+            batch: Default::default(),
+            channels: Default::default(),
+            max_frame_size: crate::socket::DEFAULT_MAX_FRAME_SIZE,
+            compression: None,
+            tcp: Default::default(),
+            tls: None,
+        }
+    }
+
+    // What the proxy can't run with, it exits with a ProxyError::Config instead
+    pub(crate) fn check(&self) -> Result<(), super::ProxyError> {
+        if self.queue.capacity == 0 {
+            return Err(super::ProxyError::Config(String::from(
+                "The capacity of the queue can't be 0, nothing could be sent",
+            )));
+        }
+        Ok(())
+    }
 }
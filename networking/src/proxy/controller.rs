@@ -1,36 +1,172 @@
-pub struct ProxyController<R: crate::Message, W: crate::Message> {
+// How long a dropped controller gives the proxy to flush its queue, see ProxyController::shutdown
+const DROP_SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(100);
+
+// The proxy exits on its own once the timeout of a shutdown is reached, unless it's blocked in a connection attempt
+const SHUTDOWN_MARGIN: std::time::Duration = std::time::Duration::from_millis(100);
+
+// The codec is only there so the right Proxy can be inferred from this type
+pub struct ProxyController<
+    R: crate::Message,
+    W: crate::Message,
+    C: crate::Codec = crate::codec::Bincode,
+> {
     stats: triple_buffer::Output<crate::NetworkStats<R, W>>,
-    channel: threading::Channel<super::ProxyMessage<R>, W>,
+    queue: std::sync::Arc<super::queue::Queue<super::rpc::Outgoing<W>>>,
+    receiver: std::sync::mpsc::Receiver<super::ProxyMessage<R>>,
+    // The requests waiting for their response
+    pending: std::sync::Arc<super::rpc::Pending<R>>,
+    // Checked here, the proxy would have to drop the message
+    channel_count: usize,
     running: std::sync::Arc<std::sync::atomic::AtomicBool>,
     connected: std::sync::Arc<std::sync::atomic::AtomicBool>,
-    thread_handle: std::thread::JoinHandle<()>,
+    // Wakes the proxy's thread up, None if the proxy could not create its poll (it has already exited)
+    waker_opt: Option<std::sync::Arc<mio::Waker>>,
+    shutdown: std::sync::Arc<super::shutdown::Shutdown>,
+    summary_receiver: std::sync::mpsc::Receiver<super::ShutdownSummary>,
+    // Only taken by the shutdown
+    thread_handle_opt: Option<std::thread::JoinHandle<()>>,
+    codec: std::marker::PhantomData<C>,
 }
 
-impl<R: crate::Message, W: crate::Message> ProxyController<R, W> {
+impl<R: crate::Message, W: crate::Message, C: crate::Codec> ProxyController<R, W, C> {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         stats: triple_buffer::Output<crate::NetworkStats<R, W>>,
-        channel: threading::Channel<super::ProxyMessage<R>, W>,
+        queue: std::sync::Arc<super::queue::Queue<super::rpc::Outgoing<W>>>,
+        receiver: std::sync::mpsc::Receiver<super::ProxyMessage<R>>,
+        pending: std::sync::Arc<super::rpc::Pending<R>>,
+        channel_count: usize,
         running: std::sync::Arc<std::sync::atomic::AtomicBool>,
         connected: std::sync::Arc<std::sync::atomic::AtomicBool>,
+        waker_opt: Option<std::sync::Arc<mio::Waker>>,
+        shutdown: std::sync::Arc<super::shutdown::Shutdown>,
+        summary_receiver: std::sync::mpsc::Receiver<super::ShutdownSummary>,
         thread_handle: std::thread::JoinHandle<()>,
-    ) -> ProxyController<R, W> {
+    ) -> ProxyController<R, W, C> {
         ProxyController {
             stats,
-            channel,
+            queue,
+            receiver,
+            pending,
+            channel_count,
             running,
             connected,
-            thread_handle,
+            waker_opt,
+            shutdown,
+            summary_receiver,
+            thread_handle_opt: Some(thread_handle),
+            codec: std::marker::PhantomData,
+        }
+    }
+
+    /// The proxy is woken up right away to forward it
+    ///
+    /// When the queue is full, what happens depends on the [`super::OverflowPolicy`] of the config
+    pub fn send(&self, msg: W) -> Result<(), super::SendError<W>> {
+        self.push(super::rpc::Outgoing::Message(0, msg))
+    }
+
+    /// Same as [`ProxyController::send`], but never blocks or drops, a full queue is an error
+    pub fn try_send(&self, msg: W) -> Result<(), super::SendError<W>> {
+        self.try_push(super::rpc::Outgoing::Message(0, msg))
+    }
+
+    /// Same as [`ProxyController::send`], on one of the channels of the config
+    ///
+    /// The channels share the queue, the priority only matters once the proxy has taken the message
+    pub fn send_on(&self, channel: u8, msg: W) -> Result<(), super::SendError<W>> {
+        if channel as usize >= self.channel_count {
+            return Err(super::SendError::UnknownChannel(msg));
+        }
+        self.push(super::rpc::Outgoing::Message(channel, msg))
+    }
+
+    /// Same as [`ProxyController::try_send`], on one of the channels of the config
+    pub fn try_send_on(&self, channel: u8, msg: W) -> Result<(), super::SendError<W>> {
+        if channel as usize >= self.channel_count {
+            return Err(super::SendError::UnknownChannel(msg));
         }
+        self.try_push(super::rpc::Outgoing::Message(channel, msg))
+    }
+
+    /// Sends a message that the other side answers with [`ProxyController::respond`]
+    ///
+    /// The response comes through the returned handle, not through [`ProxyController::recv`].
+    /// Like any message, the request can be dropped by the [`super::OverflowPolicy`], it then times out
+    pub fn request(
+        &self,
+        msg: W,
+        timeout: std::time::Duration,
+    ) -> Result<super::Request<R>, super::SendError<W>> {
+        let (id, receiver) = self.pending.insert();
+
+        if let Err(e) = self.push(super::rpc::Outgoing::Request(id, msg)) {
+            self.pending.remove(id);
+            return Err(e);
+        }
+
+        Ok(super::Request::new(
+            id,
+            receiver,
+            self.pending.clone(),
+            timeout,
+        ))
+    }
+
+    /// Answers a [`super::ProxyMessage::Request`] with its id
+    pub fn respond(&self, id: u64, msg: W) -> Result<(), super::SendError<W>> {
+        self.push(super::rpc::Outgoing::Response(id, msg))
+    }
+
+    /// Same as [`ProxyController::respond`], but never blocks or drops, a full queue is an error
+    pub fn try_respond(&self, id: u64, msg: W) -> Result<(), super::SendError<W>> {
+        self.try_push(super::rpc::Outgoing::Response(id, msg))
+    }
+
+    /// How many messages are waiting to be sent by the proxy
+    pub fn queue_len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// How many requests are waiting for their response
+    pub fn pending_requests(&self) -> usize {
+        self.pending.len()
+    }
+
+    // The proxy is woken up right away to forward it
+    fn push(&self, outgoing: super::rpc::Outgoing<W>) -> Result<(), super::SendError<W>> {
+        self.queue
+            .push(outgoing)
+            .map_err(|e| e.map(super::rpc::Outgoing::into_inner))?;
+        self.wake();
+        Ok(())
     }
 
-    pub fn send(&self, msg: W) -> Result<(), std::sync::mpsc::SendError<W>> {
-        self.channel.send(msg)
+    fn try_push(&self, outgoing: super::rpc::Outgoing<W>) -> Result<(), super::SendError<W>> {
+        self.queue
+            .try_push(outgoing)
+            .map_err(|e| e.map(super::rpc::Outgoing::into_inner))?;
+        self.wake();
+        Ok(())
+    }
+
+    fn wake(&self) {
+        if let Some(waker) = &self.waker_opt {
+            // Only fails if the proxy has exited
+            let _ = waker.wake();
+        }
     }
     pub fn recv(&self) -> Result<super::ProxyMessage<R>, std::sync::mpsc::RecvError> {
-        self.channel.recv()
+        self.receiver.recv()
     }
     pub fn try_recv(&self) -> Result<super::ProxyMessage<R>, std::sync::mpsc::TryRecvError> {
-        self.channel.try_recv()
+        self.receiver.try_recv()
+    }
+    pub fn recv_timeout(
+        &self,
+        timeout: std::time::Duration,
+    ) -> Result<super::ProxyMessage<R>, std::sync::mpsc::RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
     }
 
     pub fn is_running(&self) -> bool {
@@ -41,16 +177,75 @@ impl<R: crate::Message, W: crate::Message> ProxyController<R, W> {
         self.connected.load(std::sync::atomic::Ordering::Relaxed)
     }
 
-    pub fn request_ping(&self) -> Result<(), std::sync::mpsc::SendError<W>> {
-        self.send(W::default_ping())
-    }
-
     // Needs mut because it's updating before returning the data
     pub fn stats(&mut self) -> &crate::NetworkStats<R, W> {
         self.stats.read()
     }
 
     pub fn thread_handle(&self) -> &std::thread::JoinHandle<()> {
-        &self.thread_handle
+        self.thread_handle_opt
+            .as_ref()
+            .expect("The thread handle is only taken when the controller goes away")
+    }
+
+    /// Stops the proxy once it has sent what is waiting in the queue
+    ///
+    /// The proxy then sends a close frame and waits for the other side to close the connection too.
+    /// What is not done once the timeout is reached is dropped, the summary says how much of the queue went through
+    pub fn shutdown(mut self, timeout: std::time::Duration) -> super::ShutdownSummary {
+        self.stop(timeout)
+    }
+
+    fn stop(&mut self, timeout: std::time::Duration) -> super::ShutdownSummary {
+        self.request_shutdown(timeout);
+        self.wait_shutdown()
+    }
+
+    // The first half of a shutdown, so many proxies can flush their queue at the same time
+    pub(crate) fn request_shutdown(&self, timeout: std::time::Duration) {
+        if self.thread_handle_opt.is_none() {
+            return;
+        }
+
+        // The proxy stops its loop for it, is_running stays true until it's done
+        self.shutdown.request(timeout);
+        self.wake();
+    }
+
+    // Waits for the proxy until the deadline of the requested shutdown
+    pub(crate) fn wait_shutdown(&mut self) -> super::ShutdownSummary {
+        let Some(thread_handle) = self.thread_handle_opt.take() else {
+            return Default::default();
+        };
+        let left = self
+            .shutdown
+            .deadline()
+            .map(|deadline| deadline.saturating_duration_since(std::time::Instant::now()))
+            .unwrap_or_default();
+
+        // The summary is sent right before the thread ends, the join doesn't wait after it
+        match self.summary_receiver.recv_timeout(left + SHUTDOWN_MARGIN) {
+            Ok(summary) => {
+                let _ = thread_handle.join();
+                summary
+            }
+            Err(e) => {
+                // The thread is left behind, it exits once its connection attempt is over
+                warn!("The proxy did not give its shutdown summary in time: {e}");
+                super::ShutdownSummary {
+                    dropped: self.queue.len(),
+                    ..Default::default()
+                }
+            }
+        }
+    }
+}
+
+impl<R: crate::Message, W: crate::Message, C: crate::Codec> std::ops::Drop
+    for ProxyController<R, W, C>
+{
+    // Same as ProxyController::shutdown, with a short timeout
+    fn drop(&mut self) {
+        self.stop(DROP_SHUTDOWN_TIMEOUT);
     }
 }
@@ -3,16 +3,57 @@ pub enum ProxyError {
     #[error("Config error: {0}")]
     Config(String),
 
-    #[error("{0}")]
-    ChannelSend(String),
-    #[error("{0}")]
-    ChannelRecv(String),
+    // The host name of networking::proxy::Address::Host could not be turned into an address
+    #[error("Could not resolve {host}: {kind}")]
+    Resolve {
+        host: String,
+        kind: std::io::ErrorKind,
+    },
+
+    // ConnectionRefused when nothing listens there, TimedOut when nothing answers
+    #[error("Could not connect to {addr}: {kind}")]
+    Connect {
+        addr: std::net::SocketAddr,
+        kind: std::io::ErrorKind,
+    },
+
+    // The stream was fine but the other side is not what this side expects, or it did not answer
+    #[error("Could not connect to {addr}: {error}")]
+    Handshake {
+        addr: std::net::SocketAddr,
+        error: crate::socket::SocketError,
+    },
+
+    #[error("The controller has been dropped")]
+    ChannelSend,
+    #[error("The controller has been dropped")]
+    ChannelRecv,
 
     #[error("{0}")]
-    SocketSend(String),
+    SocketSend(crate::socket::SocketError),
     #[error("{0}")]
-    SocketRecv(String),
+    SocketRecv(crate::socket::SocketError),
 
     #[error("Proxy is disconnected")]
     Disconnected,
 }
+
+impl ProxyError {
+    /// The kind of the io error behind this one, if there is one
+    ///
+    /// Tells a refused connection or a reset apart from the errors of the protocol itself
+    pub fn io_kind(&self) -> Option<std::io::ErrorKind> {
+        use crate::socket::SocketError;
+
+        match self {
+            ProxyError::Resolve { kind, .. } | ProxyError::Connect { kind, .. } => Some(*kind),
+            ProxyError::Handshake { error, .. }
+            | ProxyError::SocketSend(error)
+            | ProxyError::SocketRecv(error) => match error {
+                SocketError::StreamRead(e) | SocketError::StreamWrite(e) => Some(e.kind()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,78 @@
+// How the proxy notices a peer that froze without closing the connection
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeartbeatConfig {
+    // A ping is sent when nothing was received for that long, the peer's pong counts as something
+    pub interval: std::time::Duration,
+    // The connection is reset with DisconnectReason::TimedOut when nothing was received for that long
+    pub timeout: std::time::Duration,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: std::time::Duration::from_secs(1),
+            timeout: std::time::Duration::from_secs(10),
+        }
+    }
+}
+
+// The last time the peer was heard of, and the last time it was asked to say something
+pub(crate) struct Heartbeat {
+    cfg: Option<HeartbeatConfig>,
+    last_recv: std::time::Instant,
+    last_ping: std::time::Instant,
+}
+
+impl Heartbeat {
+    pub(crate) fn new(cfg: Option<HeartbeatConfig>) -> Self {
+        Self {
+            cfg,
+            last_recv: std::time::Instant::now(),
+            last_ping: std::time::Instant::now(),
+        }
+    }
+
+    // Once connected, the time spent reconnecting doesn't count
+    pub(crate) fn reset(&mut self) {
+        self.last_recv = std::time::Instant::now();
+        self.last_ping = self.last_recv;
+    }
+
+    pub(crate) fn on_recv(&mut self) {
+        self.last_recv = std::time::Instant::now();
+    }
+
+    pub(crate) fn on_ping(&mut self) {
+        self.last_ping = std::time::Instant::now();
+    }
+
+    pub(crate) fn needs_ping(&self) -> bool {
+        self.cfg.is_some_and(|cfg| {
+            self.last_recv.elapsed() >= cfg.interval && self.last_ping.elapsed() >= cfg.interval
+        })
+    }
+
+    pub(crate) fn is_dead(&self) -> bool {
+        self.cfg
+            .is_some_and(|cfg| self.last_recv.elapsed() >= cfg.timeout)
+    }
+
+    pub(crate) fn silence(&self) -> std::time::Duration {
+        self.last_recv.elapsed()
+    }
+
+    // How long the proxy can sleep before it has to ping or to give up on the peer, None without a config
+    pub(crate) fn until_next_check(&self) -> Option<std::time::Duration> {
+        let cfg = self.cfg?;
+        let ping = self
+            .last_recv
+            .max(self.last_ping)
+            .checked_add(cfg.interval)?;
+        let dead = self.last_recv.checked_add(cfg.timeout)?;
+
+        Some(
+            ping.min(dead)
+                .saturating_duration_since(std::time::Instant::now()),
+        )
+    }
+}
@@ -1,6 +1,29 @@
 #[derive(PartialEq, Debug)]
 pub enum ProxyMessage<T: crate::Message> {
     Forward(T),
-    ConnectionResetError,
+    // Sent with ProxyController::request on the other side, answer it with ProxyController::respond
+    Request { id: u64, msg: T },
+    // Once the handshake is done, every time the proxy connects
+    Connected { addr: std::net::SocketAddr },
+    // The connection is lost, the proxy reconnects if it has a reconnect policy and exits otherwise
+    Disconnected { reason: DisconnectReason },
+    // An attempt to connect failed, or the proxy can't run and exits
+    Error(super::ProxyError),
+    // Only with a reconnect policy, the attempt starts once the policy's delay is over
+    Reconnecting { attempt: u32 },
+    // Sent after Connected, when it ends a reconnection
+    Reconnected,
+    // The policy's limits were reached, the proxy exits
+    GaveUp,
     Exit,
 }
+
+#[derive(PartialEq, Debug)]
+pub enum DisconnectReason {
+    // The other side has closed the connection, it sent a close frame or shut the stream down
+    Closed,
+    // The stream broke (ProxyError::io_kind says how), or the other side sent something invalid
+    Error(super::ProxyError),
+    // Nothing was received for the timeout of the heartbeat, the other side froze or the network is down
+    TimedOut,
+}
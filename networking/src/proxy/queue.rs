@@ -0,0 +1,162 @@
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverflowPolicy {
+    // ProxyController::send waits for the proxy to make some room
+    Block,
+    // The oldest queued message is dropped to make room for the new one
+    DropOldest,
+    // The new message is dropped, send still returns Ok
+    DropNewest,
+    // send returns SendError::Full, like try_send
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QueueConfig {
+    // How many messages can wait for the proxy to send them, at least 1 or the proxy exits with ProxyError::Config
+    pub capacity: usize,
+    // What ProxyController::send does when the queue is full, try_send always returns SendError::Full
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            overflow: OverflowPolicy::Block,
+        }
+    }
+}
+
+/// The message is given back, like [`std::sync::mpsc::SendError`]
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum SendError<T> {
+    #[error("The proxy's queue is full")]
+    Full(T),
+    #[error("The proxy has exited")]
+    Disconnected(T),
+    #[error("There is no such channel in the proxy's ChannelConfig")]
+    UnknownChannel(T),
+}
+
+impl<T> SendError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            SendError::Full(t) | SendError::Disconnected(t) | SendError::UnknownChannel(t) => t,
+        }
+    }
+
+    pub(crate) fn map<U>(self, f: impl FnOnce(T) -> U) -> SendError<U> {
+        match self {
+            SendError::Full(t) => SendError::Full(f(t)),
+            SendError::Disconnected(t) => SendError::Disconnected(f(t)),
+            SendError::UnknownChannel(t) => SendError::UnknownChannel(f(t)),
+        }
+    }
+}
+
+struct State<T> {
+    messages: std::collections::VecDeque<T>,
+    // Set when either side is gone
+    closed: bool,
+    // By DropOldest and DropNewest
+    dropped: u64,
+}
+
+// The messages from the controller to the proxy
+pub(crate) struct Queue<T> {
+    cfg: QueueConfig,
+    state: std::sync::Mutex<State<T>>,
+    not_full: std::sync::Condvar,
+}
+
+impl<T> Queue<T> {
+    pub(crate) fn new(cfg: QueueConfig) -> Self {
+        Self {
+            cfg,
+            state: std::sync::Mutex::new(State {
+                messages: std::collections::VecDeque::new(),
+                closed: false,
+                dropped: 0,
+            }),
+            not_full: std::sync::Condvar::new(),
+        }
+    }
+
+    // Blocks only with OverflowPolicy::Block
+    pub(crate) fn push(&self, t: T) -> Result<(), SendError<T>> {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if state.closed {
+                return Err(SendError::Disconnected(t));
+            }
+            if state.messages.len() < self.cfg.capacity {
+                state.messages.push_back(t);
+                return Ok(());
+            }
+
+            match self.cfg.overflow {
+                OverflowPolicy::Block => state = self.not_full.wait(state).unwrap(),
+                OverflowPolicy::DropOldest => {
+                    state.dropped += 1;
+                    state.messages.pop_front();
+                    state.messages.push_back(t);
+                    return Ok(());
+                }
+                OverflowPolicy::DropNewest => {
+                    state.dropped += 1;
+                    return Ok(());
+                }
+                OverflowPolicy::Error => return Err(SendError::Full(t)),
+            }
+        }
+    }
+
+    pub(crate) fn try_push(&self, t: T) -> Result<(), SendError<T>> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.closed {
+            return Err(SendError::Disconnected(t));
+        }
+        if state.messages.len() >= self.cfg.capacity {
+            return Err(SendError::Full(t));
+        }
+        state.messages.push_back(t);
+        Ok(())
+    }
+
+    // Disconnected once it's closed and empty, so nothing that was sent before is lost
+    pub(crate) fn try_pop(&self) -> Result<T, std::sync::mpsc::TryRecvError> {
+        use std::sync::mpsc::TryRecvError;
+
+        let mut state = self.state.lock().unwrap();
+
+        match state.messages.pop_front() {
+            Some(t) => {
+                self.not_full.notify_one();
+                Ok(t)
+            }
+            None if state.closed => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        self.state.lock().unwrap().messages.clear();
+        self.not_full.notify_all();
+    }
+
+    // The blocked senders get their message back
+    pub(crate) fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_full.notify_all();
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.state.lock().unwrap().messages.len()
+    }
+
+    pub(crate) fn dropped(&self) -> u64 {
+        self.state.lock().unwrap().dropped
+    }
+}
@@ -0,0 +1,123 @@
+#[derive(Clone, Debug, PartialEq)]
+pub enum Backoff {
+    // Always waits the same time between two attempts
+    Fixed(std::time::Duration),
+    // Doubles the wait after every failed attempt, up to max
+    // jitter is the fraction of the wait that is random (0. to 1.), so proxies that lost the same server don't all come back at once
+    Exponential {
+        initial: std::time::Duration,
+        max: std::time::Duration,
+        jitter: f64,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReconnectPolicy {
+    pub backoff: Backoff,
+    // The proxy gives up after that many failed attempts in a row, None to never give up
+    pub max_attempts: Option<u32>,
+    // The proxy gives up once it has been disconnected for that long, None to never give up
+    pub give_up_after: Option<std::time::Duration>,
+    // The same seed gives the same jitter for the same attempt, None for a random one
+    pub seed: Option<u64>,
+}
+
+impl ReconnectPolicy {
+    /// How long the proxy waits before the given attempt, the first one being 1
+    ///
+    /// The jitter makes it random, but it's never more than the delay without it.
+    /// With a seed, it only depends on the seed and the attempt, the rng of the calling thread is left alone
+    pub fn delay(&self, attempt: u32) -> std::time::Duration {
+        match self.backoff {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential {
+                initial,
+                max,
+                jitter,
+            } => {
+                let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+                let delay = initial.saturating_mul(factor).min(max);
+
+                let fraction = match self.seed {
+                    Some(seed) => unit(splitmix64(seed.wrapping_add(attempt as u64))),
+                    None => random::get(0., 1.),
+                };
+                delay.mul_f64(1. - jitter.clamp(0., 1.) * fraction)
+            }
+        }
+    }
+}
+
+// One step of splitmix64, the seeds of two attempts are next to each other but their outputs are not
+fn splitmix64(x: u64) -> u64 {
+    let x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+// The 53 high bits as a float from 0. to 1. (excluded)
+fn unit(bits: u64) -> f64 {
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            backoff: Backoff::Exponential {
+                initial: std::time::Duration::from_millis(100),
+                max: std::time::Duration::from_secs(10),
+                jitter: 0.5,
+            },
+            max_attempts: None,
+            give_up_after: None,
+            seed: None,
+        }
+    }
+}
+
+// The attempts since the connection was lost
+pub(crate) struct Reconnection {
+    policy: ReconnectPolicy,
+    attempt: u32,
+    since: std::time::Instant,
+}
+
+impl Reconnection {
+    pub(crate) fn new(policy: ReconnectPolicy) -> Self {
+        Self {
+            policy,
+            attempt: 0,
+            since: std::time::Instant::now(),
+        }
+    }
+
+    pub(crate) fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    // Starts the next attempt and gives how long to wait before it, None once the policy gives up
+    pub(crate) fn next_attempt(&mut self) -> Option<std::time::Duration> {
+        if self
+            .policy
+            .max_attempts
+            .is_some_and(|max| self.attempt >= max)
+        {
+            return None;
+        }
+
+        let delay = self.policy.delay(self.attempt + 1);
+
+        // No point in waiting if the attempt would be too late anyway
+        if self
+            .policy
+            .give_up_after
+            .is_some_and(|give_up_after| self.since.elapsed() + delay > give_up_after)
+        {
+            return None;
+        }
+
+        self.attempt += 1;
+        Some(delay)
+    }
+}
@@ -0,0 +1,154 @@
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum RpcError {
+    #[error("The response did not come in time")]
+    Timeout,
+    // The connection was lost or the proxy has exited, the request might not have been received
+    #[error("The proxy was disconnected before the response came")]
+    Disconnected,
+}
+
+// What the controller queues for the proxy, the kind of frame it's sent in
+// The requests and responses are sent on channel 0
+#[derive(Debug)]
+pub(crate) enum Outgoing<T> {
+    Message(u8, T),
+    Request(u64, T),
+    Response(u64, T),
+}
+
+impl<T> Outgoing<T> {
+    // The kind, channel and request id of the frame, see Socket::queue_data
+    pub(crate) fn into_frame(self) -> (crate::socket::FrameKind, u8, u64, T) {
+        use crate::socket::FrameKind;
+
+        match self {
+            Outgoing::Message(channel, t) => (FrameKind::Data, channel, 0, t),
+            Outgoing::Request(id, t) => (FrameKind::Request, 0, id, t),
+            Outgoing::Response(id, t) => (FrameKind::Response, 0, id, t),
+        }
+    }
+
+    pub(crate) fn into_inner(self) -> T {
+        match self {
+            Outgoing::Message(_, t) | Outgoing::Request(_, t) | Outgoing::Response(_, t) => t,
+        }
+    }
+}
+
+// The requests that are waiting for their response, shared by the controller, the proxy and the handles
+pub(crate) struct Pending<T> {
+    senders: std::sync::Mutex<std::collections::HashMap<u64, std::sync::mpsc::Sender<T>>>,
+    // 0 is for the frames that are not requests
+    next_id: std::sync::atomic::AtomicU64,
+}
+
+impl<T> Pending<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            senders: std::sync::Mutex::new(std::collections::HashMap::new()),
+            next_id: std::sync::atomic::AtomicU64::new(1),
+        }
+    }
+
+    // Registered before the request is queued, so the response can't come before it
+    pub(crate) fn insert(&self) -> (u64, std::sync::mpsc::Receiver<T>) {
+        let id = self
+            .next_id
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        self.senders.lock().unwrap().insert(id, sender);
+
+        (id, receiver)
+    }
+
+    pub(crate) fn remove(&self, id: u64) {
+        self.senders.lock().unwrap().remove(&id);
+    }
+
+    // Gives the response to its handle, false if nobody waits for it anymore
+    pub(crate) fn resolve(&self, id: u64, response: T) -> bool {
+        let Some(sender) = self.senders.lock().unwrap().remove(&id) else {
+            return false;
+        };
+        sender.send(response).is_ok()
+    }
+
+    // The handles that are still waiting get RpcError::Disconnected
+    pub(crate) fn clear(&self) {
+        self.senders.lock().unwrap().clear();
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.senders.lock().unwrap().len()
+    }
+}
+
+/// A request sent with [`super::ProxyController::request`], waiting for its response
+///
+/// Dropping it cancels the request, a response that comes after that is dropped by the proxy
+pub struct Request<T> {
+    id: u64,
+    receiver: std::sync::mpsc::Receiver<T>,
+    pending: std::sync::Arc<Pending<T>>,
+    deadline: std::time::Instant,
+}
+
+impl<T> Request<T> {
+    pub(crate) fn new(
+        id: u64,
+        receiver: std::sync::mpsc::Receiver<T>,
+        pending: std::sync::Arc<Pending<T>>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        Self {
+            id,
+            receiver,
+            pending,
+            deadline: std::time::Instant::now() + timeout,
+        }
+    }
+
+    /// The id that is sent in the frame header, the other side answers with it
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Blocks until the response comes or the request times out
+    pub fn wait(self) -> Result<T, RpcError> {
+        use std::sync::mpsc::RecvTimeoutError;
+
+        let timeout = self
+            .deadline
+            .saturating_duration_since(std::time::Instant::now());
+
+        match self.receiver.recv_timeout(timeout) {
+            Ok(response) => Ok(response),
+            Err(RecvTimeoutError::Timeout) => Err(RpcError::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Err(RpcError::Disconnected),
+        }
+    }
+
+    /// Ok(None) while the response has not come yet
+    pub fn try_wait(&mut self) -> Result<Option<T>, RpcError> {
+        use std::sync::mpsc::TryRecvError;
+
+        match self.receiver.try_recv() {
+            Ok(response) => Ok(Some(response)),
+            Err(TryRecvError::Empty) if std::time::Instant::now() >= self.deadline => {
+                Err(RpcError::Timeout)
+            }
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(RpcError::Disconnected),
+        }
+    }
+
+    /// The request might already have been sent, only its response is ignored
+    pub fn cancel(self) {}
+}
+
+impl<T> std::ops::Drop for Request<T> {
+    fn drop(&mut self) {
+        self.pending.remove(self.id);
+    }
+}
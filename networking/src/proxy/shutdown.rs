@@ -0,0 +1,27 @@
+/// What [`super::ProxyController::shutdown`] did with the messages that were waiting in the queue
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ShutdownSummary {
+    // Fully written to the connection before the close frame
+    pub flushed: usize,
+    // Still in the queue (or half written) once the timeout was reached or the connection was lost
+    pub dropped: usize,
+    // The other side closed the connection too before the timeout
+    pub acknowledged: bool,
+}
+
+// Set by the controller, the proxy looks at it once its loop has stopped
+#[derive(Default)]
+pub(crate) struct Shutdown {
+    deadline: std::sync::Mutex<Option<std::time::Instant>>,
+}
+
+impl Shutdown {
+    pub(crate) fn request(&self, timeout: std::time::Duration) {
+        *self.deadline.lock().unwrap() = Some(std::time::Instant::now() + timeout);
+    }
+
+    // None if the proxy stops for another reason (an error, or the reconnect policy gave up)
+    pub(crate) fn deadline(&self) -> Option<std::time::Instant> {
+        *self.deadline.lock().unwrap()
+    }
+}
@@ -0,0 +1,123 @@
+// How the proxy's tcp streams are set up, the ones it connects and the ones it is given (like the server's clients)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TcpConfig {
+    // For each of the resolved addresses, None waits for the os to give up (which can take minutes)
+    pub connect_timeout: Option<std::time::Duration>,
+    // Disables Nagle's algorithm, the proxy already writes its messages by batch
+    pub nodelay: bool,
+    // The idle time before the os starts probing the connection, None to not send keepalive probes
+    pub keepalive: Option<std::time::Duration>,
+    // The size of the os buffers (SO_SNDBUF and SO_RCVBUF), None to keep the os default
+    pub send_buffer_size: Option<usize>,
+    pub recv_buffer_size: Option<usize>,
+    // The connection is reset when the stream does not accept a single byte for that long, see networking::Socket::set_write_timeout
+    pub write_timeout: Option<std::time::Duration>,
+}
+
+impl TcpConfig {
+    /// Applies the options that live in the stream itself, and makes it non-blocking
+    pub fn apply(&self, stream: &std::net::TcpStream) -> std::io::Result<()> {
+        stream.set_nonblocking(true)?;
+        self.tune(socket2::SockRef::from(stream))
+    }
+
+    pub(crate) fn tune(&self, socket: socket2::SockRef<'_>) -> std::io::Result<()> {
+        socket.set_tcp_nodelay(self.nodelay)?;
+
+        match self.keepalive {
+            Some(time) => {
+                socket.set_tcp_keepalive(&socket2::TcpKeepalive::new().with_time(time))?
+            }
+            None => socket.set_keepalive(false)?,
+        }
+
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+
+        Ok(())
+    }
+
+    // Tries every address in order, the error is the one of the last address
+    pub(crate) fn connect(
+        &self,
+        addrs: &[std::net::SocketAddr],
+    ) -> Result<(std::net::TcpStream, std::net::SocketAddr), super::ProxyError> {
+        use std::net::TcpStream;
+
+        let mut error_opt = None;
+
+        for addr in addrs {
+            let res = match self.connect_timeout {
+                Some(timeout) => TcpStream::connect_timeout(addr, timeout),
+                None => TcpStream::connect(addr),
+            };
+
+            match res {
+                Ok(stream) => return Ok((stream, *addr)),
+                Err(e) => {
+                    debug!("Could not connect to {addr}: {e}");
+                    error_opt = Some(super::ProxyError::Connect {
+                        addr: *addr,
+                        kind: e.kind(),
+                    })
+                }
+            }
+        }
+
+        Err(error_opt.unwrap_or(super::ProxyError::Config(String::from(
+            "No address to connect to",
+        ))))
+    }
+
+    // Same as TcpConfig::connect, for the async proxy
+    #[cfg(feature = "async")]
+    pub(crate) async fn connect_async(
+        &self,
+        addrs: &[std::net::SocketAddr],
+    ) -> Result<(tokio::net::TcpStream, std::net::SocketAddr), super::ProxyError> {
+        use tokio::net::TcpStream;
+
+        let mut error_opt = None;
+
+        for addr in addrs {
+            let res = match self.connect_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, TcpStream::connect(addr))
+                    .await
+                    .unwrap_or_else(|_| Err(std::io::Error::from(std::io::ErrorKind::TimedOut))),
+                None => TcpStream::connect(addr).await,
+            };
+
+            match res {
+                Ok(stream) => return Ok((stream, *addr)),
+                Err(e) => {
+                    debug!("Could not connect to {addr}: {e}");
+                    error_opt = Some(super::ProxyError::Connect {
+                        addr: *addr,
+                        kind: e.kind(),
+                    })
+                }
+            }
+        }
+
+        Err(error_opt.unwrap_or(super::ProxyError::Config(String::from(
+            "No address to connect to",
+        ))))
+    }
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Some(std::time::Duration::from_secs(10)),
+            nodelay: true,
+            keepalive: None,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            write_timeout: None,
+        }
+    }
+}
@@ -0,0 +1,338 @@
+mod client_id;
+mod command;
+mod config;
+mod controller;
+mod error;
+mod event;
+
+pub use client_id::ClientId;
+pub use config::ServerConfig;
+pub use controller::ServerController;
+pub use error::ServerError;
+pub use event::ServerEvent;
+
+pub type StatsMap<R, W> = std::collections::HashMap<ClientId, crate::NetworkStats<R, W>>;
+
+// The poll tokens, the listener is registered as LISTENER_TOKEN, the controller and the clients wake the server with WAKER_TOKEN
+const LISTENER_TOKEN: mio::Token = mio::Token(0);
+const WAKER_TOKEN: mio::Token = mio::Token(1);
+
+// How long the clients have to flush their queue once the server stops
+const CLIENT_SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(100);
+
+// The stats of the clients change without waking the server up, they are gathered at least that often
+const STATS_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+
+// Accepts clients and spawns a Proxy for each of them
+// R is what the clients send, W is what the server sends
+pub struct Server<R: crate::Message, W: crate::Message, C: crate::Codec = crate::codec::Bincode> {
+    cfg: config::ServerConfig,
+    listener: mio::net::TcpListener,
+    poll: mio::Poll,
+    // Given to the controller and to the proxy of every client
+    waker: std::sync::Arc<mio::Waker>,
+    clients: std::collections::HashMap<ClientId, crate::proxy::ProxyController<R, W, C>>,
+    next_id: u64,
+    channel: threading::Channel<command::Command<W>, event::ServerEvent<R>>,
+    running: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    stats: triple_buffer::Input<StatsMap<R, W>>,
+}
+
+impl<R: crate::Message + 'static, W: crate::Message + 'static, C: crate::Codec> Server<R, W, C> {
+    pub fn start_new(
+        cfg: config::ServerConfig,
+    ) -> Result<controller::ServerController<R, W, C>, error::ServerError> {
+        use {
+            std::{
+                net::TcpListener,
+                sync::{atomic::AtomicBool, Arc},
+                thread,
+            },
+            threading::Channel,
+            triple_buffer::TripleBuffer,
+        };
+
+        #[cfg(feature = "tls")]
+        if let Some(crate::tls::TlsConfig::Client { .. }) = cfg.tls {
+            return Err(error::ServerError::Config(String::from(
+                "The server's tls config has to be a TlsConfig::Server",
+            )));
+        }
+
+        let listener = TcpListener::bind(cfg.addr).map_err(error::ServerError::Bind)?;
+
+        listener.set_nonblocking(true).map_err(|e| {
+            error::ServerError::Config(format!(
+                "Could not set listener to non-blocking due to: {e}"
+            ))
+        })?;
+
+        let local_addr = listener.local_addr().map_err(error::ServerError::Bind)?;
+
+        let mut listener = mio::net::TcpListener::from_std(listener);
+        let poll = mio::Poll::new()
+            .map_err(|e| error::ServerError::Config(format!("Could not create the poll: {e}")))?;
+        poll.registry()
+            .register(&mut listener, LISTENER_TOKEN, mio::Interest::READABLE)
+            .map_err(|e| {
+                error::ServerError::Config(format!(
+                    "Could not register the listener in the poll: {e}"
+                ))
+            })?;
+        let waker = Arc::new(mio::Waker::new(poll.registry(), WAKER_TOKEN).map_err(|e| {
+            error::ServerError::Config(format!("Could not create the poll's waker: {e}"))
+        })?);
+
+        let (server_channel, main_channel) =
+            Channel::<event::ServerEvent<R>, command::Command<W>>::new_pair();
+
+        let running = Arc::new(AtomicBool::new(true));
+
+        let (stats_in, stats_out) = TripleBuffer::new(&StatsMap::new()).split();
+
+        let server = Server::<R, W, C> {
+            cfg,
+            listener,
+            poll,
+            waker: waker.clone(),
+            clients: std::collections::HashMap::new(),
+            next_id: 0,
+            channel: server_channel,
+            running: running.clone(),
+            stats: stats_in,
+        };
+
+        let thread_handle = thread::spawn(move || server.run());
+
+        Ok(controller::ServerController::new(
+            stats_out,
+            main_channel,
+            running,
+            waker,
+            local_addr,
+            thread_handle,
+        ))
+    }
+
+    fn set_running(&mut self, val: bool) {
+        use std::sync::atomic::Ordering;
+        self.running.store(val, Ordering::Release)
+    }
+
+    fn run(mut self) {
+        use std::sync::atomic::Ordering;
+
+        let mut events = mio::Events::with_capacity(16);
+        let tick = std::time::Duration::from_secs_f64(1. / self.cfg.run_tps.max(1) as f64);
+
+        while self.running.load(Ordering::Acquire) {
+            let loop_start = std::time::Instant::now();
+
+            if let Err(e) = self.accept_clients() {
+                error!("{e}");
+                break;
+            }
+
+            if let Err(e) = self.handle_local() {
+                error!("{e}");
+                break;
+            }
+
+            if let Err(e) = self.handle_clients() {
+                error!("{e}");
+                break;
+            }
+
+            self.stats.write(
+                self.clients
+                    .iter_mut()
+                    .map(|(id, client)| (*id, client.stats().clone()))
+                    .collect(),
+            );
+
+            // Only there to limit the cpu usage of a very busy server
+            spin_sleep::sleep(tick.saturating_sub(loop_start.elapsed()));
+
+            // The events are edge-triggered, everything was accepted and read above
+            match self.poll.poll(&mut events, Some(STATS_DELAY)) {
+                Ok(()) => (),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(e) => {
+                    error!("Could not poll: {e}");
+                    break;
+                }
+            }
+        }
+
+        self.set_running(false);
+
+        // They all flush and close their connection at the same time, a stuck client only holds back the ones after it by what's left of the timeout
+        for client in self.clients.values() {
+            client.request_shutdown(CLIENT_SHUTDOWN_TIMEOUT);
+        }
+        for (id, mut client) in self.clients.drain() {
+            let summary = client.wait_shutdown();
+            debug!("Client {id} has stopped: {summary:?}");
+        }
+
+        debug!("Server on ({}) has exited", self.cfg.addr);
+    }
+
+    fn accept_clients(&mut self) -> Result<(), error::ServerError> {
+        loop {
+            let (stream, addr) = match self.listener.accept() {
+                // Already non-blocking, like the proxy wants it
+                Ok((stream, addr)) => (std::net::TcpStream::from(stream), addr),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => {
+                    // A failed accept only concerns that client
+                    warn!("Could not accept client: {e}");
+                    return Ok(());
+                }
+            };
+
+            let id = ClientId(self.next_id);
+            self.next_id += 1;
+
+            debug!("Accepted client {id} from {addr}");
+
+            self.clients.insert(
+                id,
+                crate::Proxy::<R, W, C>::start_notifying(
+                    self.cfg.client_cfg(addr),
+                    Some(stream),
+                    Some(self.waker.clone()),
+                ),
+            );
+
+            self.channel
+                .send(event::ServerEvent::Connected(id))
+                .map_err(|e| error::ServerError::ChannelSend(e.to_string()))?;
+        }
+    }
+
+    /// here you receive the commands sent by the controller
+    fn handle_local(&mut self) -> Result<(), error::ServerError> {
+        use std::sync::mpsc::TryRecvError;
+
+        loop {
+            match self.channel.try_recv() {
+                Ok(command::Command::SendTo(id, msg)) => {
+                    let Some(client) = self.clients.get(&id) else {
+                        warn!("Could not send {msg:?} to client {id}, it's not connected");
+                        continue;
+                    };
+                    Self::send_to_client(id, client, 0, msg);
+                }
+                Ok(command::Command::SendOn(id, channel, msg)) => {
+                    let Some(client) = self.clients.get(&id) else {
+                        warn!("Could not send {msg:?} to client {id}, it's not connected");
+                        continue;
+                    };
+                    Self::send_to_client(id, client, channel, msg);
+                }
+                Ok(command::Command::Broadcast(msg)) => {
+                    for (id, client) in self.clients.iter() {
+                        Self::send_to_client(*id, client, 0, msg.clone());
+                    }
+                }
+                Ok(command::Command::Respond(id, request_id, msg)) => {
+                    let Some(client) = self.clients.get(&id) else {
+                        warn!("Could not answer request {request_id} of client {id}, it's not connected");
+                        continue;
+                    };
+                    match client.try_respond(request_id, msg) {
+                        Ok(()) => (),
+                        Err(crate::proxy::SendError::Full(_)) => {
+                            warn!("Dropped the response to request {request_id} of client {id}, its queue is full")
+                        }
+                        Err(crate::proxy::SendError::Disconnected(_))
+                        | Err(crate::proxy::SendError::UnknownChannel(_)) => (),
+                    }
+                }
+                Err(TryRecvError::Empty) => return Ok(()),
+                Err(TryRecvError::Disconnected) => {
+                    // The controller has been dropped
+                    self.set_running(false);
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    // A slow client can't stall the others, what doesn't fit in its queue is dropped
+    fn send_to_client(
+        id: client_id::ClientId,
+        client: &crate::proxy::ProxyController<R, W, C>,
+        channel: u8,
+        msg: W,
+    ) {
+        match client.try_send_on(channel, msg) {
+            Ok(()) => (),
+            Err(crate::proxy::SendError::Full(msg)) => {
+                warn!("Dropped {msg:?} for client {id}, its queue is full")
+            }
+            Err(crate::proxy::SendError::UnknownChannel(msg)) => {
+                warn!("Dropped {msg:?} for client {id}, there is no channel {channel}")
+            }
+            // The proxy has exited, handle_clients will clean it up
+            Err(crate::proxy::SendError::Disconnected(_)) => (),
+        }
+    }
+
+    /// here you receive the messages forwarded by the client proxies
+    fn handle_clients(&mut self) -> Result<(), error::ServerError> {
+        use {crate::proxy::ProxyMessage, std::sync::mpsc::TryRecvError};
+
+        let mut disconnected = Vec::new();
+
+        for (id, client) in self.clients.iter() {
+            loop {
+                match client.try_recv() {
+                    Ok(ProxyMessage::Forward(msg)) => self
+                        .channel
+                        .send(event::ServerEvent::Message(*id, msg))
+                        .map_err(|e| error::ServerError::ChannelSend(e.to_string()))?,
+                    Ok(ProxyMessage::Request {
+                        id: request_id,
+                        msg,
+                    }) => self
+                        .channel
+                        .send(event::ServerEvent::Request(*id, request_id, msg))
+                        .map_err(|e| error::ServerError::ChannelSend(e.to_string()))?,
+                    // The proxy exits right after an error, the exit message is enough
+                    Ok(ProxyMessage::Connected { .. }) | Ok(ProxyMessage::Error(_)) => (),
+                    // Never sent, the proxies of the clients have no reconnect policy
+                    Ok(ProxyMessage::Reconnecting { .. })
+                    | Ok(ProxyMessage::Reconnected)
+                    | Ok(ProxyMessage::GaveUp) => (),
+                    Ok(ProxyMessage::Disconnected { .. }) | Ok(ProxyMessage::Exit) => {
+                        disconnected.push(*id);
+                        break;
+                    }
+                    Err(TryRecvError::Empty) => {
+                        if !client.is_running() {
+                            disconnected.push(*id);
+                        }
+                        break;
+                    }
+                    Err(TryRecvError::Disconnected) => {
+                        disconnected.push(*id);
+                        break;
+                    }
+                }
+            }
+        }
+
+        for id in disconnected {
+            self.clients.remove(&id);
+            debug!("Client {id} disconnected");
+
+            self.channel
+                .send(event::ServerEvent::Disconnected(id))
+                .map_err(|e| error::ServerError::ChannelSend(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+}
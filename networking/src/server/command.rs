@@ -0,0 +1,9 @@
+// What the controller asks the server thread to do
+#[derive(PartialEq, Debug)]
+pub enum Command<T: crate::Message> {
+    SendTo(super::ClientId, T),
+    SendOn(super::ClientId, u8, T),
+    Broadcast(T),
+    // Answers a ServerEvent::Request
+    Respond(super::ClientId, u64, T),
+}
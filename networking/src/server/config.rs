@@ -0,0 +1,42 @@
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    pub addr: std::net::SocketAddr,
+    // The most loops per second the server can do, it sleeps until a client or the controller has something for it
+    pub run_tps: u64,
+    // Applied to the proxy of every accepted client
+    pub stat_cfg: crate::stats::StatConfig,
+    pub max_frame_size: u64,
+    pub compression: Option<crate::Compression>,
+    // The channels that ServerController::send_on can use, see networking::socket::ChannelConfig
+    pub channels: crate::socket::ChannelConfig,
+    // Applied to the stream of every accepted client, its connect_timeout is not used, see networking::proxy::TcpConfig
+    pub tcp: crate::proxy::TcpConfig,
+    // A frozen client is removed once the timeout is reached, see networking::proxy::HeartbeatConfig
+    pub heartbeat: Option<crate::proxy::HeartbeatConfig>,
+    // Has to be a TlsConfig::Server, each client gets its own session
+    pub tls: Option<crate::tls::TlsConfig>,
+}
+
+impl ServerConfig {
+    /// Builds the config used by the proxy of a freshly accepted client
+    pub(crate) fn client_cfg(&self, addr: std::net::SocketAddr) -> crate::proxy::ProxyConfig {
+        crate::proxy::ProxyConfig {
+            addr: addr.into(),
+            // run_tps caps the server's loop, its proxies forward right away
+            run_tps: None,
+            stat_cfg: self.stat_cfg,
+            // The client is the one that connects, there is nothing to keep or reconnect to
+            keep_msg_while_disconnected: false,
+            reconnect: None,
+            heartbeat: self.heartbeat,
+            // The server never blocks on a slow client, see Server::handle_local
+            queue: Default::default(),
+            batch: Default::default(),
+            max_frame_size: self.max_frame_size,
+            compression: self.compression,
+            channels: self.channels.clone(),
+            tcp: self.tcp,
+            tls: self.tls.clone(),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+// The codec is only there so the right Server can be inferred from this type
+pub struct ServerController<
+    R: crate::Message,
+    W: crate::Message,
+    C: crate::Codec = crate::codec::Bincode,
+> {
+    stats: triple_buffer::Output<super::StatsMap<R, W>>,
+    channel: threading::Channel<super::ServerEvent<R>, super::command::Command<W>>,
+    running: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    // Wakes the server's thread up
+    waker: std::sync::Arc<mio::Waker>,
+    local_addr: std::net::SocketAddr,
+    thread_handle: std::thread::JoinHandle<()>,
+    codec: std::marker::PhantomData<C>,
+}
+
+impl<R: crate::Message, W: crate::Message, C: crate::Codec> ServerController<R, W, C> {
+    pub(crate) fn new(
+        stats: triple_buffer::Output<super::StatsMap<R, W>>,
+        channel: threading::Channel<super::ServerEvent<R>, super::command::Command<W>>,
+        running: std::sync::Arc<std::sync::atomic::AtomicBool>,
+        waker: std::sync::Arc<mio::Waker>,
+        local_addr: std::net::SocketAddr,
+        thread_handle: std::thread::JoinHandle<()>,
+    ) -> ServerController<R, W, C> {
+        ServerController {
+            stats,
+            channel,
+            running,
+            waker,
+            local_addr,
+            thread_handle,
+            codec: std::marker::PhantomData,
+        }
+    }
+
+    /// Queues a message for a single client, messages for unknown clients are dropped
+    pub fn send_to(&self, id: super::ClientId, msg: W) -> Result<(), super::ServerError> {
+        self.send(super::command::Command::SendTo(id, msg))
+    }
+
+    /// Same as [`ServerController::send_to`], on one of the channels of the config
+    pub fn send_on(
+        &self,
+        id: super::ClientId,
+        channel: u8,
+        msg: W,
+    ) -> Result<(), super::ServerError> {
+        self.send(super::command::Command::SendOn(id, channel, msg))
+    }
+
+    /// Queues a message for every connected client
+    pub fn broadcast(&self, msg: W) -> Result<(), super::ServerError> {
+        self.send(super::command::Command::Broadcast(msg))
+    }
+
+    /// Answers a [`super::ServerEvent::Request`], dropped if the client has left since
+    pub fn respond(
+        &self,
+        id: super::ClientId,
+        request_id: u64,
+        msg: W,
+    ) -> Result<(), super::ServerError> {
+        self.send(super::command::Command::Respond(id, request_id, msg))
+    }
+
+    // The server is woken up right away to handle it
+    fn send(&self, command: super::command::Command<W>) -> Result<(), super::ServerError> {
+        self.channel
+            .send(command)
+            .map_err(|e| super::ServerError::ChannelSend(e.to_string()))?;
+        // Only fails if the server has exited
+        let _ = self.waker.wake();
+        Ok(())
+    }
+
+    pub fn recv(&self) -> Result<super::ServerEvent<R>, std::sync::mpsc::RecvError> {
+        self.channel.recv()
+    }
+    pub fn try_recv(&self) -> Result<super::ServerEvent<R>, std::sync::mpsc::TryRecvError> {
+        self.channel.try_recv()
+    }
+    pub fn recv_timeout(
+        &self,
+        timeout: std::time::Duration,
+    ) -> Result<super::ServerEvent<R>, std::sync::mpsc::RecvTimeoutError> {
+        self.channel.recv_timeout(timeout)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The address the listener is bound to, usefull when binding on port 0
+    pub fn local_addr(&self) -> std::net::SocketAddr {
+        self.local_addr
+    }
+
+    // Needs mut because it's updating before returning the data
+    pub fn stats(&mut self) -> &super::StatsMap<R, W> {
+        self.stats.read()
+    }
+
+    pub fn thread_handle(&self) -> &std::thread::JoinHandle<()> {
+        &self.thread_handle
+    }
+}
+
+impl<R: crate::Message, W: crate::Message, C: crate::Codec> std::ops::Drop
+    for ServerController<R, W, C>
+{
+    // The server would only notice that the channel is closed on its next wake up
+    fn drop(&mut self) {
+        self.running
+            .store(false, std::sync::atomic::Ordering::Release);
+        let _ = self.waker.wake();
+    }
+}
@@ -0,0 +1,10 @@
+#[derive(thiserror::Error, Debug)]
+pub enum ServerError {
+    #[error("Could not bind the listener: {0}")]
+    Bind(std::io::Error),
+    #[error("Config error: {0}")]
+    Config(String),
+
+    #[error("{0}")]
+    ChannelSend(String),
+}
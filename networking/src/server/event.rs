@@ -0,0 +1,8 @@
+#[derive(PartialEq, Debug)]
+pub enum ServerEvent<T: crate::Message> {
+    Connected(super::ClientId),
+    Message(super::ClientId, T),
+    // Answer it with ServerController::respond and the same id
+    Request(super::ClientId, u64, T),
+    Disconnected(super::ClientId),
+}
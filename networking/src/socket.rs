@@ -1,4 +1,28 @@
-pub const HEADER_SIZE: u64 = std::mem::size_of::<Header>() as u64;
+pub(crate) mod channel;
+pub(crate) mod frame;
+mod handshake;
+mod stream;
+
+pub use channel::{ChannelConfig, DEFAULT_CHUNK_SIZE};
+pub use handshake::{Handshake, HandshakeError, HANDSHAKE_SIZE, PROTOCOL_VERSION};
+
+// How much is read from the stream at once
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+// How much of the queued frames is given to the stream at once, a frame queued on a channel with a higher priority
+// only waits for that much to be written, see Socket::flush
+const WRITE_CHUNK_SIZE: usize = 64 * 1024;
+
+// The most frames given to the stream in one write
+const MAX_WRITE_SLICES: usize = 64;
+
+/// The biggest payload a socket accepts by default, see [`Socket::set_max_frame_size`]
+pub const DEFAULT_MAX_FRAME_SIZE: u64 = 64 * 1024 * 1024;
+
+// bincode writes the fields back to back: the size as an u64, the kind's variant index as an u32, the flag as an u8,
+// the request id as an u64 and the channel as an u8
+// The size_of::<Header>() can't be used anymore as it counts the padding
+pub const HEADER_SIZE: u64 = 8 + 4 + 1 + 8 + 1;
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
 // You can modify this struct to store whatever data you want, just be sure that your data's size can't change as it
@@ -7,14 +31,74 @@ pub const HEADER_SIZE: u64 = std::mem::size_of::<Header>() as u64;
 // the field changes size (therefore the Header struct too), which makes the HEADER_SIZE constant unrepresntative of the real Header size)
 pub struct Header {
     pub size: u64,
+    pub kind: FrameKind,
+    pub compressed: bool,
+    // Links a response to its request, 0 for the other frames
+    pub request_id: u64,
+    // The logical channel of the message, see ChannelConfig
+    pub channel: u8,
+
+    // Not sent, it's filled by the socket for the stats, equal to size when the payload is not compressed
+    #[serde(skip)]
+    pub uncompressed_size: u64,
+}
+
+/// What a frame is carrying
+///
+/// Data, Request and Response frames have a payload, the others are used by the crate for keepalive and graceful close
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Data,
+    Ping,
+    Pong,
+    Close,
+    // Data that expects a Response with the same request id, see Socket::send_request
+    Request,
+    Response,
+    // A piece of a big message, the last piece is sent as the message's own kind, see ChannelConfig
+    Chunk,
+}
+
+/// A received frame, see [`Socket::try_recv_frame`]
+#[derive(Debug, PartialEq)]
+pub enum Frame<T> {
+    // Also the payload of requests and responses, the header has their kind and id
+    Data(T),
+    Ping,
+    Pong,
 }
 
 // I don't like how streams work so i'll make a simple socket-like, packet-based struct wrapper
-pub struct Socket<R: crate::Message, W: crate::Message> {
-    stream: std::net::TcpStream,
+pub struct Socket<R: crate::Message, W: crate::Message, C: crate::Codec = crate::codec::Bincode> {
+    stream: stream::Stream,
     read_type: std::marker::PhantomData<R>,
     write_type: std::marker::PhantomData<W>,
-    last_header: Option<Header>,
+    codec: std::marker::PhantomData<C>,
+
+    // Bytes received but not parsed yet are in recv_buffer[recv_start..]
+    // Frames are parsed in place, the buffer is only compacted before reading more
+    recv_buffer: Vec<u8>,
+    recv_start: usize,
+
+    // The frames that are being written, in order, the stream already took outgoing[0][outgoing_start..]
+    // They are written as they are with write_vectored, nothing is copied
+    outgoing: std::collections::VecDeque<Vec<u8>>,
+    outgoing_start: usize,
+
+    // The messages wait there by channel, the most urgent ones are moved to outgoing when it's empty
+    scheduler: channel::Scheduler,
+    chunk_size: usize,
+    // The chunks of the messages that are not fully received yet
+    reassembly: channel::Reassembly,
+
+    max_frame_size: u64,
+    compression: Option<crate::compression::Compression>,
+    // The compressions the other side said it reads in its handshake
+    peer_compressions: u32,
+
+    // See Socket::set_write_timeout, the stream has not accepted anything since write_stalled_since
+    write_timeout: Option<std::time::Duration>,
+    write_stalled_since: Option<std::time::Instant>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -22,14 +106,25 @@ pub enum SocketError {
     #[error("This should not be used outside tests")]
     TestError,
     #[error("Error when serializing: {0}")]
-    Serialization(bincode::Error),
+    Serialization(crate::codec::CodecError),
     #[error("Error when deserializing: {0}")]
-    Deserialization(bincode::Error),
+    Deserialization(crate::codec::CodecError),
+    #[error("Error when decompressing: {0}")]
+    Decompression(crate::codec::CodecError),
     #[error("Error when writing to stream: {0}")]
     StreamWrite(std::io::Error),
     #[error("Error when reading the stream: {0}")]
     StreamRead(std::io::Error),
 
+    #[error("The frame's payload is {size} bytes, the maximum is {max}")]
+    FrameTooLarge { size: u64, max: u64 },
+
+    #[error("There is no channel {0}, see Socket::set_channels")]
+    UnknownChannel(u8),
+
+    #[error("Handshake failed: {0}")]
+    Handshake(#[from] HandshakeError),
+
     #[error("The other side has closed the communication")]
     Exited,
     // #[error("Error when peeking into stream: {0}")]
@@ -38,115 +133,469 @@ pub enum SocketError {
     // WouldBlock,
 }
 
+// Neither io errors nor codec errors can be compared, the io ones are compared by kind and the codec ones by message
+impl PartialEq for SocketError {
+    fn eq(&self, other: &Self) -> bool {
+        use SocketError::*;
+
+        match (self, other) {
+            (TestError, TestError) | (Exited, Exited) => true,
+            (Serialization(a), Serialization(b))
+            | (Deserialization(a), Deserialization(b))
+            | (Decompression(a), Decompression(b)) => a.to_string() == b.to_string(),
+            (StreamWrite(a), StreamWrite(b)) | (StreamRead(a), StreamRead(b)) => {
+                a.kind() == b.kind()
+            }
+            (
+                FrameTooLarge { size, max },
+                FrameTooLarge {
+                    size: other_size,
+                    max: other_max,
+                },
+            ) => size == other_size && max == other_max,
+            (UnknownChannel(a), UnknownChannel(b)) => a == b,
+            (Handshake(a), Handshake(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
 impl Header {
-    pub fn new(size: u64) -> Self {
-        Self { size }
+    pub fn new(kind: FrameKind, size: u64) -> Self {
+        Self {
+            size,
+            kind,
+            compressed: false,
+            request_id: 0,
+            channel: 0,
+            uncompressed_size: size,
+        }
     }
 }
 
-impl<R: crate::Message, W: crate::Message> Socket<R, W> {
+impl<R: crate::Message, W: crate::Message, C: crate::Codec> Socket<R, W, C> {
     pub fn new(stream: std::net::TcpStream) -> Self {
+        Self::with_stream(stream::Stream::Tcp(stream))
+    }
+
+    /// Same as [`Socket::new`], but everything goes through the given tls session
+    ///
+    /// The tls handshake is done by the first reads and writes, see [`crate::tls::TlsConfig`] to create the session
+    #[cfg(feature = "tls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+    pub fn new_tls(stream: std::net::TcpStream, connection: rustls::Connection) -> Self {
+        Self::with_stream(stream::Stream::tls(stream, connection))
+    }
+
+    fn with_stream(stream: stream::Stream) -> Self {
         Self {
             stream,
             read_type: std::marker::PhantomData,
             write_type: std::marker::PhantomData,
-            last_header: None,
+            codec: std::marker::PhantomData,
+            recv_buffer: Vec::new(),
+            recv_start: 0,
+            outgoing: std::collections::VecDeque::new(),
+            outgoing_start: 0,
+            scheduler: channel::Scheduler::new(&ChannelConfig::default()),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            reassembly: channel::Reassembly::default(),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            compression: None,
+            peer_compressions: 0,
+            write_timeout: None,
+            write_stalled_since: None,
         }
     }
+    /// Frames with a bigger payload are refused, when receiving the connection is closed
+    /// as the rest of the stream can't be trusted
+    pub fn set_max_frame_size(&mut self, max_frame_size: u64) {
+        self.max_frame_size = max_frame_size
+    }
+
+    pub fn max_frame_size(&self) -> u64 {
+        self.max_frame_size
+    }
+
+    /// Compresses the payload of the data frames sent from now on, None disables it
+    ///
+    /// Only once the handshake says that the other side reads it, see [`Socket::handshake`].
+    /// The received frames are decompressed no matter what is set here
+    pub fn set_compression(&mut self, compression: Option<crate::compression::Compression>) {
+        self.compression = compression
+    }
+
+    pub fn compression(&self) -> Option<crate::compression::Compression> {
+        self.compression
+    }
+
+    /// Makes [`Socket::flush`] fail with a TimedOut error once the stream has not accepted a single byte for that long
+    ///
+    /// Meant for non-blocking streams, where a peer that stopped reading would otherwise keep the frames queued forever.
+    /// None (the default) waits for as long as it takes
+    pub fn set_write_timeout(&mut self, timeout: Option<std::time::Duration>) {
+        self.write_timeout = timeout
+    }
+
+    pub fn write_timeout(&self) -> Option<std::time::Duration> {
+        self.write_timeout
+    }
+
+    /// When the queued frames time out if the stream still doesn't accept them, see [`Socket::set_write_timeout`]
+    pub fn write_deadline(&self) -> Option<std::time::Instant> {
+        Some(self.write_stalled_since? + self.write_timeout?)
+    }
+
+    /// Sets the channels that messages can be sent on, see [`ChannelConfig`]
+    ///
+    /// Meant to be called before anything is sent, the queued frames of the removed channels are dropped.
+    /// The received messages are put back together whatever the channels of the other side are,
+    /// the ones that are still in pieces can't add up to more than [`Socket::max_frame_size`].
+    /// The message that goes over gives a [`SocketError::FrameTooLarge`] and the rest of it is skipped
+    pub fn set_channels(&mut self, cfg: &ChannelConfig) {
+        self.scheduler.set_priorities(&cfg.priorities);
+        // An empty chunk would never end
+        self.chunk_size = cfg.chunk_size.max(1);
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.scheduler.channel_count()
+    }
+
     pub fn send(&mut self, message: W) -> Result<Header, SocketError> {
-        use std::io::Write as _;
+        self.send_data(FrameKind::Data, 0, 0, message)
+    }
+
+    /// Same as [`Socket::send`], on one of the channels given to [`Socket::set_channels`]
+    pub fn send_on(&mut self, channel: u8, message: W) -> Result<Header, SocketError> {
+        self.send_data(FrameKind::Data, channel, 0, message)
+    }
+
+    /// Sends a message that the other side should answer with [`Socket::send_response`]
+    ///
+    /// The id is yours to pick, the response comes with it in its header
+    pub fn send_request(&mut self, request_id: u64, message: W) -> Result<Header, SocketError> {
+        self.send_data(FrameKind::Request, 0, request_id, message)
+    }
+
+    /// Answers the request that came with this id
+    pub fn send_response(&mut self, request_id: u64, message: W) -> Result<Header, SocketError> {
+        self.send_data(FrameKind::Response, 0, request_id, message)
+    }
+
+    fn send_data(
+        &mut self,
+        kind: FrameKind,
+        channel: u8,
+        request_id: u64,
+        message: W,
+    ) -> Result<Header, SocketError> {
+        let header = self.queue_data(kind, channel, request_id, message)?;
 
-        let message_bytes = bincode::serialize(&message).map_err(SocketError::Serialization)?;
+        self.flush()?;
 
-        let header = Header::new(message_bytes.len() as u64);
+        Ok(header)
+    }
 
-        let header_bytes = bincode::serialize(&header).map_err(SocketError::Serialization)?;
+    /// Same as [`Socket::send`], but nothing is written until [`Socket::flush`] is called
+    ///
+    /// Used to batch messages, the queued frames are written together
+    pub fn queue(&mut self, message: W) -> Result<Header, SocketError> {
+        self.queue_data(FrameKind::Data, 0, 0, message)
+    }
 
-        // idk if panicking is a good idea
-        // assert_eq!(header_bytes.len(), HEADER_SIZE);
-        if header_bytes.len() as u64 != HEADER_SIZE {
-            return Err(SocketError::Serialization(Box::new(bincode::ErrorKind::Custom(format!("The length of the serialized header is not equal to the HEADER_SIZE constant ({HEADER_SIZE})"))),));
+    /// Same as [`Socket::queue`], on one of the channels given to [`Socket::set_channels`]
+    pub fn queue_on(&mut self, channel: u8, message: W) -> Result<Header, SocketError> {
+        self.queue_data(FrameKind::Data, channel, 0, message)
+    }
+
+    // Any frame with a payload, it waits for its turn in the scheduler
+    // The header that is given back is the one of the whole message, even if it was sent in chunks
+    pub(crate) fn queue_data(
+        &mut self,
+        kind: FrameKind,
+        channel: u8,
+        request_id: u64,
+        message: W,
+    ) -> Result<Header, SocketError> {
+        if channel as usize >= self.scheduler.channel_count() {
+            return Err(SocketError::UnknownChannel(channel));
         }
 
-        self.stream
-            .write_all(&header_bytes)
-            .map_err(SocketError::StreamWrite)?;
-        trace!("Sending {:?}:  {:?}", header, header_bytes);
+        let compression = self
+            .compression
+            .filter(|compression| compression.is_in(self.peer_compressions));
+        let (payload, uncompressed_size) = frame::encode_data::<W, C>(&message, compression)?;
+
+        // The receiver checks the whole message too
+        let size = uncompressed_size.unwrap_or(payload.len() as u64);
+        if size > self.max_frame_size {
+            return Err(SocketError::FrameTooLarge {
+                size,
+                max: self.max_frame_size,
+            });
+        }
 
-        self.stream
-            .write_all(&message_bytes)
-            .map_err(SocketError::StreamWrite)?;
-        trace!("Sending {:?}:  {:?}", message, message_bytes);
+        // Only the last piece has the message's kind, the receiver knows the message is complete with it
+        let mut pieces = payload.chunks(self.chunk_size).peekable();
+        let mut last = &payload[..];
+        while let Some(piece) = pieces.next() {
+            if pieces.peek().is_none() {
+                last = piece;
+                break;
+            }
+
+            let mut frame = Vec::with_capacity(HEADER_SIZE as usize + piece.len());
+            frame::write_frame(
+                &mut frame,
+                FrameKind::Chunk,
+                channel,
+                0,
+                piece,
+                None,
+                self.max_frame_size,
+            )?;
+            self.scheduler.push(channel, frame)?;
+        }
 
+        let mut frame = Vec::with_capacity(HEADER_SIZE as usize + last.len());
+        let mut header = frame::write_frame(
+            &mut frame,
+            kind,
+            channel,
+            request_id,
+            last,
+            uncompressed_size,
+            self.max_frame_size,
+        )?;
+        self.scheduler.push(channel, frame)?;
+
+        header.size = payload.len() as u64;
+        header.uncompressed_size = size;
         Ok(header)
     }
 
+    /// Asks the other side for a pong, used for the round trip time
+    pub fn ping(&mut self) -> Result<Header, SocketError> {
+        self.send_frame(FrameKind::Ping, &[])
+    }
+
+    /// Answers a ping, [`Socket::try_recv`] does it for you
+    pub fn pong(&mut self) -> Result<Header, SocketError> {
+        self.send_frame(FrameKind::Pong, &[])
+    }
+
+    /// Tells the other side that we are closing the communication
+    ///
+    /// This is sent when the socket is dropped, after the messages that are still queued
+    pub fn close(&mut self) -> Result<Header, SocketError> {
+        while self.scheduler.fill(&mut self.outgoing, usize::MAX) {}
+
+        self.send_frame(FrameKind::Close, &[])
+    }
+
+    // The frame is queued, then written as much as the stream allows, see Socket::flush
+    // Only for the frames of the crate, they don't wait behind the messages of the scheduler
+    fn send_frame(&mut self, kind: FrameKind, payload: &[u8]) -> Result<Header, SocketError> {
+        let mut frame = Vec::with_capacity(HEADER_SIZE as usize + payload.len());
+        let header =
+            frame::write_frame(&mut frame, kind, 0, 0, payload, None, self.max_frame_size)?;
+        self.outgoing.push_back(frame);
+
+        self.flush()?;
+
+        Ok(header)
+    }
+
+    /// Writes the queued frames until the stream would block
+    ///
+    /// Frames that don't fit in the os buffer (big messages or a slow peer) stay queued,
+    /// they are sent by the next call to send, flush or try_recv_frame.
+    /// The queued frames are written by priority, see [`ChannelConfig`]
+    pub fn flush(&mut self) -> Result<(), SocketError> {
+        let written = self.write_queued()?;
+
+        if self.pending_bytes() == 0 {
+            self.write_stalled_since = None;
+        } else if written || self.write_stalled_since.is_none() {
+            self.write_stalled_since = Some(std::time::Instant::now());
+        } else if self
+            .write_deadline()
+            .is_some_and(|deadline| deadline <= std::time::Instant::now())
+        {
+            return Err(SocketError::StreamWrite(std::io::Error::from(
+                std::io::ErrorKind::TimedOut,
+            )));
+        }
+
+        Ok(())
+    }
+
+    // Returns true if the stream accepted something
+    fn write_queued(&mut self) -> Result<bool, SocketError> {
+        use std::io::{IoSlice, Write as _};
+
+        let mut written = false;
+
+        loop {
+            // Only refilled once it's empty, so what is queued later on a more urgent channel doesn't wait for long
+            if self.outgoing.is_empty()
+                && !self.scheduler.fill(&mut self.outgoing, WRITE_CHUNK_SIZE)
+            {
+                break;
+            }
+
+            let mut slices = [IoSlice::new(&[]); MAX_WRITE_SLICES];
+            for (slice, frame) in slices.iter_mut().zip(&self.outgoing) {
+                *slice = IoSlice::new(frame);
+            }
+            slices[0] = IoSlice::new(&self.outgoing[0][self.outgoing_start..]);
+            let count = self.outgoing.len().min(MAX_WRITE_SLICES);
+
+            match self.stream.write_vectored(&slices[..count]) {
+                Ok(0) => {
+                    return Err(SocketError::StreamWrite(std::io::Error::from(
+                        std::io::ErrorKind::WriteZero,
+                    )))
+                }
+                Ok(n) => {
+                    self.consume_outgoing(n);
+                    written = true;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(written),
+                Err(e) => return Err(SocketError::StreamWrite(e)),
+            }
+        }
+
+        Ok(written)
+    }
+
+    // Drops the frames the stream took, the one it took part of stays
+    fn consume_outgoing(&mut self, mut written: usize) {
+        while let Some(frame) = self.outgoing.front() {
+            let left = frame.len() - self.outgoing_start;
+            if written < left {
+                self.outgoing_start += written;
+                return;
+            }
+
+            written -= left;
+            self.outgoing.pop_front();
+            self.outgoing_start = 0;
+        }
+    }
+
+    /// The number of bytes that are queued, waiting for the stream to accept them
+    pub fn pending_bytes(&self) -> usize {
+        self.outgoing.iter().map(Vec::len).sum::<usize>() - self.outgoing_start
+            + self.scheduler.bytes()
+    }
+
+    /// Receives the next message, answering the pings that were received before it
     pub fn try_recv(&mut self) -> Result<(Header, R), SocketError> {
-        let header = match self.last_header {
-            Some(header) => {
-                trace!("Using saved header: {header:?}");
-                header
+        loop {
+            match self.try_recv_frame()? {
+                (header, Frame::Data(message)) => return Ok((header, message)),
+                (_, Frame::Ping) => {
+                    self.pong()?;
+                }
+                (_, Frame::Pong) => (),
+            }
+        }
+    }
+
+    /// Receives the next frame, without answering pings
+    ///
+    /// This also flushes the queued frames, see [`Socket::flush`]
+    ///
+    /// A close frame is reported as [`SocketError::Exited`]
+    pub fn try_recv_frame(&mut self) -> Result<(Header, Frame<R>), SocketError> {
+        self.flush()?;
+
+        // The chunks are kept until the last piece of their message
+        loop {
+            if let Some(frame) = self.try_recv_piece()? {
+                return Ok(frame);
             }
-            None => {
-                let header = self.try_get::<Header>(HEADER_SIZE)?;
+        }
+    }
 
-                self.last_header = Some(header);
-                header
+    fn try_recv_piece(&mut self) -> Result<Option<(Header, Frame<R>)>, SocketError> {
+        let mut header = loop {
+            match self.buffered_frame() {
+                Ok(Some(header)) => break header,
+                // Returns a WouldBlock error once everything available has been read
+                Ok(None) => self.fill_recv_buffer()?,
+                Err(e) => {
+                    // We can't know where the next frame starts, nothing else can be read
+                    error!(
+                        "Closing the connection with {:?}: {e}",
+                        self.stream.tcp().peer_addr()
+                    );
+                    let _ = self.stream.tcp().shutdown(std::net::Shutdown::Both);
+                    return Err(e);
+                }
             }
         };
 
-        let message = self.try_get::<R>(header.size)?;
+        let payload_start = self.recv_start + HEADER_SIZE as usize;
+        let payload_end = payload_start + header.size as usize;
 
-        self.last_header = None;
+        // The frame is consumed before decoding, even if the decoding fails the next frame is still readable
+        self.recv_start = payload_end;
 
-        if message.is_exit() {
-            return Err(SocketError::Exited);
-        }
+        let frame = frame::read_frame::<R, C>(
+            &mut self.reassembly,
+            &mut header,
+            &self.recv_buffer[payload_start..payload_end],
+            self.max_frame_size,
+        )?;
 
-        Ok((header, message))
+        Ok(frame.map(|frame| (header, frame)))
     }
 
-    fn try_get<T: serde::de::DeserializeOwned + std::fmt::Debug>(
-        &mut self,
-        target_size: u64,
-    ) -> Result<T, SocketError> {
+    fn buffered_frame(&self) -> Result<Option<Header>, SocketError> {
+        frame::buffered_frame(&self.recv_buffer[self.recv_start..], self.max_frame_size)
+    }
+
+    fn fill_recv_buffer(&mut self) -> Result<(), SocketError> {
         use std::io::Read as _;
-        let mut peek_buffer = vec![0; target_size as usize];
-
-        let read_len = self
-            .stream
-            .peek(&mut peek_buffer)
-            .map_err(SocketError::StreamRead)? as u64;
-
-        if read_len != 0 {
-            trace!(
-                "Peeking steam, looking for {} bytes.. Done, found {} bytes",
-                target_size,
-                read_len
-            );
-        }
 
-        if read_len != target_size {
-            if read_len != 0 {
-                warn!("Read {} but was waiting for {}", read_len, target_size);
+        // Drop what has already been parsed
+        if self.recv_start != 0 {
+            self.recv_buffer.drain(..self.recv_start);
+            self.recv_start = 0;
+
+            // Don't keep the memory of a big message around
+            if self.recv_buffer.capacity() > READ_CHUNK_SIZE * 4
+                && self.recv_buffer.len() < READ_CHUNK_SIZE
+            {
+                self.recv_buffer.shrink_to(READ_CHUNK_SIZE * 2);
             }
-            return Err(SocketError::StreamRead(std::io::Error::new(
-                std::io::ErrorKind::WouldBlock,
-                "",
-            )));
         }
 
-        let mut message_buffer = vec![0; target_size as usize];
+        let len = self.recv_buffer.len();
+        self.recv_buffer.resize(len + READ_CHUNK_SIZE, 0);
 
-        self.stream
-            .read_exact(&mut message_buffer)
-            .map_err(SocketError::StreamRead)?;
+        let res = self.stream.read(&mut self.recv_buffer[len..]);
 
-        let message: T =
-            bincode::deserialize(&message_buffer).map_err(SocketError::Deserialization)?;
-        trace!("Deserializing message.. Done, {message:?}");
+        self.recv_buffer
+            .truncate(len + res.as_ref().copied().unwrap_or(0));
 
-        Ok(message)
+        match res {
+            // A read of 0 bytes means that the other side has shut the stream down
+            Ok(0) => Err(SocketError::Exited),
+            Ok(read_len) => {
+                trace!(
+                    "Read {read_len} bytes, {} are buffered",
+                    self.recv_buffer.len()
+                );
+                Ok(())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => Ok(()),
+            Err(e) => Err(SocketError::StreamRead(e)),
+        }
     }
 
     pub fn recv(&mut self, check_delay: std::time::Duration) -> Result<(Header, R), SocketError> {
@@ -169,21 +618,27 @@ impl<R: crate::Message, W: crate::Message> Socket<R, W> {
         }
     }
 
+    // Used by the proxy to register the stream in its poll
+    pub(crate) fn try_clone_stream(&self) -> std::io::Result<std::net::TcpStream> {
+        self.stream.tcp().try_clone()
+    }
+
     pub fn local_addr(&self) -> std::net::SocketAddr {
-        self.stream.local_addr().unwrap()
+        self.stream.tcp().local_addr().unwrap()
     }
 
     pub fn remote_addr(&self) -> std::net::SocketAddr {
-        self.stream.peer_addr().unwrap()
+        self.stream.tcp().peer_addr().unwrap()
     }
     pub fn shutdown(&self) {
-        self.stream.shutdown(std::net::Shutdown::Both).unwrap();
+        // The other side might have done it already
+        let _ = self.stream.tcp().shutdown(std::net::Shutdown::Both);
     }
 }
 
-impl<R: crate::Message, W: crate::Message> std::ops::Drop for Socket<R, W> {
+impl<R: crate::Message, W: crate::Message, C: crate::Codec> std::ops::Drop for Socket<R, W, C> {
     fn drop(&mut self) {
         // Don't care about the error, half the time it's gonna be disconnected anyway
-        let _ = self.send(W::default_exit());
+        let _ = self.close();
     }
 }
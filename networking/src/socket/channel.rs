@@ -0,0 +1,183 @@
+/// The logical channels of a connection, see [`super::Socket::send_on`]
+///
+/// A channel is ordered, but the channels are independent: a big message on one of them
+/// is sent in chunks, so the frames of a channel with a higher priority can go between them
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelConfig {
+    // The priority of each channel, indexed by the channel id, the highest goes first
+    // The channels with the same priority take turns, a busy one can starve the ones below it
+    pub priorities: Vec<u8>,
+    // Bigger payloads are split in chunks of that size, see DEFAULT_CHUNK_SIZE
+    pub chunk_size: usize,
+}
+
+/// Small enough to not hold the other channels for long, big enough to not cost much in headers
+pub const DEFAULT_CHUNK_SIZE: usize = 16 * 1024;
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            // A single channel, 0, which is the one used by Socket::send
+            priorities: vec![0],
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+}
+
+// The frames of a channel, each one already has its header
+struct Lane {
+    priority: u8,
+    frames: std::collections::VecDeque<Vec<u8>>,
+}
+
+// The frames that are waiting for their turn to be written, see Socket::flush
+pub(crate) struct Scheduler {
+    lanes: Vec<Lane>,
+    // Where the search for the next lane starts, so the lanes with the same priority take turns
+    next: usize,
+    bytes: usize,
+}
+
+impl Scheduler {
+    pub(crate) fn new(cfg: &ChannelConfig) -> Self {
+        let mut scheduler = Self {
+            lanes: Vec::new(),
+            next: 0,
+            bytes: 0,
+        };
+        scheduler.set_priorities(&cfg.priorities);
+        scheduler
+    }
+
+    // The frames of the removed channels are dropped
+    pub(crate) fn set_priorities(&mut self, priorities: &[u8]) {
+        for lane in self.lanes.iter().skip(priorities.len()) {
+            self.bytes -= lane.frames.iter().map(Vec::len).sum::<usize>();
+        }
+
+        self.lanes.truncate(priorities.len());
+        self.lanes.resize_with(priorities.len(), || Lane {
+            priority: 0,
+            frames: std::collections::VecDeque::new(),
+        });
+
+        for (lane, priority) in self.lanes.iter_mut().zip(priorities) {
+            lane.priority = *priority;
+        }
+        self.next = 0;
+    }
+
+    pub(crate) fn channel_count(&self) -> usize {
+        self.lanes.len()
+    }
+
+    pub(crate) fn push(&mut self, channel: u8, frame: Vec<u8>) -> Result<(), super::SocketError> {
+        let Some(lane) = self.lanes.get_mut(channel as usize) else {
+            return Err(super::SocketError::UnknownChannel(channel));
+        };
+
+        self.bytes += frame.len();
+        lane.frames.push_back(frame);
+        Ok(())
+    }
+
+    // Moves the most urgent frames to the outgoing ones until they hold at least max bytes, they are not copied
+    // Returns false if there was nothing to move
+    pub(crate) fn fill(
+        &mut self,
+        outgoing: &mut std::collections::VecDeque<Vec<u8>>,
+        max: usize,
+    ) -> bool {
+        let mut filled = false;
+        let mut bytes = outgoing.iter().map(Vec::len).sum::<usize>();
+
+        while bytes < max {
+            let Some(frame) = self.pop() else {
+                break;
+            };
+            bytes += frame.len();
+            outgoing.push_back(frame);
+            filled = true;
+        }
+
+        filled
+    }
+
+    fn pop(&mut self) -> Option<Vec<u8>> {
+        let count = self.lanes.len();
+
+        // The first of the busiest lanes after the last one that was picked
+        let index = (0..count)
+            .map(|offset| (self.next + offset) % count)
+            .filter(|index| !self.lanes[*index].frames.is_empty())
+            .max_by_key(|index| {
+                // max_by_key keeps the last of the equals, the offset makes it the first
+                let offset = (*index + count - self.next) % count;
+                (self.lanes[*index].priority, std::cmp::Reverse(offset))
+            })?;
+
+        let frame = self.lanes[index].frames.pop_front()?;
+        self.bytes -= frame.len();
+        self.next = (index + 1) % count;
+        Some(frame)
+    }
+
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+// The chunks of the messages that are being received, by channel
+#[derive(Default)]
+pub(crate) struct Reassembly {
+    partial: std::collections::HashMap<u8, Vec<u8>>,
+    bytes: usize,
+    // The channels whose message was too large, the rest of it is dropped up to its last piece
+    discarding: std::collections::HashSet<u8>,
+}
+
+impl Reassembly {
+    // Keeps the payload of a chunk, the messages of all the channels together are checked against max_frame_size
+    // Any channel can be used by the other side, so it's what bounds the memory held here
+    pub(crate) fn push(
+        &mut self,
+        channel: u8,
+        payload: &[u8],
+        max_frame_size: u64,
+    ) -> Result<(), super::SocketError> {
+        // The error was given for the chunk that went over
+        if self.discarding.contains(&channel) {
+            return Ok(());
+        }
+
+        let size = (self.bytes + payload.len()) as u64;
+
+        if size > max_frame_size {
+            self.take(channel);
+            self.discarding.insert(channel);
+            return Err(super::SocketError::FrameTooLarge {
+                size,
+                max: max_frame_size,
+            });
+        }
+
+        self.partial
+            .entry(channel)
+            .or_default()
+            .extend_from_slice(payload);
+        self.bytes += payload.len();
+        Ok(())
+    }
+
+    // True for the last piece of a message that was dropped, the next one on that channel is read again
+    pub(crate) fn end_discard(&mut self, channel: u8) -> bool {
+        self.discarding.remove(&channel)
+    }
+
+    // The chunks received before the last piece of the message, if it had any
+    pub(crate) fn take(&mut self, channel: u8) -> Option<Vec<u8>> {
+        let partial = self.partial.remove(&channel)?;
+        self.bytes -= partial.len();
+        Some(partial)
+    }
+}
@@ -0,0 +1,153 @@
+// The framing shared by Socket and the async socket, so both can talk to each other
+
+use super::{Frame, FrameKind, Header, SocketError, HEADER_SIZE};
+
+// Encodes the message, then compresses it if that's worth it
+// Gives the payload and, when it was compressed, the size it had before
+pub(crate) fn encode_data<W: crate::Message, C: crate::Codec>(
+    message: &W,
+    compression: Option<crate::Compression>,
+) -> Result<(Vec<u8>, Option<u64>), SocketError> {
+    let message_bytes = C::encode(message).map_err(SocketError::Serialization)?;
+    trace!("Sending {:?}:  {:?}", message, message_bytes);
+
+    Ok(
+        match compression.and_then(|compression| compression.compress(&message_bytes)) {
+            Some(compressed) => (compressed, Some(message_bytes.len() as u64)),
+            None => (message_bytes, None),
+        },
+    )
+}
+
+// Appends the header and the payload to the buffer
+// uncompressed_size is only given for compressed payloads
+pub(crate) fn write_frame(
+    buffer: &mut Vec<u8>,
+    kind: FrameKind,
+    channel: u8,
+    request_id: u64,
+    payload: &[u8],
+    uncompressed_size: Option<u64>,
+    max_frame_size: u64,
+) -> Result<Header, SocketError> {
+    let mut header = Header::new(kind, payload.len() as u64);
+    header.request_id = request_id;
+    header.channel = channel;
+
+    if let Some(uncompressed_size) = uncompressed_size {
+        header.compressed = true;
+        header.uncompressed_size = uncompressed_size;
+    }
+
+    // The receiver checks the size once decompressed too
+    if header.uncompressed_size > max_frame_size {
+        return Err(SocketError::FrameTooLarge {
+            size: header.uncompressed_size,
+            max: max_frame_size,
+        });
+    }
+
+    // The header doesn't go through the codec, its size has to be known by the reader
+    let header_bytes = bincode::serialize(&header).map_err(|e| SocketError::Serialization(e))?;
+
+    // idk if panicking is a good idea
+    // assert_eq!(header_bytes.len(), HEADER_SIZE);
+    if header_bytes.len() as u64 != HEADER_SIZE {
+        return Err(SocketError::Serialization(format!("The length of the serialized header is not equal to the HEADER_SIZE constant ({HEADER_SIZE})").into()));
+    }
+
+    buffer.extend_from_slice(&header_bytes);
+    buffer.extend_from_slice(payload);
+    trace!("Sending {:?}:  {:?}", header, header_bytes);
+
+    Ok(header)
+}
+
+// Gives the header of the first buffered frame, if that frame has been fully received
+pub(crate) fn buffered_frame(
+    buffered: &[u8],
+    max_frame_size: u64,
+) -> Result<Option<Header>, SocketError> {
+    if (buffered.len() as u64) < HEADER_SIZE {
+        return Ok(None);
+    }
+
+    let mut header: Header = bincode::deserialize(&buffered[..HEADER_SIZE as usize])
+        .map_err(|e| SocketError::Deserialization(e))?;
+    header.uncompressed_size = header.size;
+
+    // Checked before anything is allocated for the payload
+    if header.size > max_frame_size {
+        return Err(SocketError::FrameTooLarge {
+            size: header.size,
+            max: max_frame_size,
+        });
+    }
+
+    if (buffered.len() as u64) < HEADER_SIZE + header.size {
+        return Ok(None);
+    }
+
+    Ok(Some(header))
+}
+
+// Decodes the payload of a frame given by buffered_frame
+// None for the chunks, they are kept until the last piece of their message, which gets the size of the whole message
+// None too for the last piece of a message whose chunks were too large, the error was given for them
+// A close frame is reported as SocketError::Exited
+pub(crate) fn read_frame<R: crate::Message, C: crate::Codec>(
+    reassembly: &mut super::channel::Reassembly,
+    header: &mut Header,
+    payload: &[u8],
+    max_frame_size: u64,
+) -> Result<Option<Frame<R>>, SocketError> {
+    Ok(Some(match header.kind {
+        FrameKind::Data | FrameKind::Request | FrameKind::Response => {
+            if reassembly.end_discard(header.channel) {
+                return Ok(None);
+            }
+
+            let message = match reassembly.take(header.channel) {
+                None => decode_data::<R, C>(header, payload, max_frame_size)?,
+                Some(mut message) => {
+                    message.extend_from_slice(payload);
+                    header.size = message.len() as u64;
+                    header.uncompressed_size = header.size;
+                    if header.size > max_frame_size {
+                        return Err(SocketError::FrameTooLarge {
+                            size: header.size,
+                            max: max_frame_size,
+                        });
+                    }
+
+                    decode_data::<R, C>(header, &message, max_frame_size)?
+                }
+            };
+            Frame::Data(message)
+        }
+        FrameKind::Chunk => {
+            reassembly.push(header.channel, payload, max_frame_size)?;
+            return Ok(None);
+        }
+        FrameKind::Ping => Frame::Ping,
+        FrameKind::Pong => Frame::Pong,
+        FrameKind::Close => return Err(SocketError::Exited),
+    }))
+}
+
+fn decode_data<R: crate::Message, C: crate::Codec>(
+    header: &mut Header,
+    payload: &[u8],
+    max_frame_size: u64,
+) -> Result<R, SocketError> {
+    let message: R = if header.compressed {
+        let decompressed = crate::compression::decompress(payload, max_frame_size)?;
+        header.uncompressed_size = decompressed.len() as u64;
+        C::decode(&decompressed)
+    } else {
+        C::decode(payload)
+    }
+    .map_err(SocketError::Deserialization)?;
+    trace!("Deserializing message.. Done, {message:?}");
+    Ok(message)
+}
@@ -0,0 +1,135 @@
+// Written by both sides before any frame, see Socket::handshake
+// Like the header, it always goes through bincode and has a fixed size
+// (magic, version, read hash, write hash and compressions, back to back)
+pub const HANDSHAKE_SIZE: u64 = 4 + 4 + 8 + 8 + 4;
+
+/// Bumped every time the wire format changes
+pub const PROTOCOL_VERSION: u32 = 4;
+
+const MAGIC: u32 = u32::from_be_bytes(*b"NTWK");
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Handshake {
+    pub magic: u32,
+    pub version: u32,
+    // Hashes of the type names of what this side reads and writes
+    pub read_hash: u64,
+    pub write_hash: u64,
+    // The compressions this side can read, one bit each, the other side only compresses with one of them
+    pub compressions: u32,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum HandshakeError {
+    #[error("The other side is not a networking socket")]
+    Magic,
+    #[error(
+        "Protocol version mismatch, this side uses v{local} and the other side uses v{remote}"
+    )]
+    Version { local: u32, remote: u32 },
+    #[error("Message types mismatch, this side reads {read} and writes {write} but the other side doesn't")]
+    Messages {
+        read: &'static str,
+        write: &'static str,
+    },
+    #[error("The other side did not answer in time")]
+    Timeout,
+}
+
+impl Handshake {
+    pub fn new<R: crate::Message, W: crate::Message>() -> Self {
+        Self {
+            magic: MAGIC,
+            version: PROTOCOL_VERSION,
+            read_hash: type_hash::<R>(),
+            write_hash: type_hash::<W>(),
+            compressions: crate::Compression::supported(),
+        }
+    }
+
+    // What we read is what the other side writes, and the other way around
+    pub(crate) fn check<R: crate::Message, W: crate::Message>(
+        &self,
+        remote: &Handshake,
+    ) -> Result<(), HandshakeError> {
+        if remote.magic != self.magic {
+            return Err(HandshakeError::Magic);
+        }
+
+        if remote.version != self.version {
+            return Err(HandshakeError::Version {
+                local: self.version,
+                remote: remote.version,
+            });
+        }
+
+        if remote.write_hash != self.read_hash || remote.read_hash != self.write_hash {
+            return Err(HandshakeError::Messages {
+                read: std::any::type_name::<R>(),
+                write: std::any::type_name::<W>(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+// FNV-1a, the std hashers are not guaranteed to give the same result across builds
+fn type_hash<T>() -> u64 {
+    std::any::type_name::<T>()
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+impl<R: crate::Message, W: crate::Message, C: crate::Codec> super::Socket<R, W, C> {
+    /// Makes sure that the other side speaks the same protocol, with the same message types
+    ///
+    /// It also tells which compressions the other side reads, nothing is compressed before it's done.
+    /// Has to be called by both sides, before anything else is sent.
+    /// Blocks until the other side's handshake is received, or until the timeout
+    pub fn handshake(&mut self, timeout: std::time::Duration) -> Result<(), super::SocketError> {
+        use super::SocketError;
+
+        const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+
+        let local = Handshake::new::<R, W>();
+
+        let bytes = bincode::serialize(&local).map_err(|e| SocketError::Serialization(e))?;
+        self.outgoing.push_back(bytes);
+
+        let start = std::time::Instant::now();
+
+        while ((self.recv_buffer.len() - self.recv_start) as u64) < HANDSHAKE_SIZE {
+            self.flush()?;
+
+            match self.fill_recv_buffer() {
+                Ok(()) => (),
+                Err(SocketError::StreamRead(ref io_e))
+                    if io_e.kind() == std::io::ErrorKind::WouldBlock =>
+                {
+                    if start.elapsed() > timeout {
+                        return Err(HandshakeError::Timeout.into());
+                    }
+                    spin_sleep::sleep(CHECK_DELAY);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let handshake_end = self.recv_start + HANDSHAKE_SIZE as usize;
+
+        let remote: Handshake =
+            bincode::deserialize(&self.recv_buffer[self.recv_start..handshake_end])
+                .map_err(|e| SocketError::Deserialization(e))?;
+        self.recv_start = handshake_end;
+
+        local.check::<R, W>(&remote)?;
+        self.peer_compressions = remote.compressions;
+
+        debug!("Handshake with {:?} done", self.stream.tcp().peer_addr());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+// What a Socket reads and writes, the framing doesn't care if it's encrypted or not
+pub(crate) enum Stream {
+    Tcp(std::net::TcpStream),
+    // rustls::StreamOwned doesn't take a rustls::Connection, the sides have their own variants
+    #[cfg(feature = "tls")]
+    TlsClient(Box<rustls::StreamOwned<rustls::ClientConnection, std::net::TcpStream>>),
+    #[cfg(feature = "tls")]
+    TlsServer(Box<rustls::StreamOwned<rustls::ServerConnection, std::net::TcpStream>>),
+}
+
+impl Stream {
+    #[cfg(feature = "tls")]
+    pub(crate) fn tls(stream: std::net::TcpStream, connection: rustls::Connection) -> Self {
+        match connection {
+            rustls::Connection::Client(connection) => {
+                Stream::TlsClient(Box::new(rustls::StreamOwned::new(connection, stream)))
+            }
+            rustls::Connection::Server(connection) => {
+                Stream::TlsServer(Box::new(rustls::StreamOwned::new(connection, stream)))
+            }
+        }
+    }
+
+    // For the addresses and the shutdown
+    pub(crate) fn tcp(&self) -> &std::net::TcpStream {
+        match self {
+            Stream::Tcp(stream) => stream,
+            #[cfg(feature = "tls")]
+            Stream::TlsClient(stream) => &stream.sock,
+            #[cfg(feature = "tls")]
+            Stream::TlsServer(stream) => &stream.sock,
+        }
+    }
+}
+
+// Like a non-blocking TcpStream, the tls session gives a WouldBlock error until it has something to give
+impl std::io::Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Stream::TlsClient(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Stream::TlsServer(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl std::io::Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Stream::TlsClient(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Stream::TlsServer(stream) => stream.write(buf),
+        }
+    }
+
+    // rustls takes the slices one by one, the tcp stream writes them all at once
+    fn write_vectored(&mut self, bufs: &[std::io::IoSlice<'_>]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write_vectored(bufs),
+            #[cfg(feature = "tls")]
+            Stream::TlsClient(stream) => stream.write_vectored(bufs),
+            #[cfg(feature = "tls")]
+            Stream::TlsServer(stream) => stream.write_vectored(bufs),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Stream::TlsClient(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Stream::TlsServer(stream) => stream.flush(),
+        }
+    }
+}
@@ -2,6 +2,9 @@
 pub struct Bps {
     total_sent: u64,
     total_received: u64,
+    // What it would have been without compression
+    total_sent_uncompressed: u64,
+    total_received_uncompressed: u64,
 
     rolling_window: Vec<WindowEntry>,
     cfg: super::config::BpsConfig,
@@ -15,6 +18,8 @@ pub struct WindowEntry {
     time: std::time::Instant,
     bytes_sent: u64,
     bytes_received: u64,
+    bytes_sent_uncompressed: u64,
+    bytes_received_uncompressed: u64,
 }
 
 impl Bps {
@@ -22,6 +27,8 @@ impl Bps {
         let mut bps = Self {
             total_sent: 0,
             total_received: 0,
+            total_sent_uncompressed: 0,
+            total_received_uncompressed: 0,
             rolling_window: Vec::new(),
             cfg,
         };
@@ -33,15 +40,19 @@ impl Bps {
     }
 
     pub fn update(&mut self) {
-        let ten_seconds_ago = std::time::Instant::now() - std::time::Duration::from_secs(10);
+        let now = std::time::Instant::now();
+        let window = self.cfg.window;
         self.rolling_window.retain(|entry| {
-            entry.time >= ten_seconds_ago && (entry.bytes_sent != 0 || entry.bytes_received != 0)
+            now.duration_since(entry.time) <= window
+                && (entry.bytes_sent != 0 || entry.bytes_received != 0)
         });
 
         self.rolling_window.push(WindowEntry {
             time: std::time::Instant::now(),
             bytes_sent: 0,
             bytes_received: 0,
+            bytes_sent_uncompressed: 0,
+            bytes_received_uncompressed: 0,
         });
 
         // println!("{} windows", self.rolling_window.len());
@@ -53,31 +64,83 @@ impl Bps {
     pub fn total_sent(&self) -> u64 {
         self.total_sent
     }
-    pub fn received_last_10_sec(&self) -> u64 {
+    pub fn received_in_window(&self) -> u64 {
         self.rolling_window
             .iter()
             .map(|entry| entry.bytes_received)
             .sum::<u64>()
     }
-    pub fn bps_received_last_10_sec(&self) -> u64 {
-        self.received_last_10_sec() / 10
+    pub fn bps_received(&self) -> u64 {
+        self.per_second(self.received_in_window())
     }
-    pub fn sent_last_10_sec(&self) -> u64 {
+    pub fn sent_in_window(&self) -> u64 {
         self.rolling_window
             .iter()
             .map(|entry| entry.bytes_sent)
             .sum::<u64>()
     }
-    pub fn bps_sent_last_10_sec(&self) -> u64 {
-        self.sent_last_10_sec() / 10
+    pub fn bps_sent(&self) -> u64 {
+        self.per_second(self.sent_in_window())
+    }
+    // Over the whole window, even when the proxy has been running for less than that
+    fn per_second(&self, bytes: u64) -> u64 {
+        let secs = self.cfg.window.as_secs_f64();
+        if secs == 0. {
+            return 0;
+        }
+        (bytes as f64 / secs) as u64
+    }
+    pub fn total_received_uncompressed(&self) -> u64 {
+        self.total_received_uncompressed
+    }
+    pub fn total_sent_uncompressed(&self) -> u64 {
+        self.total_sent_uncompressed
+    }
+    pub fn received_uncompressed_in_window(&self) -> u64 {
+        self.rolling_window
+            .iter()
+            .map(|entry| entry.bytes_received_uncompressed)
+            .sum::<u64>()
+    }
+    pub fn sent_uncompressed_in_window(&self) -> u64 {
+        self.rolling_window
+            .iter()
+            .map(|entry| entry.bytes_sent_uncompressed)
+            .sum::<u64>()
     }
     pub fn on_bytes_recv(&mut self, header: &crate::socket::Header) {
         self.total_received += header.size;
-        self.rolling_window.last_mut().unwrap().bytes_received += header.size;
+        self.total_received_uncompressed += header.uncompressed_size;
+
+        let entry = self.rolling_window.last_mut().unwrap();
+        entry.bytes_received += header.size;
+        entry.bytes_received_uncompressed += header.uncompressed_size;
+    }
+    // Datagrams are not compressed, the whole datagram is counted
+    pub fn on_datagram_recv(&mut self, len: u64) {
+        self.total_received += len;
+        self.total_received_uncompressed += len;
+
+        let entry = self.rolling_window.last_mut().unwrap();
+        entry.bytes_received += len;
+        entry.bytes_received_uncompressed += len;
+    }
+    pub fn on_datagram_send(&mut self, len: u64) {
+        self.total_sent += len;
+        self.total_sent_uncompressed += len;
+
+        let entry = self.rolling_window.last_mut().unwrap();
+        entry.bytes_sent += len;
+        entry.bytes_sent_uncompressed += len;
     }
     pub fn on_bytes_send(&mut self, header: &crate::socket::Header) {
         let byte_sent = header.size + crate::socket::HEADER_SIZE;
+        let byte_sent_uncompressed = header.uncompressed_size + crate::socket::HEADER_SIZE;
         self.total_sent += byte_sent;
-        self.rolling_window.last_mut().unwrap().bytes_sent += byte_sent;
+        self.total_sent_uncompressed += byte_sent_uncompressed;
+
+        let entry = self.rolling_window.last_mut().unwrap();
+        entry.bytes_sent += byte_sent;
+        entry.bytes_sent_uncompressed += byte_sent_uncompressed;
     }
 }
@@ -0,0 +1,72 @@
+// What went through each channel, see networking::socket::ChannelConfig
+#[derive(Clone, Default)]
+pub struct Channels {
+    // Indexed by the channel id, grown as the channels are used
+    channels: Vec<Channel>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Channel {
+    messages_sent: u64,
+    messages_received: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+}
+
+impl Channels {
+    pub fn on_send(&mut self, header: &crate::socket::Header) {
+        if let Some(channel) = self.channel_mut(header) {
+            channel.messages_sent += 1;
+            channel.bytes_sent += header.size;
+        }
+    }
+    pub fn on_recv(&mut self, header: &crate::socket::Header) {
+        if let Some(channel) = self.channel_mut(header) {
+            channel.messages_received += 1;
+            channel.bytes_received += header.size;
+        }
+    }
+
+    // Only the messages are counted, not the pings
+    fn channel_mut(&mut self, header: &crate::socket::Header) -> Option<&mut Channel> {
+        if !is_message(header) {
+            return None;
+        }
+
+        let index = header.channel as usize;
+        if self.channels.len() <= index {
+            self.channels.resize(index + 1, Channel::default());
+        }
+        self.channels.get_mut(index)
+    }
+
+    fn channel(&self, channel: u8) -> Channel {
+        self.channels
+            .get(channel as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn messages_sent(&self, channel: u8) -> u64 {
+        self.channel(channel).messages_sent
+    }
+    pub fn messages_received(&self, channel: u8) -> u64 {
+        self.channel(channel).messages_received
+    }
+    pub fn bytes_sent(&self, channel: u8) -> u64 {
+        self.channel(channel).bytes_sent
+    }
+    pub fn bytes_received(&self, channel: u8) -> u64 {
+        self.channel(channel).bytes_received
+    }
+}
+
+// The frames that carry a message, the others are pings, pongs and the close frame
+pub(super) fn is_message(header: &crate::socket::Header) -> bool {
+    use crate::socket::FrameKind;
+
+    matches!(
+        header.kind,
+        FrameKind::Data | FrameKind::Request | FrameKind::Response
+    )
+}
@@ -1,25 +1,40 @@
 #[derive(Copy, Clone, Debug)]
 pub struct BpsConfig {
     pub enabled: bool,
+    // The bytes of the last `window` are used for the rates, see NetworkStats::bps_received
+    pub window: std::time::Duration,
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct RttConfig {
     pub enabled: bool,
     pub ping_request_delay: std::time::Duration,
+    // The samples of the last `window` are used for the min, max and percentiles
+    pub window: std::time::Duration,
+    // The weight of a new sample in the smoothed rtt (0. to 1.), 0.125 is what tcp uses
+    pub smoothing: f64,
+}
+
+// Counts the messages and bytes of each message type, see NetworkStats::message_types
+// The type of every message has to be found, it's off by default
+#[derive(Default, Copy, Clone, Debug)]
+pub struct MessageTypeConfig {
+    pub enabled: bool,
 }
 
 #[derive(Default, Copy, Clone, Debug)]
 pub struct StatConfig {
     pub bps: BpsConfig,
     pub rtt: RttConfig,
+    pub message_types: MessageTypeConfig,
 }
 
-// Readability
-#[allow(clippy::derivable_impls)]
 impl Default for BpsConfig {
     fn default() -> Self {
-        Self { enabled: false }
+        Self {
+            enabled: false,
+            window: std::time::Duration::from_secs(10),
+        }
     }
 }
 
@@ -28,6 +43,8 @@ impl Default for RttConfig {
         Self {
             enabled: false,
             ping_request_delay: std::time::Duration::from_millis(1000),
+            window: std::time::Duration::from_secs(10),
+            smoothing: 0.125,
         }
     }
 }
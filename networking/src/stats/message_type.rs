@@ -0,0 +1,243 @@
+// The traffic of each type of message, keyed by the name serde gives them
+#[derive(Clone, Default)]
+pub struct MessageTypes {
+    types: std::collections::HashMap<&'static str, MessageTypeStats>,
+}
+
+/// What went through the connection for one type of message, see [`super::NetworkStats::message_types`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct MessageTypeStats {
+    // The variant of an enum, the name of a struct or the rust type of anything else
+    pub name: &'static str,
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    // Payloads only, as they were sent (compressed or not)
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl MessageTypeStats {
+    pub fn messages(&self) -> u64 {
+        self.messages_sent + self.messages_received
+    }
+    pub fn bytes(&self) -> u64 {
+        self.bytes_sent + self.bytes_received
+    }
+}
+
+/// The message types sorted by traffic, the biggest first
+///
+/// Printed as a table, or serialized as a list of [`MessageTypeStats`] to be exported
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(transparent)]
+pub struct MessageTypeTable {
+    pub rows: Vec<MessageTypeStats>,
+}
+
+impl MessageTypes {
+    pub fn on_send(&mut self, name: &'static str, header: &crate::socket::Header) {
+        let stats = self.entry(name);
+        stats.messages_sent += 1;
+        stats.bytes_sent += header.size;
+    }
+    pub fn on_recv(&mut self, name: &'static str, header: &crate::socket::Header) {
+        let stats = self.entry(name);
+        stats.messages_received += 1;
+        stats.bytes_received += header.size;
+    }
+
+    fn entry(&mut self, name: &'static str) -> &mut MessageTypeStats {
+        self.types.entry(name).or_insert_with(|| MessageTypeStats {
+            name,
+            ..Default::default()
+        })
+    }
+
+    pub fn get(&self, name: &str) -> MessageTypeStats {
+        self.types.get(name).copied().unwrap_or_default()
+    }
+
+    // By bytes then by messages, the name keeps the order stable
+    pub fn table(&self) -> MessageTypeTable {
+        let mut rows = self.types.values().copied().collect::<Vec<_>>();
+        rows.sort_by(|a, b| {
+            b.bytes()
+                .cmp(&a.bytes())
+                .then(b.messages().cmp(&a.messages()))
+                .then(a.name.cmp(b.name))
+        });
+        MessageTypeTable { rows }
+    }
+}
+
+impl std::fmt::Display for MessageTypeTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name_width = self
+            .rows
+            .iter()
+            .map(|row| row.name.len())
+            .max()
+            .unwrap_or(0)
+            .max("type".len());
+
+        writeln!(
+            f,
+            "{:<name_width$} {:>12} {:>12} {:>14} {:>14}",
+            "type", "sent", "received", "bytes sent", "bytes received"
+        )?;
+        for row in &self.rows {
+            writeln!(
+                f,
+                "{:<name_width$} {:>12} {:>12} {:>14} {:>14}",
+                row.name,
+                row.messages_sent,
+                row.messages_received,
+                row.bytes_sent,
+                row.bytes_received
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The name of the message's type, the variant for an enum
+///
+/// It's the name serde sees (a `#[serde(rename)]` changes it), nothing is serialized as it stops at the name
+pub fn message_type_name<M: serde::Serialize>(message: &M) -> &'static str {
+    match message.serialize(NameSerializer) {
+        Err(Name(Some(name))) => name,
+        _ => std::any::type_name::<M>(),
+    }
+}
+
+// Not really an error, it's how NameSerializer stops as soon as it has the name
+#[derive(Debug)]
+struct Name(Option<&'static str>);
+
+impl std::fmt::Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl std::error::Error for Name {}
+
+impl serde::ser::Error for Name {
+    fn custom<T: std::fmt::Display>(_msg: T) -> Self {
+        Name(None)
+    }
+}
+
+struct NameSerializer;
+
+// The types that are not named
+macro_rules! unnamed {
+    ($($method:ident($($arg:ty),*) -> $ret:ty;)*) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<$ret, Name> {
+                Err(Name(None))
+            }
+        )*
+    };
+}
+
+impl serde::Serializer for NameSerializer {
+    type Ok = ();
+    type Error = Name;
+    type SerializeSeq = serde::ser::Impossible<(), Name>;
+    type SerializeTuple = serde::ser::Impossible<(), Name>;
+    type SerializeTupleStruct = serde::ser::Impossible<(), Name>;
+    type SerializeTupleVariant = serde::ser::Impossible<(), Name>;
+    type SerializeMap = serde::ser::Impossible<(), Name>;
+    type SerializeStruct = serde::ser::Impossible<(), Name>;
+    type SerializeStructVariant = serde::ser::Impossible<(), Name>;
+
+    unnamed! {
+        serialize_bool(bool) -> ();
+        serialize_i8(i8) -> ();
+        serialize_i16(i16) -> ();
+        serialize_i32(i32) -> ();
+        serialize_i64(i64) -> ();
+        serialize_u8(u8) -> ();
+        serialize_u16(u16) -> ();
+        serialize_u32(u32) -> ();
+        serialize_u64(u64) -> ();
+        serialize_f32(f32) -> ();
+        serialize_f64(f64) -> ();
+        serialize_char(char) -> ();
+        serialize_str(&str) -> ();
+        serialize_bytes(&[u8]) -> ();
+        serialize_none() -> ();
+        serialize_unit() -> ();
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+    }
+
+    fn serialize_some<T: serde::Serialize + ?Sized>(self, _: &T) -> Result<(), Name> {
+        Err(Name(None))
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<(), Name> {
+        Err(Name(Some(name)))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<(), Name> {
+        Err(Name(Some(variant)))
+    }
+
+    fn serialize_newtype_struct<T: serde::Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        _: &T,
+    ) -> Result<(), Name> {
+        Err(Name(Some(name)))
+    }
+
+    fn serialize_newtype_variant<T: serde::Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        _: &T,
+    ) -> Result<(), Name> {
+        Err(Name(Some(variant)))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Name> {
+        Err(Name(Some(name)))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Name> {
+        Err(Name(Some(variant)))
+    }
+
+    fn serialize_struct(self, name: &'static str, _: usize) -> Result<Self::SerializeStruct, Name> {
+        Err(Name(Some(name)))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Name> {
+        Err(Name(Some(variant)))
+    }
+}
@@ -1,13 +1,26 @@
 mod bps;
+mod channel;
 pub mod config;
+mod message_type;
+mod packets;
+mod queue;
 mod rtt;
 
 pub use config::StatConfig;
+pub use message_type::{message_type_name, MessageTypeStats, MessageTypeTable};
 
 #[derive(Clone)]
 pub struct NetworkStats<SRCW: crate::Message, SWCR: crate::Message> {
     bps_opt: Option<bps::Bps>,
     rtt_opt: Option<rtt::Rtt>,
+    // Always there, it's only two numbers
+    queue: queue::Queue,
+    // Always there too, a few numbers per channel
+    channels: channel::Channels,
+    packets: packets::Packets,
+    // The successful reconnections of the proxy
+    reconnects: u64,
+    message_types_opt: Option<message_type::MessageTypes>,
     srcw: std::marker::PhantomData<SRCW>,
     swcr: std::marker::PhantomData<SWCR>,
     cfg: config::StatConfig,
@@ -26,82 +39,178 @@ impl<SRCW: crate::Message, SWCR: crate::Message> NetworkStats<SRCW, SWCR> {
             } else {
                 None
             },
+            queue: queue::Queue::default(),
+            channels: channel::Channels::default(),
+            packets: packets::Packets::default(),
+            reconnects: 0,
+            message_types_opt: if cfg.message_types.enabled {
+                Some(message_type::MessageTypes::default())
+            } else {
+                None
+            },
             srcw: std::marker::PhantomData,
             swcr: std::marker::PhantomData,
             cfg,
         }
     }
-    pub fn update(
+    pub fn update<C: crate::Codec>(
         &mut self,
-        _channel: &mut threading::Channel<SWCR, super::proxy::ProxyMessage<SRCW>>,
-        socket: &mut crate::Socket<SRCW, SWCR>,
+        socket: &mut crate::Socket<SRCW, SWCR, C>,
     ) -> Result<(), crate::proxy::ProxyError> {
         if self.cfg.rtt.enabled {
             self.update_rtt(socket)?;
         }
 
+        if let Some(rtt) = &mut self.rtt_opt {
+            rtt.update();
+        }
+
         if let Some(bps) = &mut self.bps_opt {
             bps.update();
         }
         Ok(())
     }
 
-    // This can't be in rtt.update as you need the function on_msg_send and on_bytes_send
-    fn update_rtt(
+    // This can't be in rtt.update as you need the function on_ping_send and on_bytes_send
+    fn update_rtt<C: crate::Codec>(
         &mut self,
-        socket: &mut crate::Socket<SRCW, SWCR>,
+        socket: &mut crate::Socket<SRCW, SWCR, C>,
     ) -> Result<(), crate::proxy::ProxyError> {
         let Some(rtt) = &mut self.rtt_opt else {
             return Ok(());
         };
 
         if rtt.needs_ping() {
-            let msg = SWCR::default_ping();
-            self.on_msg_send(&msg);
             let header = socket
-                .send(msg)
-                .map_err(|e| crate::proxy::ProxyError::SocketSend(e.to_string()))?;
+                .ping()
+                .map_err(crate::proxy::ProxyError::SocketSend)?;
+            self.on_ping_send();
             self.on_bytes_send(&header);
         }
 
         Ok(())
     }
 
-    pub fn on_msg_recv(&mut self, msg: &SRCW, socket: &mut crate::Socket<SRCW, SWCR>) {
+    // Without it, the rtt would wait forever for the pong of a ping sent before the disconnection
+    pub(crate) fn on_disconnect(&mut self) {
+        if let Some(rtt) = &mut self.rtt_opt {
+            rtt.on_disconnect();
+        }
+    }
+
+    // A pong is on its way for the ping of the rtt, it's the only ping that can be in flight
+    pub(crate) fn is_waiting_pong(&self) -> bool {
+        self.rtt_opt
+            .as_ref()
+            .is_some_and(|rtt| rtt.ping_request_stopwatch.is_some())
+    }
+
+    pub fn on_pong_recv(&mut self) {
         if let Some(rtt) = &mut self.rtt_opt {
-            if msg.is_ping() {
-                let resp = SWCR::default_pong();
-                self.on_msg_send(&resp);
-                if let Ok(header) = socket.send(resp) {
-                    self.on_bytes_send(&header);
-                } else {
-                    warn!("Could not send pong to {}", socket.remote_addr());
-                }
-            } else if msg.is_pong() {
-                if let Some(stopwatch) = &rtt.ping_request_stopwatch {
-                    rtt.set(stopwatch.read());
-                    rtt.ping_request_stopwatch = None;
-                    rtt.last_pong = std::time::Instant::now();
-                }
+            if let Some(stopwatch) = &rtt.ping_request_stopwatch {
+                rtt.set(stopwatch.read());
+                rtt.ping_request_stopwatch = None;
+                rtt.last_pong = std::time::Instant::now();
             }
         }
     }
     pub fn on_bytes_recv(&mut self, header: &crate::socket::Header) {
+        self.channels.on_recv(header);
+        self.packets.on_recv(header);
+
         // we don't use if let else here because it's a general purpose function
         if let Some(bps) = &mut self.bps_opt {
             bps.on_bytes_recv(header)
         }
     }
 
-    pub fn on_msg_send(&mut self, msg: &SWCR) {
+    // None when the message types are not tracked, the name is only looked up when it's needed
+    // The proxy gives the message to the socket before it knows its header, so the name is taken before
+    pub fn message_type_name_opt<M: crate::Message>(&self, message: &M) -> Option<&'static str> {
+        self.message_types_opt
+            .as_ref()
+            .map(|_| message_type::message_type_name(message))
+    }
+    pub fn on_message_send(
+        &mut self,
+        name_opt: Option<&'static str>,
+        header: &crate::socket::Header,
+    ) {
+        if let (Some(message_types), Some(name)) = (&mut self.message_types_opt, name_opt) {
+            message_types.on_send(name, header)
+        }
+    }
+    pub fn on_message_recv(
+        &mut self,
+        name_opt: Option<&'static str>,
+        header: &crate::socket::Header,
+    ) {
+        if let (Some(message_types), Some(name)) = (&mut self.message_types_opt, name_opt) {
+            message_types.on_recv(name, header)
+        }
+    }
+
+    pub(crate) fn on_reconnect(&mut self) {
+        self.reconnects += 1;
+    }
+
+    pub fn on_queue_update(&mut self, depth: usize, dropped: u64) {
+        self.queue.update(depth, dropped)
+    }
+
+    pub fn on_datagram_recv(&mut self, len: u64) {
+        self.packets.on_datagram_recv();
+        if let Some(bps) = &mut self.bps_opt {
+            bps.on_datagram_recv(len)
+        }
+    }
+    pub fn on_datagram_send(&mut self, len: u64) {
+        self.packets.on_datagram_send();
+        if let Some(bps) = &mut self.bps_opt {
+            bps.on_datagram_send(len)
+        }
+    }
+
+    // For the sockets that can't go through update, the udp one (its rtt comes from the acks) and the async one
+    // Drops what is older than the windows of the bps and the rtt
+    pub(crate) fn update_windows(&mut self) {
+        if let Some(bps) = &mut self.bps_opt {
+            bps.update();
+        }
+        if let Some(rtt) = &mut self.rtt_opt {
+            rtt.update();
+        }
+    }
+    pub(crate) fn needs_ping(&self) -> bool {
+        self.rtt_opt.as_ref().is_some_and(|rtt| rtt.needs_ping())
+    }
+
+    // How long the proxy can sleep before the next ping or the next bps window, None if it doesn't matter
+    pub(crate) fn next_update(&self) -> Option<std::time::Duration> {
+        let ping = self.rtt_opt.as_ref().and_then(|rtt| rtt.until_next_ping());
+        let bps = self
+            .bps_opt
+            .as_ref()
+            .map(|_| std::time::Duration::from_secs(1));
+
+        match (ping, bps) {
+            (Some(ping), Some(bps)) => Some(ping.min(bps)),
+            (ping, bps) => ping.or(bps),
+        }
+    }
+
+    pub fn on_ping_send(&mut self) {
         // we don't use if let else here because it's a general purpose function
         if let Some(rtt) = &mut self.rtt_opt {
-            if msg.is_ping() && rtt.ping_request_stopwatch.is_none() {
+            if rtt.ping_request_stopwatch.is_none() {
                 rtt.ping_request_stopwatch = Some(time::Stopwatch::start_new())
             }
         }
     }
     pub fn on_bytes_send(&mut self, header: &crate::socket::Header) {
+        self.channels.on_send(header);
+        self.packets.on_send(header);
+
         // we don't use if let else here because it's a general purpose function
         if let Some(bps) = &mut self.bps_opt {
             bps.on_bytes_send(header)
@@ -116,12 +225,126 @@ impl<SRCW: crate::Message, SWCR: crate::Message> NetworkStats<SRCW, SWCR> {
             rtt.set(duration)
         }
     }
+    // The latest sample
     pub fn get_rtt(&self) -> std::time::Duration {
         self.rtt_opt
             .as_ref()
             .map(|rtt| rtt.get())
             .unwrap_or(std::time::Duration::ZERO)
     }
+    // An average that favors the recent samples, see RttConfig::smoothing
+    pub fn smoothed_rtt(&self) -> std::time::Duration {
+        self.rtt_opt
+            .as_ref()
+            .map(|rtt| rtt.smoothed())
+            .unwrap_or(std::time::Duration::ZERO)
+    }
+    // How much the samples move around the smoothed rtt
+    pub fn rtt_jitter(&self) -> std::time::Duration {
+        self.rtt_opt
+            .as_ref()
+            .map(|rtt| rtt.variation())
+            .unwrap_or(std::time::Duration::ZERO)
+    }
+    // Over the samples of the window, see RttConfig::window
+    pub fn min_rtt(&self) -> std::time::Duration {
+        self.rtt_opt
+            .as_ref()
+            .map(|rtt| rtt.min())
+            .unwrap_or(std::time::Duration::ZERO)
+    }
+    pub fn max_rtt(&self) -> std::time::Duration {
+        self.rtt_opt
+            .as_ref()
+            .map(|rtt| rtt.max())
+            .unwrap_or(std::time::Duration::ZERO)
+    }
+    // percentile goes from 0. to 100., rtt_percentile(99.) is the p99
+    pub fn rtt_percentile(&self, percentile: f64) -> std::time::Duration {
+        self.rtt_opt
+            .as_ref()
+            .map(|rtt| rtt.percentile(percentile))
+            .unwrap_or(std::time::Duration::ZERO)
+    }
+    pub fn rtt_sample_count(&self) -> usize {
+        self.rtt_opt
+            .as_ref()
+            .map(|rtt| rtt.sample_count())
+            .unwrap_or(0)
+    }
+}
+
+// packets
+impl<SRCW: crate::Message, SWCR: crate::Message> NetworkStats<SRCW, SWCR> {
+    // The messages of every channel, a message sent in chunks counts once
+    pub fn messages_sent(&self) -> u64 {
+        self.packets.messages_sent()
+    }
+    pub fn messages_received(&self) -> u64 {
+        self.packets.messages_received()
+    }
+    // The frames, pings and pongs included, or the datagrams for the udp socket
+    pub fn packets_sent(&self) -> u64 {
+        self.packets.packets_sent()
+    }
+    pub fn packets_received(&self) -> u64 {
+        self.packets.packets_received()
+    }
+}
+
+// queue
+impl<SRCW: crate::Message, SWCR: crate::Message> NetworkStats<SRCW, SWCR> {
+    // The messages waiting to be sent, the last time the proxy looked
+    pub fn queue_depth(&self) -> usize {
+        self.queue.depth()
+    }
+    pub fn queue_peak_depth(&self) -> usize {
+        self.queue.peak_depth()
+    }
+    // Dropped by OverflowPolicy::DropOldest or OverflowPolicy::DropNewest
+    pub fn dropped_messages(&self) -> u64 {
+        self.queue.dropped()
+    }
+    // How many times the proxy got its connection back, see ReconnectPolicy
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+}
+
+// message types
+impl<SRCW: crate::Message, SWCR: crate::Message> NetworkStats<SRCW, SWCR> {
+    // Empty when StatConfig::message_types is disabled
+    pub fn message_types(&self) -> MessageTypeTable {
+        self.message_types_opt
+            .as_ref()
+            .map(|message_types| message_types.table())
+            .unwrap_or_default()
+    }
+    // The variant of an enum, see networking::stats::message_type_name
+    pub fn message_type(&self, name: &str) -> MessageTypeStats {
+        self.message_types_opt
+            .as_ref()
+            .map(|message_types| message_types.get(name))
+            .unwrap_or_default()
+    }
+}
+
+// channels
+impl<SRCW: crate::Message, SWCR: crate::Message> NetworkStats<SRCW, SWCR> {
+    // The messages of each channel, a message sent in chunks counts once
+    pub fn channel_messages_sent(&self, channel: u8) -> u64 {
+        self.channels.messages_sent(channel)
+    }
+    pub fn channel_messages_received(&self, channel: u8) -> u64 {
+        self.channels.messages_received(channel)
+    }
+    // Payloads only, as they were sent (compressed or not)
+    pub fn channel_bytes_sent(&self, channel: u8) -> u64 {
+        self.channels.bytes_sent(channel)
+    }
+    pub fn channel_bytes_received(&self, channel: u8) -> u64 {
+        self.channels.bytes_received(channel)
+    }
 }
 
 //bps
@@ -138,28 +361,51 @@ impl<SRCW: crate::Message, SWCR: crate::Message> NetworkStats<SRCW, SWCR> {
             .map(|bps| bps.total_sent())
             .unwrap_or(0)
     }
-    pub fn received_last_10_sec(&self) -> u64 {
+    // Over the last BpsConfig::window
+    pub fn received_in_window(&self) -> u64 {
+        self.bps_opt
+            .as_ref()
+            .map(|bps| bps.received_in_window())
+            .unwrap_or(0)
+    }
+    pub fn bps_received(&self) -> u64 {
+        self.bps_opt
+            .as_ref()
+            .map(|bps| bps.bps_received())
+            .unwrap_or(0)
+    }
+    pub fn sent_in_window(&self) -> u64 {
+        self.bps_opt
+            .as_ref()
+            .map(|bps| bps.sent_in_window())
+            .unwrap_or(0)
+    }
+    pub fn bps_sent(&self) -> u64 {
+        self.bps_opt.as_ref().map(|bps| bps.bps_sent()).unwrap_or(0)
+    }
+    // Same as above, but counting the payloads as they were before compression
+    pub fn total_received_uncompressed(&self) -> u64 {
         self.bps_opt
             .as_ref()
-            .map(|bps| bps.received_last_10_sec())
+            .map(|bps| bps.total_received_uncompressed())
             .unwrap_or(0)
     }
-    pub fn bps_received_last_10_sec(&self) -> u64 {
+    pub fn total_sent_uncompressed(&self) -> u64 {
         self.bps_opt
             .as_ref()
-            .map(|bps| bps.bps_received_last_10_sec())
+            .map(|bps| bps.total_sent_uncompressed())
             .unwrap_or(0)
     }
-    pub fn sent_last_10_sec(&self) -> u64 {
+    pub fn received_uncompressed_in_window(&self) -> u64 {
         self.bps_opt
             .as_ref()
-            .map(|bps| bps.sent_last_10_sec())
+            .map(|bps| bps.received_uncompressed_in_window())
             .unwrap_or(0)
     }
-    pub fn bps_sent_last_10_sec(&self) -> u64 {
+    pub fn sent_uncompressed_in_window(&self) -> u64 {
         self.bps_opt
             .as_ref()
-            .map(|bps| bps.bps_sent_last_10_sec())
+            .map(|bps| bps.sent_uncompressed_in_window())
             .unwrap_or(0)
     }
 }
@@ -169,6 +415,11 @@ impl<SRCW: crate::Message, SWCR: crate::Message> Default for NetworkStats<SRCW,
         Self {
             bps_opt: None,
             rtt_opt: None,
+            queue: queue::Queue::default(),
+            channels: channel::Channels::default(),
+            packets: packets::Packets::default(),
+            reconnects: 0,
+            message_types_opt: None,
             srcw: std::marker::PhantomData,
             swcr: std::marker::PhantomData,
             cfg: config::StatConfig::default(),
@@ -181,46 +432,10 @@ fn testing() {
     #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Clone)]
     pub enum ClientMessage {
         Text(String),
-        Ping,
-        Pong,
     }
     #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Clone)]
     pub enum ServerMessage {
         Text(String),
-        Ping,
-        Pong,
-    }
-
-    impl crate::Message for ClientMessage {
-        fn is_ping(&self) -> bool {
-            matches!(self, Self::Ping)
-        }
-        fn is_pong(&self) -> bool {
-            matches!(self, Self::Pong)
-        }
-
-        fn default_ping() -> Self {
-            Self::Ping
-        }
-        fn default_pong() -> Self {
-            Self::Pong
-        }
-    }
-
-    impl crate::Message for ServerMessage {
-        fn is_ping(&self) -> bool {
-            matches!(self, Self::Ping)
-        }
-        fn is_pong(&self) -> bool {
-            matches!(self, Self::Pong)
-        }
-
-        fn default_ping() -> Self {
-            Self::Ping
-        }
-        fn default_pong() -> Self {
-            Self::Pong
-        }
     }
 
     pub struct Testing<R: crate::Message, W: crate::Message> {
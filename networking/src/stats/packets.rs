@@ -0,0 +1,44 @@
+// What went through the connection in each direction, whatever the channel
+#[derive(Clone, Default)]
+pub struct Packets {
+    messages_sent: u64,
+    messages_received: u64,
+    // Every frame, pings and pongs included, or every datagram for the udp socket
+    packets_sent: u64,
+    packets_received: u64,
+}
+
+impl Packets {
+    pub fn on_send(&mut self, header: &crate::socket::Header) {
+        self.packets_sent += 1;
+        if super::channel::is_message(header) {
+            self.messages_sent += 1;
+        }
+    }
+    pub fn on_recv(&mut self, header: &crate::socket::Header) {
+        self.packets_received += 1;
+        if super::channel::is_message(header) {
+            self.messages_received += 1;
+        }
+    }
+    // A datagram can hold acks only, or be a resend, so it's not counted as a message
+    pub fn on_datagram_send(&mut self) {
+        self.packets_sent += 1;
+    }
+    pub fn on_datagram_recv(&mut self) {
+        self.packets_received += 1;
+    }
+
+    pub fn messages_sent(&self) -> u64 {
+        self.messages_sent
+    }
+    pub fn messages_received(&self) -> u64 {
+        self.messages_received
+    }
+    pub fn packets_sent(&self) -> u64 {
+        self.packets_sent
+    }
+    pub fn packets_received(&self) -> u64 {
+        self.packets_received
+    }
+}
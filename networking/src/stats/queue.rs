@@ -0,0 +1,25 @@
+// The controller's queue, as seen by the proxy
+#[derive(Clone, Default)]
+pub struct Queue {
+    depth: usize,
+    peak_depth: usize,
+    dropped: u64,
+}
+
+impl Queue {
+    pub fn update(&mut self, depth: usize, dropped: u64) {
+        self.depth = depth;
+        self.peak_depth = self.peak_depth.max(depth);
+        self.dropped = dropped;
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+    pub fn peak_depth(&self) -> usize {
+        self.peak_depth
+    }
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
@@ -3,19 +3,41 @@ pub struct Rtt {
     pub latest_rtt: std::time::Duration,
     pub ping_request_stopwatch: Option<time::Stopwatch>,
     pub last_pong: std::time::Instant,
+    // Both None until the first sample, see Rtt::set
+    smoothed: Option<std::time::Duration>,
+    variation: Option<std::time::Duration>,
+    // The samples of the window, oldest first
+    samples: std::collections::VecDeque<(std::time::Instant, std::time::Duration)>,
     cfg: super::config::RttConfig,
 }
 
+// The weight of a new sample in the variation, the one of rfc 6298
+const VARIATION_SMOOTHING: f64 = 0.25;
+
 impl Rtt {
     pub fn new(cfg: super::config::RttConfig) -> Self {
         Self {
             latest_rtt: std::time::Duration::ZERO,
             ping_request_stopwatch: None,
             last_pong: std::time::Instant::now(),
+            smoothed: None,
+            variation: None,
+            samples: std::collections::VecDeque::new(),
             cfg,
         }
     }
 
+    pub fn update(&mut self) {
+        let now = std::time::Instant::now();
+        while self
+            .samples
+            .front()
+            .is_some_and(|(time, _)| now.duration_since(*time) > self.cfg.window)
+        {
+            self.samples.pop_front();
+        }
+    }
+
     pub fn needs_ping(&self) -> bool {
         if !self.cfg.enabled {
             return false;
@@ -24,10 +46,79 @@ impl Rtt {
             && self.ping_request_stopwatch.is_none()
     }
 
+    // None while waiting for a pong
+    pub fn until_next_ping(&self) -> Option<std::time::Duration> {
+        if !self.cfg.enabled || self.ping_request_stopwatch.is_some() {
+            return None;
+        }
+        Some(
+            self.cfg
+                .ping_request_delay
+                .saturating_sub(self.last_pong.elapsed()),
+        )
+    }
+
+    // The pong of the ping that was sent on the lost connection will never come
+    pub fn on_disconnect(&mut self) {
+        self.ping_request_stopwatch = None;
+    }
+
+    // Same as tcp's estimator (rfc 6298), the first sample sets the smoothed rtt and half of it is the variation
     pub fn set(&mut self, rtt: std::time::Duration) {
-        self.latest_rtt = rtt
+        self.latest_rtt = rtt;
+
+        let (smoothed, variation) = match (self.smoothed, self.variation) {
+            (Some(smoothed), Some(variation)) => {
+                let alpha = self.cfg.smoothing.clamp(0., 1.);
+                let diff = smoothed.abs_diff(rtt);
+                (
+                    smoothed.mul_f64(1. - alpha) + rtt.mul_f64(alpha),
+                    variation.mul_f64(1. - VARIATION_SMOOTHING) + diff.mul_f64(VARIATION_SMOOTHING),
+                )
+            }
+            _ => (rtt, rtt / 2),
+        };
+        self.smoothed = Some(smoothed);
+        self.variation = Some(variation);
+
+        self.samples.push_back((std::time::Instant::now(), rtt));
+        self.update();
     }
     pub fn get(&self) -> std::time::Duration {
         self.latest_rtt
     }
+    pub fn smoothed(&self) -> std::time::Duration {
+        self.smoothed.unwrap_or(std::time::Duration::ZERO)
+    }
+    pub fn variation(&self) -> std::time::Duration {
+        self.variation.unwrap_or(std::time::Duration::ZERO)
+    }
+    pub fn min(&self) -> std::time::Duration {
+        self.samples
+            .iter()
+            .map(|(_, rtt)| *rtt)
+            .min()
+            .unwrap_or(std::time::Duration::ZERO)
+    }
+    pub fn max(&self) -> std::time::Duration {
+        self.samples
+            .iter()
+            .map(|(_, rtt)| *rtt)
+            .max()
+            .unwrap_or(std::time::Duration::ZERO)
+    }
+    // Nearest rank, the smallest sample that is above `percentile`% of them
+    pub fn percentile(&self, percentile: f64) -> std::time::Duration {
+        let mut sorted = self.samples.iter().map(|(_, rtt)| *rtt).collect::<Vec<_>>();
+        if sorted.is_empty() {
+            return std::time::Duration::ZERO;
+        }
+        sorted.sort_unstable();
+
+        let rank = (percentile.clamp(0., 100.) / 100. * sorted.len() as f64).ceil() as usize;
+        sorted[rank.saturating_sub(1)]
+    }
+    pub fn sample_count(&self) -> usize {
+        self.samples.len()
+    }
 }
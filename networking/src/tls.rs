@@ -0,0 +1,119 @@
+/// Encrypts the connection of a Proxy or of every client of a Server, see [`crate::proxy::ProxyConfig`]
+///
+/// The client side needs the certificates it trusts and the name of the server,
+/// the server side needs its certificate chain and private key
+#[derive(Debug, Clone)]
+pub enum TlsConfig {
+    #[cfg(feature = "tls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+    Client {
+        config: std::sync::Arc<rustls::ClientConfig>,
+        server_name: rustls::pki_types::ServerName<'static>,
+    },
+    #[cfg(feature = "tls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+    Server {
+        config: std::sync::Arc<rustls::ServerConfig>,
+    },
+}
+
+#[cfg(feature = "tls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+#[derive(thiserror::Error, Debug)]
+pub enum TlsError {
+    #[error("{0}")]
+    Rustls(#[from] rustls::Error),
+    #[error("Invalid server name: {0}")]
+    ServerName(#[from] rustls::pki_types::InvalidDnsNameError),
+    #[error("Could not build the client certificate verifier: {0}")]
+    ClientVerifier(#[from] rustls::server::VerifierBuilderError),
+}
+
+#[cfg(feature = "tls")]
+impl TlsConfig {
+    /// Connects to a server that has a certificate signed by one of the roots
+    pub fn client(roots: rustls::RootCertStore, server_name: &str) -> Result<Self, TlsError> {
+        let config = rustls::ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        Self::new_client(config, server_name)
+    }
+
+    /// Same as [`TlsConfig::client`], but also presents a certificate to the server
+    pub fn client_with_cert(
+        roots: rustls::RootCertStore,
+        server_name: &str,
+        cert_chain: Vec<rustls::pki_types::CertificateDer<'static>>,
+        key: rustls::pki_types::PrivateKeyDer<'static>,
+    ) -> Result<Self, TlsError> {
+        let config = rustls::ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_client_auth_cert(cert_chain, key)?;
+
+        Self::new_client(config, server_name)
+    }
+
+    /// Accepts any client
+    pub fn server(
+        cert_chain: Vec<rustls::pki_types::CertificateDer<'static>>,
+        key: rustls::pki_types::PrivateKeyDer<'static>,
+    ) -> Result<Self, TlsError> {
+        let config = rustls::ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(cert_chain, key)?;
+
+        Ok(Self::Server {
+            config: std::sync::Arc::new(config),
+        })
+    }
+
+    /// Only accepts the clients that present a certificate signed by one of the client_roots
+    pub fn server_with_client_auth(
+        cert_chain: Vec<rustls::pki_types::CertificateDer<'static>>,
+        key: rustls::pki_types::PrivateKeyDer<'static>,
+        client_roots: rustls::RootCertStore,
+    ) -> Result<Self, TlsError> {
+        let verifier = rustls::server::WebPkiClientVerifier::builder_with_provider(
+            std::sync::Arc::new(client_roots),
+            provider(),
+        )
+        .build()?;
+
+        let config = rustls::ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(cert_chain, key)?;
+
+        Ok(Self::Server {
+            config: std::sync::Arc::new(config),
+        })
+    }
+
+    fn new_client(config: rustls::ClientConfig, server_name: &str) -> Result<Self, TlsError> {
+        Ok(Self::Client {
+            config: std::sync::Arc::new(config),
+            server_name: rustls::pki_types::ServerName::try_from(server_name)?.to_owned(),
+        })
+    }
+
+    // A fresh session, every connection needs its own
+    pub(crate) fn connection(&self) -> Result<rustls::Connection, TlsError> {
+        Ok(match self {
+            TlsConfig::Client {
+                config,
+                server_name,
+            } => rustls::ClientConnection::new(config.clone(), server_name.clone())?.into(),
+            TlsConfig::Server { config } => rustls::ServerConnection::new(config.clone())?.into(),
+        })
+    }
+}
+
+// Explicit, so it doesn't depend on the process-wide default provider
+#[cfg(feature = "tls")]
+fn provider() -> std::sync::Arc<rustls::crypto::CryptoProvider> {
+    std::sync::Arc::new(rustls::crypto::ring::default_provider())
+}
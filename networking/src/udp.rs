@@ -0,0 +1,368 @@
+mod error;
+mod packet;
+
+pub use error::UdpError;
+pub use packet::{Delivery, PacketHeader, PacketKind, PACKET_HEADER_SIZE};
+
+/// The biggest datagram a udp socket sends by default, see [`UdpSocket::set_max_datagram_size`]
+///
+/// Small enough to not be fragmented on most networks
+pub const DEFAULT_MAX_DATAGRAM_SIZE: u64 = 1200;
+
+// Used to resend the reliable packets before the first ack gives us a round trip time
+const DEFAULT_RESEND_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+const MIN_RESEND_DELAY: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// How long a reliable packet is resent for before [`UdpError::AckTimeout`], see [`UdpSocket::set_ack_timeout`]
+pub const DEFAULT_ACK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+// The reliable packets further ahead than that are dropped without an ack, so the ones held back for the order
+// can't pile up, the other side resends them once the ones before them are given
+const RELIABLE_WINDOW: u32 = 1024;
+
+// The biggest payload of an udp datagram, anything smaller could truncate what the other side sends
+const RECV_BUFFER_SIZE: usize = 65_535;
+
+// A reliable packet that has not been acked yet
+struct Pending {
+    bytes: Vec<u8>,
+    first_sent: std::time::Instant,
+    last_sent: std::time::Instant,
+    sends: u32,
+}
+
+/// Same api as [`crate::Socket`], but over udp, with a [`Delivery`] mode for each message
+///
+/// The given socket has to be connected to the other side, and non-blocking for the try_ functions to not block.
+/// There is no handshake, ping or close, the round trip time comes from the acks of the reliable packets.
+/// Call [`UdpSocket::update`] regularly so the reliable packets are resent even when nothing is received
+pub struct UdpSocket<R: crate::Message, W: crate::Message, C: crate::Codec = crate::codec::Bincode>
+{
+    socket: std::net::UdpSocket,
+    read_type: std::marker::PhantomData<R>,
+    write_type: std::marker::PhantomData<W>,
+    codec: std::marker::PhantomData<C>,
+
+    recv_buffer: Vec<u8>,
+    max_datagram_size: u64,
+    ack_timeout: std::time::Duration,
+
+    next_sequenced: u32,
+    last_sequenced: Option<u32>,
+
+    next_reliable: u32,
+    reliable_pending: std::collections::BTreeMap<u32, Pending>,
+
+    // Reliable packets are only given once every packet before them has been
+    next_expected_reliable: u32,
+    out_of_order: std::collections::BTreeMap<u32, Vec<u8>>,
+    ready: std::collections::VecDeque<(PacketHeader, Vec<u8>)>,
+
+    rtt: Option<std::time::Duration>,
+    stats: crate::NetworkStats<R, W>,
+}
+
+impl<R: crate::Message, W: crate::Message, C: crate::Codec> UdpSocket<R, W, C> {
+    pub fn new(socket: std::net::UdpSocket, stat_cfg: crate::stats::StatConfig) -> Self {
+        Self {
+            socket,
+            read_type: std::marker::PhantomData,
+            write_type: std::marker::PhantomData,
+            codec: std::marker::PhantomData,
+            recv_buffer: vec![0; RECV_BUFFER_SIZE],
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE,
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            next_sequenced: 0,
+            last_sequenced: None,
+            next_reliable: 0,
+            reliable_pending: std::collections::BTreeMap::new(),
+            next_expected_reliable: 0,
+            out_of_order: std::collections::BTreeMap::new(),
+            ready: std::collections::VecDeque::new(),
+            rtt: None,
+            stats: crate::NetworkStats::new(stat_cfg),
+        }
+    }
+
+    /// Messages that would give a bigger datagram are refused by [`UdpSocket::send`]
+    pub fn set_max_datagram_size(&mut self, max_datagram_size: u64) {
+        self.max_datagram_size = max_datagram_size
+    }
+
+    pub fn max_datagram_size(&self) -> u64 {
+        self.max_datagram_size
+    }
+
+    /// A reliable packet that is still not acked after that long gives an [`UdpError::AckTimeout`],
+    /// the other side is considered gone
+    pub fn set_ack_timeout(&mut self, ack_timeout: std::time::Duration) {
+        self.ack_timeout = ack_timeout
+    }
+
+    pub fn ack_timeout(&self) -> std::time::Duration {
+        self.ack_timeout
+    }
+
+    pub fn send(&mut self, message: W, delivery: Delivery) -> Result<PacketHeader, UdpError> {
+        let message_bytes = C::encode(&message).map_err(UdpError::Serialization)?;
+
+        let size = PACKET_HEADER_SIZE + message_bytes.len() as u64;
+        if size > self.max_datagram_size {
+            return Err(UdpError::PacketTooLarge {
+                size,
+                max: self.max_datagram_size,
+            });
+        }
+
+        let sequence = match delivery {
+            Delivery::Unreliable => 0,
+            Delivery::UnreliableSequenced => {
+                let sequence = self.next_sequenced;
+                self.next_sequenced = self.next_sequenced.wrapping_add(1);
+                sequence
+            }
+            Delivery::ReliableOrdered => {
+                let sequence = self.next_reliable;
+                self.next_reliable = self.next_reliable.wrapping_add(1);
+                sequence
+            }
+        };
+
+        let header = PacketHeader {
+            kind: delivery.into(),
+            sequence,
+        };
+
+        let mut bytes = bincode::serialize(&header).map_err(|e| UdpError::Serialization(e))?;
+        bytes.extend_from_slice(&message_bytes);
+        trace!("Sending {:?}:  {:?}", message, message_bytes);
+
+        match self.send_datagram(&bytes) {
+            Ok(()) => (),
+            // It will be resent anyway
+            Err(UdpError::Send(ref e))
+                if delivery == Delivery::ReliableOrdered
+                    && e.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+
+        if delivery == Delivery::ReliableOrdered {
+            let now = std::time::Instant::now();
+            self.reliable_pending.insert(
+                sequence,
+                Pending {
+                    bytes,
+                    first_sent: now,
+                    last_sent: now,
+                    sends: 1,
+                },
+            );
+        }
+
+        Ok(header)
+    }
+
+    fn send_datagram(&mut self, bytes: &[u8]) -> Result<(), UdpError> {
+        self.socket.send(bytes).map_err(UdpError::Send)?;
+        self.stats.on_datagram_send(bytes.len() as u64);
+        Ok(())
+    }
+
+    /// Resends the reliable packets that have not been acked in time, and updates the stats
+    ///
+    /// Gives an [`UdpError::AckTimeout`] once one of them has been resent for too long
+    pub fn update(&mut self) -> Result<(), UdpError> {
+        self.resend()?;
+        self.stats.update_windows();
+        Ok(())
+    }
+
+    fn resend(&mut self) -> Result<(), UdpError> {
+        let delay = self
+            .rtt
+            .map(|rtt| (rtt * 2).max(MIN_RESEND_DELAY))
+            .unwrap_or(DEFAULT_RESEND_DELAY);
+
+        for (sequence, pending) in self.reliable_pending.iter_mut() {
+            if pending.first_sent.elapsed() >= self.ack_timeout {
+                return Err(UdpError::AckTimeout {
+                    sequence: *sequence,
+                    timeout: self.ack_timeout,
+                });
+            }
+            if pending.last_sent.elapsed() < delay {
+                continue;
+            }
+
+            match self.socket.send(&pending.bytes) {
+                Ok(_) => self.stats.on_datagram_send(pending.bytes.len() as u64),
+                // The os buffer is full, the rest will be resent later
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(UdpError::Send(e)),
+            }
+
+            pending.last_sent = std::time::Instant::now();
+            pending.sends += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Receives the next message, resending the reliable packets that need it first
+    ///
+    /// Acks are sent and received here, a WouldBlock error means that nothing is left to read
+    pub fn try_recv(&mut self) -> Result<(PacketHeader, R), UdpError> {
+        self.resend()?;
+
+        loop {
+            if let Some((header, payload)) = self.ready.pop_front() {
+                return Ok((header, Self::decode(&payload)?));
+            }
+
+            let len = self
+                .socket
+                .recv(&mut self.recv_buffer)
+                .map_err(UdpError::Recv)?;
+            self.stats.on_datagram_recv(len as u64);
+
+            if (len as u64) < PACKET_HEADER_SIZE {
+                return Err(UdpError::Deserialization(
+                    format!("The datagram is {len} bytes, smaller than a packet header").into(),
+                ));
+            }
+
+            let header: PacketHeader =
+                bincode::deserialize(&self.recv_buffer[..PACKET_HEADER_SIZE as usize])
+                    .map_err(|e| UdpError::Deserialization(e))?;
+            let payload = &self.recv_buffer[PACKET_HEADER_SIZE as usize..len];
+
+            match header.kind {
+                PacketKind::Unreliable => return Ok((header, Self::decode(payload)?)),
+                PacketKind::Sequenced => {
+                    if self
+                        .last_sequenced
+                        .is_some_and(|last| !packet::is_newer(header.sequence, last))
+                    {
+                        trace!("Dropping the old sequenced packet {}", header.sequence);
+                        continue;
+                    }
+                    self.last_sequenced = Some(header.sequence);
+                    return Ok((header, Self::decode(payload)?));
+                }
+                PacketKind::Reliable => {
+                    if packet::is_newer(
+                        header.sequence,
+                        self.next_expected_reliable
+                            .wrapping_add(RELIABLE_WINDOW - 1),
+                    ) {
+                        trace!(
+                            "Dropping the reliable packet {}, too far ahead",
+                            header.sequence
+                        );
+                        continue;
+                    }
+                    let payload = payload.to_vec();
+                    self.ack(header.sequence)?;
+                    self.on_reliable(header, payload);
+                }
+                PacketKind::Ack => self.on_ack(header.sequence),
+            }
+        }
+    }
+
+    pub fn recv(
+        &mut self,
+        check_delay: std::time::Duration,
+    ) -> Result<(PacketHeader, R), UdpError> {
+        loop {
+            match self.try_recv() {
+                Ok(t) => return Ok(t),
+                Err(UdpError::Recv(ref io_e)) if io_e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+
+            spin_sleep::sleep(check_delay);
+        }
+    }
+
+    fn decode(payload: &[u8]) -> Result<R, UdpError> {
+        let message: R = C::decode(payload).map_err(UdpError::Deserialization)?;
+        trace!("Deserializing message.. Done, {message:?}");
+        Ok(message)
+    }
+
+    // Every copy is acked, the previous ack might have been lost
+    fn ack(&mut self, sequence: u32) -> Result<(), UdpError> {
+        let header = PacketHeader {
+            kind: PacketKind::Ack,
+            sequence,
+        };
+        let bytes = bincode::serialize(&header).map_err(|e| UdpError::Serialization(e))?;
+
+        match self.send_datagram(&bytes) {
+            // The packet will be resent, and acked then
+            Err(UdpError::Send(ref e)) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(()),
+            res => res,
+        }
+    }
+
+    fn on_reliable(&mut self, header: PacketHeader, payload: Vec<u8>) {
+        if header.sequence != self.next_expected_reliable {
+            // Already given, or waiting for the ones before it
+            if packet::is_newer(header.sequence, self.next_expected_reliable) {
+                self.out_of_order.entry(header.sequence).or_insert(payload);
+            }
+            return;
+        }
+
+        self.ready.push_back((header, payload));
+        self.next_expected_reliable = self.next_expected_reliable.wrapping_add(1);
+
+        while let Some(payload) = self.out_of_order.remove(&self.next_expected_reliable) {
+            self.ready.push_back((
+                PacketHeader {
+                    kind: PacketKind::Reliable,
+                    sequence: self.next_expected_reliable,
+                },
+                payload,
+            ));
+            self.next_expected_reliable = self.next_expected_reliable.wrapping_add(1);
+        }
+    }
+
+    fn on_ack(&mut self, sequence: u32) {
+        let Some(pending) = self.reliable_pending.remove(&sequence) else {
+            // Ack of a resent packet, the first ack already removed it
+            return;
+        };
+
+        // A resent packet doesn't tell which send is acked
+        if pending.sends == 1 {
+            let rtt = pending.last_sent.elapsed();
+            self.rtt = Some(rtt);
+            self.stats.set_rtt(rtt);
+        }
+    }
+
+    /// The latest round trip time, measured on the acks of the reliable packets
+    pub fn rtt(&self) -> Option<std::time::Duration> {
+        self.rtt
+    }
+
+    pub fn stats(&self) -> &crate::NetworkStats<R, W> {
+        &self.stats
+    }
+
+    /// The number of reliable packets that have not been acked yet
+    pub fn pending_reliable(&self) -> usize {
+        self.reliable_pending.len()
+    }
+
+    pub fn local_addr(&self) -> std::net::SocketAddr {
+        self.socket.local_addr().unwrap()
+    }
+
+    pub fn remote_addr(&self) -> std::net::SocketAddr {
+        self.socket.peer_addr().unwrap()
+    }
+}
@@ -0,0 +1,19 @@
+#[derive(thiserror::Error, Debug)]
+pub enum UdpError {
+    #[error("Error when serializing: {0}")]
+    Serialization(crate::codec::CodecError),
+    #[error("Error when deserializing: {0}")]
+    Deserialization(crate::codec::CodecError),
+    #[error("Error when sending a datagram: {0}")]
+    Send(std::io::Error),
+    #[error("Error when receiving a datagram: {0}")]
+    Recv(std::io::Error),
+
+    #[error("The packet is {size} bytes, the maximum is {max}")]
+    PacketTooLarge { size: u64, max: u64 },
+    #[error("The reliable packet {sequence} was not acked after {timeout:?}")]
+    AckTimeout {
+        sequence: u32,
+        timeout: std::time::Duration,
+    },
+}
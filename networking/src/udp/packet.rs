@@ -0,0 +1,47 @@
+// bincode writes the kind's variant index as an u32, then the sequence as an u32
+pub const PACKET_HEADER_SIZE: u64 = 4 + 4;
+
+/// How a message sent through a [`crate::udp::UdpSocket`] is delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Sent once, it can be lost, duplicated or received out of order
+    Unreliable,
+    /// Sent once, it can be lost, but it's dropped if a newer sequenced message has already been received
+    UnreliableSequenced,
+    /// Resent until acked, for up to the ack timeout of the socket, received exactly once and in the order it was sent
+    ReliableOrdered,
+}
+
+/// What a datagram is carrying
+///
+/// Acks have no payload, their sequence is the one of the reliable packet they acknowledge
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketKind {
+    Unreliable,
+    Sequenced,
+    Reliable,
+    Ack,
+}
+
+// Every datagram starts with it, like the frame header it always goes through bincode
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketHeader {
+    pub kind: PacketKind,
+    // Each kind has its own sequence, 0 for unreliable packets
+    pub sequence: u32,
+}
+
+impl From<Delivery> for PacketKind {
+    fn from(delivery: Delivery) -> Self {
+        match delivery {
+            Delivery::Unreliable => PacketKind::Unreliable,
+            Delivery::UnreliableSequenced => PacketKind::Sequenced,
+            Delivery::ReliableOrdered => PacketKind::Reliable,
+        }
+    }
+}
+
+// Sequences wrap around, a is newer than b if it's less than half the range ahead
+pub(crate) fn is_newer(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
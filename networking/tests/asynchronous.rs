@@ -0,0 +1,474 @@
+#![cfg(feature = "async")]
+
+use {
+    networking::{
+        proxy::{DisconnectReason, ProxyMessage},
+        server::ServerEvent,
+    },
+    std::str::FromStr as _,
+};
+
+mod common;
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+enum Message {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+fn start_server() -> networking::server::ServerController<Message, Message> {
+    let server_cfg = networking::server::ServerConfig {
+        addr: std::net::SocketAddr::from_str("127.0.0.1:0").unwrap(),
+        run_tps: 100,
+        stat_cfg: Default::default(),
+        max_frame_size: networking::socket::DEFAULT_MAX_FRAME_SIZE,
+        compression: None,
+        channels: Default::default(),
+        tcp: Default::default(),
+        heartbeat: None,
+        tls: None,
+    };
+
+    networking::Server::start_new(server_cfg).unwrap()
+}
+
+fn proxy_cfg(addr: std::net::SocketAddr) -> networking::proxy::ProxyConfig {
+    networking::proxy::ProxyConfig {
+        // The rate of the stats updates and pings for the async proxy
+        run_tps: Some(100),
+        stat_cfg: networking::stats::StatConfig {
+            bps: networking::stats::config::BpsConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            rtt: networking::stats::config::RttConfig {
+                enabled: true,
+                ping_request_delay: std::time::Duration::from_millis(10),
+                ..Default::default()
+            },
+            message_types: Default::default(),
+        },
+        ..networking::proxy::ProxyConfig::new(addr)
+    }
+}
+
+// The sync side runs in its own thread, like it would in another program
+#[tokio::test(flavor = "multi_thread")]
+async fn interop() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let sync_side = std::thread::spawn(move || {
+        const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+
+        let stream = std::net::TcpStream::connect(addr).unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut socket: networking::Socket<Message, Message> = networking::Socket::new(stream);
+        socket.handshake(TIMEOUT).unwrap();
+
+        socket.ping().unwrap();
+        socket.send(Message::Text(String::from("Hi"))).unwrap();
+
+        // Echoes what it receives, compressed or not
+        for _ in 0..2 {
+            let (_, message) = socket.recv(CHECK_DELAY).unwrap();
+            socket.send(message).unwrap();
+        }
+    });
+
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut socket: networking::AsyncSocket<Message, Message> =
+        networking::AsyncSocket::new(stream);
+    socket.handshake(TIMEOUT).await.unwrap();
+
+    // The ping is answered by recv
+    let (_, message) = socket.recv().await.unwrap();
+    assert_eq!(message, Message::Text(String::from("Hi")));
+
+    #[cfg(feature = "lz4")]
+    socket.set_compression(Some(networking::Compression::Lz4 { threshold: 1024 }));
+
+    for message in [
+        Message::Text(String::from("Hello")),
+        Message::Bytes(vec![42; 64 * 1024]),
+    ] {
+        socket.send(message.clone()).await.unwrap();
+        assert_eq!(socket.recv().await.unwrap().1, message);
+    }
+
+    sync_side.join().unwrap();
+
+    // The sync socket sends a close frame when it's dropped
+    assert!(matches!(
+        socket.recv().await,
+        Err(networking::socket::SocketError::Exited)
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn proxy() {
+    let server = start_server();
+
+    let mut client =
+        networking::AsyncProxy::<Message, Message>::start_new(proxy_cfg(server.local_addr()), None);
+
+    let ServerEvent::Connected(id) = server.recv_timeout(TIMEOUT).unwrap() else {
+        panic!("Expected the client to connect")
+    };
+
+    // Messages sent before the end of the handshakes would be dropped
+    assert_eq!(
+        tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+        Some(ProxyMessage::Connected {
+            addr: server.local_addr()
+        })
+    );
+    assert!(client.is_connected());
+
+    client
+        .send(Message::Text(String::from("Hi")))
+        .await
+        .unwrap();
+    assert_eq!(
+        server.recv_timeout(TIMEOUT).unwrap(),
+        ServerEvent::Message(id, Message::Text(String::from("Hi")))
+    );
+
+    server
+        .send_to(id, Message::Text(String::from("Hello")))
+        .unwrap();
+    assert_eq!(
+        tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+        Some(ProxyMessage::Forward(Message::Text(String::from("Hello"))))
+    );
+
+    // A few pings, and a few stats updates
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let stats = client.stats();
+    assert!(stats.get_rtt() > std::time::Duration::ZERO);
+    assert!(stats.total_sent() > 0);
+    assert!(stats.total_received() > 0);
+
+    // The proxy exits once the server is gone
+    drop(server);
+    assert_eq!(
+        tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+        Some(ProxyMessage::Disconnected {
+            reason: DisconnectReason::Closed
+        })
+    );
+    assert_eq!(
+        tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+        Some(ProxyMessage::Exit)
+    );
+    assert!(!client.is_running());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn handshake_mismatch() {
+    use networking::{
+        proxy::ProxyError,
+        socket::{HandshakeError, SocketError},
+    };
+
+    let server = start_server();
+
+    let mut client =
+        networking::AsyncProxy::<String, Message>::start_new(proxy_cfg(server.local_addr()), None);
+
+    assert!(matches!(
+        tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+        Some(ProxyMessage::Error(ProxyError::Handshake {
+            error: SocketError::Handshake(HandshakeError::Messages { .. }),
+            ..
+        }))
+    ));
+    assert_eq!(
+        tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+        Some(ProxyMessage::Exit)
+    );
+    assert!(!client.is_connected());
+}
+
+// Only a mismatch stops the proxy, a handshake cut short is retried
+#[tokio::test(flavor = "multi_thread")]
+async fn handshake_reconnect() {
+    use networking::proxy::{Backoff, ProxyError, ReconnectPolicy};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut client = networking::AsyncProxy::<Message, Message>::start_new(
+        networking::proxy::ProxyConfig {
+            reconnect: Some(ReconnectPolicy {
+                backoff: Backoff::Fixed(std::time::Duration::from_millis(20)),
+                max_attempts: Some(3),
+                give_up_after: None,
+                seed: None,
+            }),
+            ..proxy_cfg(addr)
+        },
+        None,
+    );
+
+    let peer = std::thread::spawn(move || {
+        let (raw, _) = listener.accept().unwrap();
+        drop(raw);
+
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut socket: networking::Socket<Message, Message> = networking::Socket::new(stream);
+        socket.handshake(TIMEOUT).unwrap();
+        socket
+    });
+
+    assert!(matches!(
+        tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+        Some(ProxyMessage::Disconnected {
+            reason: DisconnectReason::Error(ProxyError::Handshake { .. })
+        })
+    ));
+    assert_eq!(
+        tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+        Some(ProxyMessage::Reconnecting { attempt: 1 })
+    );
+    assert_eq!(
+        tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+        Some(ProxyMessage::Connected { addr })
+    );
+    assert_eq!(
+        tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+        Some(ProxyMessage::Reconnected)
+    );
+    let socket = tokio::task::spawn_blocking(move || peer.join().unwrap())
+        .await
+        .unwrap();
+
+    assert!(client.is_connected());
+    drop(socket);
+}
+
+// Two proxies send more than the os buffers can hold to each other, neither waits for its writes to read
+#[tokio::test(flavor = "multi_thread")]
+async fn both_send() {
+    const COUNT: usize = 32;
+    const SIZE: usize = 256 * 1024;
+
+    async fn receive(
+        client: &mut networking::asynchronous::AsyncProxyController<Message, Message>,
+    ) {
+        let mut received = 0;
+        while received != COUNT {
+            match tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap() {
+                Some(ProxyMessage::Forward(Message::Bytes(bytes))) => {
+                    assert_eq!(bytes.len(), SIZE);
+                    received += 1;
+                }
+                other => panic!("{other:?}"),
+            }
+        }
+    }
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut client = networking::AsyncProxy::<Message, Message>::start_new(proxy_cfg(addr), None);
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut server =
+        networking::AsyncProxy::<Message, Message>::start_new(proxy_cfg(addr), Some(stream));
+
+    // Messages sent before the end of the handshakes would be dropped
+    for proxy in [&mut client, &mut server] {
+        assert!(matches!(
+            tokio::time::timeout(TIMEOUT, proxy.recv()).await.unwrap(),
+            Some(ProxyMessage::Connected { .. })
+        ));
+    }
+
+    for _ in 0..COUNT {
+        client.send(Message::Bytes(vec![1; SIZE])).await.unwrap();
+        server.send(Message::Bytes(vec![2; SIZE])).await.unwrap();
+    }
+
+    tokio::join!(receive(&mut client), receive(&mut server));
+}
+
+// What the async proxy can't do is refused before it connects
+#[tokio::test(flavor = "multi_thread")]
+async fn unsupported_config() {
+    use networking::proxy::{OverflowPolicy, ProxyError, QueueConfig};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    for cfg in [
+        networking::proxy::ProxyConfig {
+            queue: QueueConfig {
+                capacity: 0,
+                ..Default::default()
+            },
+            ..proxy_cfg(addr)
+        },
+        networking::proxy::ProxyConfig {
+            queue: QueueConfig {
+                overflow: OverflowPolicy::DropOldest,
+                ..Default::default()
+            },
+            ..proxy_cfg(addr)
+        },
+        networking::proxy::ProxyConfig {
+            channels: networking::socket::ChannelConfig {
+                priorities: vec![0, 1],
+                ..Default::default()
+            },
+            ..proxy_cfg(addr)
+        },
+    ] {
+        let mut client = networking::AsyncProxy::<Message, Message>::start_new(cfg, None);
+
+        assert!(matches!(
+            tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+            Some(ProxyMessage::Error(ProxyError::Config(_)))
+        ));
+        assert_eq!(
+            tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+            Some(ProxyMessage::Exit)
+        );
+    }
+
+    // It never tried to connect
+    listener.set_nonblocking(true).unwrap();
+    assert!(listener.accept().is_err());
+}
+
+// The messages of the controller are bounded by the queue of the config
+#[tokio::test(flavor = "multi_thread")]
+async fn queue() {
+    use networking::proxy::{OverflowPolicy, QueueConfig, SendError};
+
+    // The peer never does the handshake, so the proxy never takes anything from the queue
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    for overflow in [OverflowPolicy::Error, OverflowPolicy::DropNewest] {
+        let client = networking::AsyncProxy::<Message, Message>::start_new(
+            networking::proxy::ProxyConfig {
+                queue: QueueConfig {
+                    capacity: 2,
+                    overflow,
+                },
+                ..proxy_cfg(addr)
+            },
+            None,
+        );
+        let _stream = listener.accept().unwrap();
+
+        let message = Message::Text(String::from("Hi"));
+        client.send(message.clone()).await.unwrap();
+        client.try_send(message.clone()).unwrap();
+        assert_eq!(
+            client.try_send(message.clone()),
+            Err(SendError::Full(message.clone()))
+        );
+
+        // Dropped or refused
+        let res = client.send(message.clone()).await;
+        match overflow {
+            OverflowPolicy::Error => assert_eq!(res, Err(SendError::Full(message))),
+            _ => assert_eq!(res, Ok(())),
+        }
+    }
+}
+
+// The pongs of the heartbeat aren't taken for the ones of the rtt
+#[tokio::test(flavor = "multi_thread")]
+async fn heartbeat_rtt() {
+    const DELAY: std::time::Duration = std::time::Duration::from_millis(50);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut client = networking::AsyncProxy::<Message, Message>::start_new(
+        networking::proxy::ProxyConfig {
+            heartbeat: Some(networking::proxy::HeartbeatConfig {
+                interval: std::time::Duration::from_millis(20),
+                timeout: std::time::Duration::from_secs(1),
+            }),
+            ..proxy_cfg(addr)
+        },
+        None,
+    );
+
+    let peer = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut socket: networking::Socket<Message, Message> = networking::Socket::new(stream);
+        socket.handshake(TIMEOUT).unwrap();
+        common::slow_pongs(&mut socket, DELAY, std::time::Duration::from_secs(1));
+        socket
+    });
+
+    assert_eq!(
+        tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+        Some(ProxyMessage::Connected { addr })
+    );
+    let socket = tokio::task::spawn_blocking(move || peer.join().unwrap())
+        .await
+        .unwrap();
+
+    assert!(client.is_connected());
+    let stats = client.stats();
+    assert!(
+        stats.rtt_sample_count() >= 3,
+        "{}",
+        stats.rtt_sample_count()
+    );
+    assert!(stats.min_rtt() >= DELAY, "{:?}", stats.min_rtt());
+    drop(socket);
+}
+
+// The peer does the handshake and then never reads again
+#[tokio::test(flavor = "multi_thread")]
+async fn heartbeat() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut client = networking::AsyncProxy::<Message, Message>::start_new(
+        networking::proxy::ProxyConfig {
+            heartbeat: Some(networking::proxy::HeartbeatConfig {
+                interval: std::time::Duration::from_millis(50),
+                timeout: std::time::Duration::from_millis(300),
+            }),
+            ..proxy_cfg(addr)
+        },
+        None,
+    );
+
+    let frozen = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut socket: networking::Socket<Message, Message> = networking::Socket::new(stream);
+        socket.handshake(TIMEOUT).unwrap();
+        socket
+    });
+
+    assert_eq!(
+        tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+        Some(ProxyMessage::Connected { addr })
+    );
+    assert_eq!(
+        tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+        Some(ProxyMessage::Disconnected {
+            reason: DisconnectReason::TimedOut
+        })
+    );
+    assert_eq!(
+        tokio::time::timeout(TIMEOUT, client.recv()).await.unwrap(),
+        Some(ProxyMessage::Exit)
+    );
+
+    drop(frozen.join().unwrap());
+}
@@ -0,0 +1,212 @@
+mod common;
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+enum Message {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[test]
+fn priority() {
+    use networking::socket::{ChannelConfig, FrameKind};
+
+    // Way bigger than the os buffers, most of it is still queued when the small one is sent
+    const SIZE: usize = 8 * 1024 * 1024;
+
+    let (mut client, mut server) = common::loopback_pair::<Message, Message>();
+    client.set_channels(&ChannelConfig {
+        priorities: vec![0, 1],
+        ..Default::default()
+    });
+
+    let big = Message::Bytes(vec![42; SIZE]);
+    let header = client.send(big.clone()).unwrap();
+    // The header is the one of the whole message
+    assert!(header.size > SIZE as u64);
+    assert_ne!(client.pending_bytes(), 0);
+
+    let small = Message::Text(String::from("Move"));
+    let header = client.send_on(1, small.clone()).unwrap();
+    assert_eq!(header.channel, 1);
+
+    let mut received = Vec::new();
+    let start = std::time::Instant::now();
+    while received.len() != 2 {
+        assert!(start.elapsed() < TIMEOUT, "The messages never came");
+
+        client.flush().unwrap();
+        match server.try_recv() {
+            Ok((header, message)) => {
+                assert_eq!(header.kind, FrameKind::Data);
+                received.push((header.channel, message));
+            }
+            Err(e) if common::is_would_block(&e) => std::thread::sleep(CHECK_DELAY),
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    // The small one went between the chunks of the big one
+    assert_eq!(received, vec![(1, small), (0, big)]);
+}
+
+#[test]
+fn chunks() {
+    use networking::socket::{ChannelConfig, SocketError};
+
+    let (mut client, mut server) = common::loopback_pair::<Message, Message>();
+    client.set_channels(&ChannelConfig {
+        priorities: vec![0, 0],
+        chunk_size: 100,
+    });
+
+    // Both channels take turns, the receiver puts each message back together
+    let first = Message::Bytes((0..10_000).map(|i| i as u8).collect());
+    let second = Message::Bytes((0..5_000).map(|i| (i * 7) as u8).collect());
+    client.queue_on(0, first.clone()).unwrap();
+    client.queue_on(1, second.clone()).unwrap();
+    client.flush().unwrap();
+
+    let (header, received) = server.recv(CHECK_DELAY).unwrap();
+    assert_eq!((header.channel, received), (1, second));
+    let (header, received) = server.recv(CHECK_DELAY).unwrap();
+    assert_eq!((header.channel, received), (0, first));
+
+    // The chunks of a message are not allowed to add up to more than the maximum
+    server.set_max_frame_size(1000);
+    client.send(Message::Bytes(vec![0; 2000])).unwrap();
+    assert!(matches!(
+        server.recv(CHECK_DELAY),
+        Err(SocketError::FrameTooLarge { .. })
+    ));
+
+    assert!(matches!(
+        client.send_on(2, Message::Text(String::from("Hi"))),
+        Err(SocketError::UnknownChannel(2))
+    ));
+}
+
+// The rest of a message that was too large is skipped, the next one on its channel is read as usual
+#[test]
+fn oversized_chunks() {
+    use networking::socket::{ChannelConfig, SocketError};
+
+    let (mut client, mut server) = common::loopback_pair::<Message, Message>();
+    client.set_channels(&ChannelConfig {
+        priorities: vec![0],
+        chunk_size: 100,
+    });
+    server.set_max_frame_size(1000);
+
+    let next = Message::Bytes((0..500).map(|i| i as u8).collect());
+    client.queue(Message::Bytes(vec![7; 2000])).unwrap();
+    client.queue(next.clone()).unwrap();
+    client.flush().unwrap();
+
+    assert!(matches!(
+        server.recv(CHECK_DELAY),
+        Err(SocketError::FrameTooLarge { max: 1000, .. })
+    ));
+    let (header, received) = server.recv(CHECK_DELAY).unwrap();
+    assert_eq!((header.channel, received), (0, next));
+}
+
+// Chunks that never end, on channels the receiver doesn't send on, can't pile up
+#[test]
+fn unknown_channel_chunks() {
+    use {
+        networking::socket::{FrameKind, Header, SocketError, HEADER_SIZE},
+        std::io::Write as _,
+    };
+
+    let (mut raw, mut server) = common::raw_pair::<Message, Message>();
+    server.set_max_frame_size(1000);
+
+    // 100 bytes on each of the 255 channels the server doesn't have
+    for channel in 1..=u8::MAX {
+        let header = Header {
+            size: 100,
+            kind: FrameKind::Chunk,
+            compressed: false,
+            request_id: 0,
+            channel,
+            uncompressed_size: 100,
+        };
+        let mut frame = bincode::serialize(&header).unwrap();
+        assert_eq!(frame.len(), HEADER_SIZE as usize);
+        frame.extend_from_slice(&[0; 100]);
+        raw.write_all(&frame).unwrap();
+    }
+
+    // The eleventh chunk is refused, like the ones after it
+    let mut refused = 0;
+    let start = std::time::Instant::now();
+    while refused < 245 {
+        assert!(start.elapsed() < TIMEOUT, "The chunks never came");
+        match server.try_recv() {
+            Err(SocketError::FrameTooLarge {
+                size: 1100,
+                max: 1000,
+            }) => refused += 1,
+            Err(e) if common::is_would_block(&e) => std::thread::sleep(CHECK_DELAY),
+            res => panic!("{res:?}"),
+        }
+    }
+}
+
+#[test]
+fn send_on() {
+    use networking::proxy::{ProxyMessage, SendError};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let proxy_cfg = networking::proxy::ProxyConfig {
+        keep_msg_while_disconnected: true,
+        channels: networking::socket::ChannelConfig {
+            priorities: vec![0, 1],
+            ..Default::default()
+        },
+        ..networking::proxy::ProxyConfig::new(listener.local_addr().unwrap())
+    };
+    let mut proxy_controller: networking::proxy::ProxyController<Message, Message> =
+        networking::Proxy::start_new(proxy_cfg, None);
+
+    let (stream, _) = listener.accept().unwrap();
+    stream.set_nonblocking(true).unwrap();
+    let mut socket: networking::Socket<Message, Message> = networking::Socket::new(stream);
+    socket.handshake(TIMEOUT).unwrap();
+    assert!(matches!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Connected { .. }
+    ));
+
+    let message = Message::Text(String::from("Hi"));
+    proxy_controller.send_on(1, message.clone()).unwrap();
+    assert_eq!(
+        proxy_controller.send_on(2, message.clone()),
+        Err(SendError::UnknownChannel(message.clone()))
+    );
+
+    let (header, received) = socket.recv(CHECK_DELAY).unwrap();
+    assert_eq!((header.channel, &received), (1, &message));
+
+    // The channel is only known by the header, the proxy forwards the message like any other
+    socket.send(message.clone()).unwrap();
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Forward(message)
+    );
+
+    let start = std::time::Instant::now();
+    while proxy_controller.stats().channel_messages_received(0) != 1 {
+        assert!(start.elapsed() < TIMEOUT, "The stats were never updated");
+        std::thread::sleep(CHECK_DELAY);
+    }
+    let stats = proxy_controller.stats();
+    assert_eq!(stats.channel_messages_sent(0), 0);
+    assert_eq!(stats.channel_messages_sent(1), 1);
+    assert_eq!(stats.channel_bytes_sent(1), header.size);
+    assert_eq!(stats.channel_messages_received(1), 0);
+}
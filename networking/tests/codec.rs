@@ -0,0 +1,69 @@
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+enum Message {
+    Text(String),
+    Position { x: f32, y: f32 },
+    Bytes(Vec<u8>),
+}
+
+fn round_trip<C: networking::Codec>() {
+    const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let client_stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server_stream, _) = listener.accept().unwrap();
+    client_stream.set_nonblocking(true).unwrap();
+    server_stream.set_nonblocking(true).unwrap();
+
+    let mut client: networking::Socket<Message, Message, C> =
+        networking::Socket::new(client_stream);
+    let mut server: networking::Socket<Message, Message, C> =
+        networking::Socket::new(server_stream);
+
+    let messages = [
+        Message::Text(String::from("Hi")),
+        Message::Position { x: 1.5, y: -3.0 },
+        Message::Bytes(vec![0, 1, 2, 255]),
+    ];
+
+    for message in messages.iter() {
+        client.send(message.clone()).unwrap();
+    }
+
+    for message in messages.iter() {
+        let (_header, received) = server.recv(CHECK_DELAY).unwrap();
+        assert_eq!(&received, message);
+    }
+}
+
+#[test]
+fn bincode() {
+    round_trip::<networking::codec::Bincode>()
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json() {
+    round_trip::<networking::codec::Json>()
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack() {
+    round_trip::<networking::codec::MessagePack>()
+}
+
+#[cfg(feature = "postcard")]
+#[test]
+fn postcard() {
+    round_trip::<networking::codec::Postcard>()
+}
+
+#[test]
+fn mismatch() {
+    use networking::Codec as _;
+
+    // Raw bytes of a Json message, as received by a Bincode socket
+    let bytes = br#"{"Text":"Hi"}"#;
+    let res = networking::codec::Bincode::decode::<Message>(bytes);
+    assert!(res.is_err());
+}
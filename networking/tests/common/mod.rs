@@ -0,0 +1,55 @@
+// The helpers shared by the tests, not every test uses all of them
+#![allow(dead_code)]
+
+// Two sockets connected to each other over the loopback
+pub fn loopback_pair<R: networking::Message, W: networking::Message>(
+) -> (networking::Socket<R, W>, networking::Socket<W, R>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let client_stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server_stream, _) = listener.accept().unwrap();
+    client_stream.set_nonblocking(true).unwrap();
+    server_stream.set_nonblocking(true).unwrap();
+
+    (
+        networking::Socket::new(client_stream),
+        networking::Socket::new(server_stream),
+    )
+}
+
+// A raw stream on one side, so the test can write whatever it wants
+pub fn raw_pair<R: networking::Message, W: networking::Message>(
+) -> (std::net::TcpStream, networking::Socket<R, W>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let raw = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    stream.set_nonblocking(true).unwrap();
+
+    raw.set_read_timeout(Some(std::time::Duration::from_secs(5)))
+        .unwrap();
+
+    (raw, networking::Socket::new(stream))
+}
+
+pub fn is_would_block(e: &networking::socket::SocketError) -> bool {
+    matches!(e, networking::socket::SocketError::StreamRead(io_e) if io_e.kind() == std::io::ErrorKind::WouldBlock)
+}
+
+// Answers every ping `delay` after it came in, for `duration`, so the round trip time is at least `delay`
+pub fn slow_pongs<R: networking::Message, W: networking::Message>(
+    socket: &mut networking::Socket<R, W>,
+    delay: std::time::Duration,
+    duration: std::time::Duration,
+) {
+    let start = std::time::Instant::now();
+    while start.elapsed() < duration {
+        match socket.try_recv_frame() {
+            Ok((_, networking::socket::Frame::Ping)) => {
+                std::thread::sleep(delay);
+                socket.pong().unwrap();
+            }
+            Ok(_) => (),
+            Err(e) if is_would_block(&e) => std::thread::sleep(std::time::Duration::from_millis(1)),
+            Err(e) => panic!("{e}"),
+        }
+    }
+}
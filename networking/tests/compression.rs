@@ -0,0 +1,185 @@
+mod common;
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+enum Message {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+#[cfg(feature = "lz4")]
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+// A compressed data frame, its payload being the uncompressed size (as lz4 prepends it) and garbage
+fn write_compressed_frame(raw: &mut std::net::TcpStream, uncompressed_size: u32) {
+    use {
+        networking::socket::{FrameKind, Header},
+        std::io::Write as _,
+    };
+
+    let mut payload = uncompressed_size.to_le_bytes().to_vec();
+    payload.extend_from_slice(&[0; 4]);
+
+    let mut header = Header::new(FrameKind::Data, payload.len() as u64);
+    header.compressed = true;
+
+    raw.write_all(&bincode::serialize(&header).unwrap())
+        .unwrap();
+    raw.write_all(&payload).unwrap();
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn lz4() {
+    let (mut client, server) = common::loopback_pair::<Message, Message>();
+
+    // The handshake tells the client that the server reads lz4
+    let server_thread = std::thread::spawn(move || {
+        let mut server = server;
+        server.handshake(TIMEOUT).unwrap();
+        server
+    });
+    client.handshake(TIMEOUT).unwrap();
+    let mut server = server_thread.join().unwrap();
+
+    client.set_compression(Some(networking::Compression::Lz4 { threshold: 1024 }));
+
+    // Under the threshold
+    let small = Message::Text(String::from("Hi"));
+    let header = client.send(small.clone()).unwrap();
+    assert!(!header.compressed);
+
+    let (header, received) = server.recv(CHECK_DELAY).unwrap();
+    assert!(!header.compressed);
+    assert_eq!(header.size, header.uncompressed_size);
+    assert_eq!(received, small);
+
+    // Something like a map, with a lot of repetitions
+    let big = Message::Bytes((0..64 * 1024).map(|i| (i % 16) as u8).collect());
+    let sent_header = client.send(big.clone()).unwrap();
+    assert!(sent_header.compressed);
+    assert!(sent_header.size < sent_header.uncompressed_size);
+
+    let (header, received) = server.recv(CHECK_DELAY).unwrap();
+    assert!(header.compressed);
+    assert_eq!(header.size, sent_header.size);
+    assert_eq!(header.uncompressed_size, sent_header.uncompressed_size);
+    assert_eq!(received, big);
+}
+
+// A peer built without lz4 gets the messages uncompressed
+#[cfg(feature = "lz4")]
+#[test]
+fn negotiation() {
+    use {networking::socket::Handshake, std::io::Write as _};
+
+    let (mut raw, mut socket) = common::raw_pair::<Message, Message>();
+
+    let mut handshake = Handshake::new::<Message, Message>();
+    assert_ne!(handshake.compressions, 0);
+    handshake.compressions = 0;
+    raw.write_all(&bincode::serialize(&handshake).unwrap())
+        .unwrap();
+    socket.handshake(TIMEOUT).unwrap();
+
+    socket.set_compression(Some(networking::Compression::Lz4 { threshold: 0 }));
+    let header = socket.send(Message::Bytes(vec![42; 64 * 1024])).unwrap();
+    assert!(!header.compressed);
+    assert_eq!(header.size, header.uncompressed_size);
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn decompressed_size_limit() {
+    use networking::socket::SocketError;
+
+    let (mut raw, mut socket) = common::raw_pair::<Message, Message>();
+    socket.set_max_frame_size(1024);
+
+    // A few bytes on the wire, a GiB once decompressed
+    write_compressed_frame(&mut raw, 1 << 30);
+
+    assert!(matches!(
+        socket.recv(CHECK_DELAY),
+        Err(SocketError::FrameTooLarge {
+            size: 1_073_741_824,
+            max: 1024
+        })
+    ));
+}
+
+#[cfg(not(feature = "lz4"))]
+#[test]
+fn disabled() {
+    use networking::socket::SocketError;
+
+    let (mut raw, mut socket) = common::raw_pair::<Message, Message>();
+
+    write_compressed_frame(&mut raw, 16);
+
+    assert!(matches!(
+        socket.recv(CHECK_DELAY),
+        Err(SocketError::Decompression(_))
+    ));
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn stats() {
+    use {networking::server::ServerEvent, std::str::FromStr as _};
+
+    let server_cfg = networking::server::ServerConfig {
+        addr: std::net::SocketAddr::from_str("127.0.0.1:0").unwrap(),
+        run_tps: 100,
+        stat_cfg: Default::default(),
+        max_frame_size: networking::socket::DEFAULT_MAX_FRAME_SIZE,
+        compression: Some(networking::Compression::Lz4 { threshold: 1024 }),
+        channels: Default::default(),
+        tcp: Default::default(),
+        heartbeat: None,
+        tls: None,
+    };
+
+    let server: networking::server::ServerController<Message, Message> =
+        networking::Server::start_new(server_cfg).unwrap();
+
+    let proxy_cfg = networking::proxy::ProxyConfig {
+        stat_cfg: networking::stats::StatConfig {
+            bps: networking::stats::config::BpsConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..networking::proxy::ProxyConfig::new(server.local_addr())
+    };
+
+    let mut client: networking::proxy::ProxyController<Message, Message> =
+        networking::Proxy::start_new(proxy_cfg, None);
+
+    let ServerEvent::Connected(id) = server.recv_timeout(TIMEOUT).unwrap() else {
+        panic!("Expected the client to connect")
+    };
+    assert!(matches!(
+        client.recv_timeout(TIMEOUT).unwrap(),
+        networking::proxy::ProxyMessage::Connected { .. }
+    ));
+
+    let big = Message::Bytes(vec![42; 64 * 1024]);
+    server.send_to(id, big.clone()).unwrap();
+    assert_eq!(
+        client.recv_timeout(TIMEOUT).unwrap(),
+        networking::proxy::ProxyMessage::Forward(big)
+    );
+
+    // The stats are written at the end of the proxy's loop
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let stats = client.stats();
+    assert!(stats.total_received_uncompressed() > 64 * 1024);
+    assert!(stats.total_received() < stats.total_received_uncompressed() / 10);
+    assert_eq!(
+        stats.received_uncompressed_in_window(),
+        stats.total_received_uncompressed()
+    );
+}
@@ -0,0 +1,243 @@
+#![cfg(feature = "conditioner")]
+
+use networking::conditioner::{ConditionerConfig, LinkConfig, TcpConditioner, UdpConditioner};
+
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+const MS: std::time::Duration = std::time::Duration::from_millis(1);
+
+fn cfg(target: std::net::SocketAddr, upstream: LinkConfig, seed: u64) -> ConditionerConfig {
+    ConditionerConfig {
+        addr: "127.0.0.1:0".parse().unwrap(),
+        target,
+        upstream,
+        downstream: LinkConfig {
+            latency: upstream.latency,
+            ..Default::default()
+        },
+        seed: Some(seed),
+    }
+}
+
+// The proxy sees the latency of both directions in its round trip time
+#[test]
+fn tcp_latency() {
+    use networking::proxy::ProxyMessage;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let link = LinkConfig {
+        latency: 50 * MS,
+        jitter: 10 * MS,
+        ..Default::default()
+    };
+    let conditioner =
+        TcpConditioner::start_new(cfg(listener.local_addr().unwrap(), link, 0)).unwrap();
+
+    let proxy_cfg = networking::proxy::ProxyConfig {
+        stat_cfg: networking::stats::StatConfig {
+            rtt: networking::stats::config::RttConfig {
+                enabled: true,
+                ping_request_delay: 10 * MS,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..networking::proxy::ProxyConfig::new(conditioner.local_addr())
+    };
+    let mut proxy_controller: networking::proxy::ProxyController<u32, u32> =
+        networking::Proxy::start_new(proxy_cfg, None);
+
+    let (stream, _) = listener.accept().unwrap();
+    stream.set_nonblocking(true).unwrap();
+    let mut socket: networking::Socket<u32, u32> = networking::Socket::new(stream);
+    socket.handshake(TIMEOUT).unwrap();
+    assert!(matches!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Connected { .. }
+    ));
+
+    // The numbers go through in order, the jitter doesn't reorder a stream
+    for i in 0..20 {
+        proxy_controller.send(i).unwrap();
+    }
+    let start = std::time::Instant::now();
+    let mut received = Vec::new();
+    while received.len() < 20 && start.elapsed() < TIMEOUT {
+        // Answers the pings too
+        match socket.try_recv() {
+            Ok((_, i)) => received.push(i),
+            Err(_) => std::thread::sleep(MS),
+        }
+    }
+    assert_eq!(received, (0..20).collect::<Vec<_>>());
+
+    let start = std::time::Instant::now();
+    while proxy_controller.stats().rtt_sample_count() < 3 && start.elapsed() < TIMEOUT {
+        let _ = socket.try_recv();
+        std::thread::sleep(MS);
+    }
+    let stats = proxy_controller.stats();
+    assert!(stats.rtt_sample_count() >= 3);
+    assert!(stats.min_rtt() >= 100 * MS, "{:?}", stats.min_rtt());
+    assert!(stats.max_rtt() < 100 * MS + 2 * link.jitter + 50 * MS);
+}
+
+#[test]
+fn tcp_bandwidth() {
+    use std::io::{Read as _, Write as _};
+
+    const BANDWIDTH: u64 = 100_000;
+    const SIZE: usize = 50_000;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let link = LinkConfig {
+        bandwidth: Some(BANDWIDTH),
+        ..Default::default()
+    };
+    let conditioner =
+        TcpConditioner::start_new(cfg(listener.local_addr().unwrap(), link, 0)).unwrap();
+
+    let mut client = std::net::TcpStream::connect(conditioner.local_addr()).unwrap();
+    let (mut target, _) = listener.accept().unwrap();
+    target.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+    let bytes = (0..SIZE).map(|i| i as u8).collect::<Vec<_>>();
+    let start = std::time::Instant::now();
+    client.write_all(&bytes).unwrap();
+
+    let mut received = vec![0; SIZE];
+    target.read_exact(&mut received).unwrap();
+    assert_eq!(received, bytes);
+
+    // Half a second at that rate, a bit less as the first chunk goes out right away
+    let expected = std::time::Duration::from_secs_f64(SIZE as f64 / BANDWIDTH as f64);
+    assert!(
+        start.elapsed() >= expected - 20 * MS,
+        "{:?}",
+        start.elapsed()
+    );
+
+    // The end of the stream goes through too
+    drop(client);
+    assert_eq!(target.read(&mut received).unwrap(), 0);
+}
+
+// A sender faster than the bandwidth is held back by the os buffers, the conditioner doesn't read it all
+#[test]
+fn tcp_backpressure() {
+    use std::io::Write as _;
+
+    // Way more than the os buffers can hold
+    const LIMIT: usize = 64 * 1024 * 1024;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let link = LinkConfig {
+        latency: 10 * MS,
+        bandwidth: Some(100_000),
+        ..Default::default()
+    };
+    let conditioner =
+        TcpConditioner::start_new(cfg(listener.local_addr().unwrap(), link, 0)).unwrap();
+
+    let mut client = std::net::TcpStream::connect(conditioner.local_addr()).unwrap();
+    let (_target, _) = listener.accept().unwrap();
+    client.set_nonblocking(true).unwrap();
+
+    // Only the bandwidth's worth goes through once the buffers are full
+    let bytes = vec![0; 64 * 1024];
+    let mut sent = 0;
+    let start = std::time::Instant::now();
+    while sent < LIMIT && start.elapsed() < 500 * MS {
+        match client.write(&bytes) {
+            Ok(written) => sent += written,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => std::thread::sleep(MS),
+            Err(e) => panic!("{e}"),
+        }
+    }
+    assert!(sent < LIMIT, "{sent}");
+}
+
+// Sends numbers through the conditioner and returns them as the target got them
+fn relay_numbers(link: LinkConfig, seed: u64) -> Vec<u32> {
+    let target = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let conditioner =
+        UdpConditioner::start_new(cfg(target.local_addr().unwrap(), link, seed)).unwrap();
+
+    let client = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(conditioner.local_addr()).unwrap();
+    target.connect(conditioner.target_side_addr()).unwrap();
+    target.set_read_timeout(Some(200 * MS)).unwrap();
+
+    // Read as they come, a burst would overflow the receive buffer of the target
+    let receiver = {
+        let target = target.try_clone().unwrap();
+        std::thread::spawn(move || {
+            let mut received = Vec::new();
+            let mut buffer = [0; 4];
+            // Until nothing comes for a while
+            while let Ok(4) = target.recv(&mut buffer) {
+                received.push(u32::from_be_bytes(buffer));
+            }
+            received
+        })
+    };
+
+    for i in 0..200u32 {
+        client.send(&i.to_be_bytes()).unwrap();
+        std::thread::sleep(std::time::Duration::from_micros(100));
+    }
+    let received = receiver.join().unwrap();
+
+    // And the other way
+    let mut buffer = [0; 4];
+    target.send(b"back").unwrap();
+    client.set_read_timeout(Some(TIMEOUT)).unwrap();
+    assert_eq!(client.recv(&mut buffer).unwrap(), 4);
+    assert_eq!(&buffer, b"back");
+
+    received
+}
+
+#[test]
+fn udp_loss() {
+    let link = LinkConfig {
+        loss: 0.3,
+        ..Default::default()
+    };
+
+    let received = relay_numbers(link, 42);
+    assert!(
+        received.len() > 100 && received.len() < 180,
+        "{}",
+        received.len()
+    );
+    assert!(received.windows(2).all(|pair| pair[0] < pair[1]));
+
+    // The same seed loses the same datagrams
+    assert_eq!(relay_numbers(link, 42), received);
+    assert_ne!(relay_numbers(link, 7), received);
+}
+
+#[test]
+fn udp_duplication_and_reordering() {
+    let received = relay_numbers(
+        LinkConfig {
+            duplication: 1.,
+            ..Default::default()
+        },
+        0,
+    );
+    let twice = (0..200).flat_map(|i| [i, i]).collect::<Vec<_>>();
+    assert_eq!(received, twice);
+
+    let mut received = relay_numbers(
+        LinkConfig {
+            reordering: 0.2,
+            ..Default::default()
+        },
+        0,
+    );
+    assert!(!received.windows(2).all(|pair| pair[0] < pair[1]));
+    // Nothing is lost
+    received.sort_unstable();
+    assert_eq!(received, (0..200).collect::<Vec<_>>());
+}
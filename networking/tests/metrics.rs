@@ -0,0 +1,171 @@
+#![cfg(feature = "metrics")]
+
+use networking::metrics::{Metrics, MetricsConfig, MetricsExporter};
+
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+fn stats() -> networking::NetworkStats<u32, u32> {
+    use networking::socket::{FrameKind, Header};
+
+    let mut stats = networking::NetworkStats::new(networking::stats::StatConfig {
+        bps: networking::stats::config::BpsConfig {
+            enabled: true,
+            ..Default::default()
+        },
+        rtt: networking::stats::config::RttConfig {
+            enabled: true,
+            ..Default::default()
+        },
+        message_types: networking::stats::config::MessageTypeConfig { enabled: true },
+    });
+    stats.on_bytes_send(&Header::new(FrameKind::Data, 100));
+    stats.on_message_send(Some("Chat"), &Header::new(FrameKind::Data, 100));
+    stats.on_bytes_recv(&Header::new(FrameKind::Data, 10));
+    stats.set_rtt(std::time::Duration::from_millis(20));
+    stats
+}
+
+// Sends a request and returns the whole answer, the exporter closes the connection after it
+fn scrape(addr: std::net::SocketAddr, request: &str) -> String {
+    use std::io::{Read as _, Write as _};
+
+    let mut stream = std::net::TcpStream::connect_timeout(&addr, TIMEOUT).unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn render() {
+    use networking::socket::HEADER_SIZE;
+
+    let mut metrics = Metrics::new();
+    assert!(metrics.is_empty());
+    assert_eq!(metrics.render(), "");
+
+    metrics.add(&[("connection", "eu-1")], &stats());
+    metrics.add(
+        &[("connection", "say \"hi\"\n")],
+        &networking::NetworkStats::<u32, u32>::default(),
+    );
+    let text = metrics.render();
+
+    // The help and type lines once, then a sample per connection
+    assert_eq!(
+        text.matches("# TYPE networking_sent_bytes_total counter\n")
+            .count(),
+        1
+    );
+    assert!(text.contains(&format!(
+        "networking_sent_bytes_total{{connection=\"eu-1\"}} {}\n",
+        100 + HEADER_SIZE
+    )));
+    assert!(text.contains("networking_sent_bytes_total{connection=\"say \\\"hi\\\"\\n\"} 0\n"));
+    assert!(text.contains("networking_rtt_seconds{connection=\"eu-1\"} 0.02\n"));
+    assert!(text.contains("# TYPE networking_queue_depth gauge\n"));
+
+    // Only the first connection tracks its message types
+    assert!(text.contains(
+        "networking_message_type_sent_bytes_total{connection=\"eu-1\",type=\"Chat\"} 100\n"
+    ));
+    assert_eq!(text.matches("type=\"Chat\"").count(), 4);
+
+    // Every line is a comment or a sample with a value
+    for line in text.lines().filter(|line| !line.starts_with('#')) {
+        let (_, value) = line.rsplit_once(' ').unwrap();
+        value.parse::<f64>().unwrap();
+    }
+}
+
+#[test]
+fn exporter() {
+    let exporter = MetricsExporter::start_new(MetricsConfig {
+        addr: "127.0.0.1:0".parse().unwrap(),
+        ..Default::default()
+    })
+    .unwrap();
+    let addr = exporter.local_addr();
+    assert!(exporter.is_running());
+
+    let mut metrics = Metrics::new();
+    metrics.add(&[("connection", "eu-1")], &stats());
+    exporter.publish(&metrics);
+
+    let response = scrape(addr, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(head.contains(networking::metrics::CONTENT_TYPE));
+    assert!(head.contains(&format!("Content-Length: {}", body.len())));
+    assert_eq!(body, metrics.render());
+
+    // A new publish replaces the old metrics
+    exporter.publish(&Metrics::new());
+    let response = scrape(addr, "GET /metrics?name=x HTTP/1.1\r\n\r\n");
+    assert!(response.ends_with("\r\n\r\n"));
+
+    assert!(scrape(addr, "GET / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+    assert!(scrape(addr, "POST /metrics HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 405"));
+
+    // The port is closed once the exporter is dropped
+    drop(exporter);
+    assert!(std::net::TcpStream::connect_timeout(&addr, TIMEOUT).is_err());
+}
+
+// The stats of a server's clients, each with its own label
+#[test]
+fn server() {
+    use networking::server::ServerEvent;
+
+    let server_cfg = networking::server::ServerConfig {
+        addr: "127.0.0.1:0".parse().unwrap(),
+        run_tps: 100,
+        stat_cfg: Default::default(),
+        max_frame_size: networking::socket::DEFAULT_MAX_FRAME_SIZE,
+        compression: None,
+        channels: Default::default(),
+        tcp: Default::default(),
+        heartbeat: None,
+        tls: None,
+    };
+    let mut server = networking::Server::<u32, u32>::start_new(server_cfg).unwrap();
+
+    let mut clients = Vec::new();
+    for _ in 0..2 {
+        let stream = std::net::TcpStream::connect(server.local_addr()).unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut socket: networking::Socket<u32, u32> = networking::Socket::new(stream);
+        socket.handshake(TIMEOUT).unwrap();
+        socket.send(7).unwrap();
+        clients.push(socket);
+    }
+    let messages = (0..4)
+        .filter(|_| {
+            matches!(
+                server.recv_timeout(TIMEOUT).unwrap(),
+                ServerEvent::Message(_, 7)
+            )
+        })
+        .count();
+    assert_eq!(messages, 2);
+
+    let expected = ["0", "1"].map(|client| {
+        format!("networking_received_messages_total{{server=\"lobby\",client=\"{client}\"}} 1\n")
+    });
+
+    // The stats are published by the server's thread, a bit after the messages
+    let start = std::time::Instant::now();
+    loop {
+        let mut metrics = Metrics::new();
+        metrics.add_server(&[("server", "lobby")], server.stats());
+        let text = metrics.render();
+
+        if expected.iter().all(|line| text.contains(line.as_str())) {
+            break;
+        }
+        assert!(start.elapsed() < TIMEOUT, "{text}");
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+}
@@ -1,5 +1,7 @@
 use std::str::FromStr;
 
+mod common;
+
 #[test]
 fn proxy() {
     #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -7,32 +9,34 @@ fn proxy() {
         Text(String),
         // ..
     }
-
-    impl networking::Message for Message {
-        /*
-        Methods of this trait are used for stat calculation
-        if you want to enable the stats, don't forget to impl thoses
-
-        IMPORTANT
-            When RTT stat is disabled in proxy config, the proxy will not anwser to any ping
-            calls from the remote, therefore their rtt calculation will not work.
-        */
-    }
+    // networking::Message is implemented for every type that has the right bounds
+    // Pings and disconnections are handled by the proxy, no need for extra variants
 
     let addr = std::net::SocketAddr::from_str("127.0.0.1:42069").unwrap();
     let proxy_cfg = networking::proxy::ProxyConfig {
         // The address where the proxy has to connect
-        addr,
-        // The target Tick Per Second of the Proxy's internal loop
-        run_tps: 10, // 10 updates per second
+        addr: addr.into(),
+        // The proxy sleeps until there is something to forward, this caps its loops per second when it's very busy
+        run_tps: None, // Or Some(100) for at most 100 loops per second
         // Everything is disabled by default
         stat_cfg: Default::default(),
         // This option set to true will stores the msg that you send to the proxy while the proxy is disconnected
         // And will send them as soon as it reconnects
         // keep this to false unless you know what you are doing
         keep_msg_while_disconnected: false,
-        // Auto reconnect to the given address
-        auto_reconnect: false,
+        // How the proxy reconnects when the connection is lost, None to exit instead
+        reconnect: None, // Or Some(networking::proxy::ReconnectPolicy::default())
+        // Messages waiting to be sent, send blocks when there are too many
+        queue: Default::default(),
+        // How much the proxy sends or receives before looking at the other direction
+        batch: Default::default(),
+        channels: Default::default(),
+        // Bigger messages from the remote close the connection
+        max_frame_size: networking::socket::DEFAULT_MAX_FRAME_SIZE,
+        compression: None,
+        tcp: Default::default(),
+        heartbeat: None,
+        tls: None,
     };
     /*
     Note:
@@ -56,8 +60,28 @@ fn proxy() {
         networking::proxy::ProxyMessage::Forward(_msg) => {
             // Direct message from the remote
         }
-        networking::proxy::ProxyMessage::ConnectionResetError => {
-            // The proxy's connection has stopped, if auto_reconnect is set, the proxy will try to reconnect
+        networking::proxy::ProxyMessage::Request { id, msg: _ } => {
+            // The remote waits for an answer, sent with proxy_controller.respond(id, msg)
+            let _ = id;
+        }
+        networking::proxy::ProxyMessage::Connected { addr: _ } => {
+            // The handshake is done, what you send from now on goes through
+        }
+        networking::proxy::ProxyMessage::Disconnected { reason: _ } => {
+            // The proxy's connection has stopped, if reconnect is set, the proxy will try to reconnect
+            // The reason tells a peer that closed the connection apart from an error
+        }
+        networking::proxy::ProxyMessage::Error(_error) => {
+            // Could not connect (refused, timed out, rejected handshake..)
+        }
+        networking::proxy::ProxyMessage::Reconnecting { attempt: _ } => {
+            // The connection was lost (or never made), the proxy tries again once the policy's delay is over
+        }
+        networking::proxy::ProxyMessage::Reconnected => {
+            // Sent after Reconnecting, once the proxy is connected again
+        }
+        networking::proxy::ProxyMessage::GaveUp => {
+            // The policy's max attempts or give up timeout was reached, the proxy exits
         }
         networking::proxy::ProxyMessage::Exit => {
             // The proxy encountered an error and exited
@@ -66,4 +90,1011 @@ fn proxy() {
 
     // Non-blocking
     let _server_msg_res = proxy_controller.try_recv();
+
+    // Sends what is still queued, then closes the connection, dropping it does the same with a short timeout
+    let _summary: networking::proxy::ShutdownSummary =
+        proxy_controller.shutdown(std::time::Duration::from_secs(1));
+}
+
+#[test]
+fn frame_too_large() {
+    use {
+        networking::{
+            proxy::{DisconnectReason, ProxyError, ProxyMessage},
+            socket::{FrameKind, Handshake, Header, SocketError},
+        },
+        std::io::Write as _,
+    };
+
+    #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    enum Message {
+        Text(String),
+    }
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let proxy_cfg = networking::proxy::ProxyConfig {
+        max_frame_size: 1024,
+        ..networking::proxy::ProxyConfig::new(listener.local_addr().unwrap())
+    };
+
+    let proxy_controller: networking::proxy::ProxyController<Message, Message> =
+        networking::Proxy::start_new(proxy_cfg, None);
+
+    let (mut raw, _) = listener.accept().unwrap();
+
+    // The proxy doesn't read anything before the handshake
+    let handshake = Handshake::new::<Message, Message>();
+    raw.write_all(&bincode::serialize(&handshake).unwrap())
+        .unwrap();
+
+    assert!(matches!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Connected { .. }
+    ));
+
+    let header = Header::new(FrameKind::Data, 1 << 40);
+    raw.write_all(&bincode::serialize(&header).unwrap())
+        .unwrap();
+
+    // A protocol error, it has nothing to do with the stream itself
+    let ProxyMessage::Disconnected {
+        reason: DisconnectReason::Error(error),
+    } = proxy_controller.recv_timeout(TIMEOUT).unwrap()
+    else {
+        panic!("Expected the proxy to disconnect")
+    };
+    assert_eq!(
+        error,
+        ProxyError::SocketRecv(SocketError::FrameTooLarge {
+            size: 1 << 40,
+            max: 1024
+        })
+    );
+    assert_eq!(error.io_kind(), None);
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Exit
+    );
+}
+
+// Without a reconnect policy, the proxy exits after its first disconnection
+fn single_attempt_proxy<M: networking::Message + 'static>(
+    addr: impl Into<networking::proxy::Address>,
+    tcp: networking::proxy::TcpConfig,
+) -> networking::proxy::ProxyController<M, M> {
+    let proxy_cfg = networking::proxy::ProxyConfig {
+        tcp,
+        ..networking::proxy::ProxyConfig::new(addr)
+    };
+
+    networking::Proxy::start_new(proxy_cfg, None)
+}
+
+#[test]
+fn refused() {
+    use networking::proxy::{ProxyError, ProxyMessage};
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    // Nothing listens there
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let proxy_controller = single_attempt_proxy::<u32>(addr, Default::default());
+
+    let ProxyMessage::Error(error) = proxy_controller.recv_timeout(TIMEOUT).unwrap() else {
+        panic!("Expected the connection to fail")
+    };
+    assert_eq!(
+        error,
+        ProxyError::Connect {
+            addr,
+            kind: std::io::ErrorKind::ConnectionRefused
+        }
+    );
+    assert_eq!(error.io_kind(), Some(std::io::ErrorKind::ConnectionRefused));
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Exit
+    );
+    assert!(!proxy_controller.is_connected());
+}
+
+#[test]
+fn handshake_timeout() {
+    use networking::{
+        proxy::{DisconnectReason, ProxyError, ProxyMessage},
+        socket::{HandshakeError, SocketError},
+    };
+
+    // Longer than the handshake timeout of the proxy
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+    // The connection is accepted, but nothing answers the handshake
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let proxy_controller = single_attempt_proxy::<u32>(addr, Default::default());
+    let (_raw, _) = listener.accept().unwrap();
+
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Disconnected {
+            reason: DisconnectReason::Error(ProxyError::Handshake {
+                addr,
+                error: SocketError::Handshake(HandshakeError::Timeout)
+            })
+        }
+    );
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Exit
+    );
+}
+
+// The first connection is closed before the handshake is done, that's not a mismatch and the policy applies
+#[test]
+fn handshake_reconnect() {
+    use networking::proxy::{Backoff, DisconnectReason, ProxyError, ProxyMessage, ReconnectPolicy};
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let proxy_cfg = networking::proxy::ProxyConfig {
+        reconnect: Some(ReconnectPolicy {
+            backoff: Backoff::Fixed(std::time::Duration::from_millis(20)),
+            max_attempts: Some(3),
+            give_up_after: None,
+            seed: None,
+        }),
+        ..networking::proxy::ProxyConfig::new(addr)
+    };
+    let proxy_controller: networking::proxy::ProxyController<u32, u32> =
+        networking::Proxy::start_new(proxy_cfg, None);
+
+    let (raw, _) = listener.accept().unwrap();
+    drop(raw);
+
+    assert!(matches!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Disconnected {
+            reason: DisconnectReason::Error(ProxyError::Handshake { .. })
+        }
+    ));
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Reconnecting { attempt: 1 }
+    );
+
+    let socket = accept::<u32>(&listener);
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Connected { addr }
+    );
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Reconnected
+    );
+    assert!(proxy_controller.is_connected());
+    drop(socket);
+}
+
+#[test]
+fn closed() {
+    use networking::proxy::{DisconnectReason, ProxyMessage};
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let proxy_controller = single_attempt_proxy::<u32>(addr, Default::default());
+    let socket = accept::<u32>(&listener);
+
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Connected { addr }
+    );
+
+    // Dropping the socket sends a close frame, that's not an error
+    drop(socket);
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Disconnected {
+            reason: DisconnectReason::Closed
+        }
+    );
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Exit
+    );
+}
+
+// A peer that answers the pings stays connected, the one that froze is dropped once the timeout is reached
+#[test]
+fn heartbeat() {
+    use networking::proxy::{DisconnectReason, HeartbeatConfig, ProxyMessage};
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+    const HEARTBEAT: HeartbeatConfig = HeartbeatConfig {
+        interval: std::time::Duration::from_millis(50),
+        timeout: std::time::Duration::from_millis(300),
+    };
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let proxy_cfg = networking::proxy::ProxyConfig {
+        heartbeat: Some(HEARTBEAT),
+        ..networking::proxy::ProxyConfig::new(addr)
+    };
+    let proxy_controller: networking::proxy::ProxyController<u32, u32> =
+        networking::Proxy::start_new(proxy_cfg, None);
+    let mut socket = accept::<u32>(&listener);
+
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Connected { addr }
+    );
+
+    // Nothing is sent but the pongs
+    let start = std::time::Instant::now();
+    while start.elapsed() < HEARTBEAT.timeout * 3 {
+        assert!(socket.try_recv().is_err());
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    assert!(proxy_controller.is_connected());
+
+    // The peer froze, but the connection is still open
+    let start = std::time::Instant::now();
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Disconnected {
+            reason: DisconnectReason::TimedOut
+        }
+    );
+    assert!(start.elapsed() >= HEARTBEAT.timeout - HEARTBEAT.interval);
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Exit
+    );
+    drop(socket);
+}
+
+// The pongs of the heartbeat aren't taken for the ones of the rtt, it would look shorter than it is
+#[test]
+fn heartbeat_rtt() {
+    const DELAY: std::time::Duration = std::time::Duration::from_millis(50);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let proxy_cfg = networking::proxy::ProxyConfig {
+        stat_cfg: networking::stats::StatConfig {
+            rtt: networking::stats::config::RttConfig {
+                enabled: true,
+                ping_request_delay: std::time::Duration::from_millis(20),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        heartbeat: Some(networking::proxy::HeartbeatConfig {
+            interval: std::time::Duration::from_millis(20),
+            timeout: std::time::Duration::from_secs(1),
+        }),
+        ..networking::proxy::ProxyConfig::new(addr)
+    };
+    let mut proxy_controller: networking::proxy::ProxyController<u32, u32> =
+        networking::Proxy::start_new(proxy_cfg, None);
+    let mut socket = accept::<u32>(&listener);
+
+    common::slow_pongs(&mut socket, DELAY, std::time::Duration::from_secs(1));
+
+    assert!(proxy_controller.is_connected());
+    let stats = proxy_controller.stats();
+    assert!(
+        stats.rtt_sample_count() >= 3,
+        "{}",
+        stats.rtt_sample_count()
+    );
+    assert!(stats.min_rtt() >= DELAY, "{:?}", stats.min_rtt());
+}
+
+// A host name is resolved by the proxy, the addresses that refuse the connection are skipped
+#[test]
+fn resolve() {
+    use networking::proxy::{ProxyError, ProxyMessage};
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    // localhost might resolve to ::1 first, nothing listens there
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let proxy_controller =
+        single_attempt_proxy::<u32>(format!("localhost:{}", addr.port()), Default::default());
+    let _socket = accept::<u32>(&listener);
+
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Connected { addr }
+    );
+
+    // Not even a port, the os doesn't have to be asked
+    let proxy_controller = single_attempt_proxy::<u32>("localhost", Default::default());
+
+    let ProxyMessage::Error(error) = proxy_controller.recv_timeout(TIMEOUT).unwrap() else {
+        panic!("Expected the resolution to fail")
+    };
+    assert_eq!(
+        error,
+        ProxyError::Resolve {
+            host: String::from("localhost"),
+            kind: std::io::ErrorKind::InvalidInput
+        }
+    );
+    assert_eq!(error.io_kind(), Some(std::io::ErrorKind::InvalidInput));
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Exit
+    );
+}
+
+#[test]
+fn tcp_config() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    assert!(!stream.nodelay().unwrap());
+
+    networking::proxy::TcpConfig::default()
+        .apply(&stream)
+        .unwrap();
+    assert!(stream.nodelay().unwrap());
+
+    // Non-blocking, nothing to read yet
+    assert_eq!(
+        std::io::Read::read(&mut &stream, &mut [0; 1])
+            .unwrap_err()
+            .kind(),
+        std::io::ErrorKind::WouldBlock
+    );
+}
+
+// A peer that stopped reading resets the connection once the stream has been full for too long
+#[test]
+fn write_timeout() {
+    use networking::{
+        proxy::{DisconnectReason, ProxyError, ProxyMessage, TcpConfig},
+        socket::SocketError,
+    };
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+    const WRITE_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(200);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let proxy_controller = single_attempt_proxy::<Vec<u8>>(
+        addr,
+        TcpConfig {
+            send_buffer_size: Some(4096),
+            write_timeout: Some(WRITE_TIMEOUT),
+            ..Default::default()
+        },
+    );
+    // Never reads
+    let _socket = accept::<Vec<u8>>(&listener);
+
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Connected { addr }
+    );
+
+    // More than the os buffers of both sides can hold
+    let start = std::time::Instant::now();
+    for _ in 0..64 {
+        if proxy_controller.try_send(vec![42; 1024 * 1024]).is_err() {
+            break;
+        }
+    }
+
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Disconnected {
+            reason: DisconnectReason::Error(ProxyError::SocketSend(SocketError::StreamWrite(
+                std::io::ErrorKind::TimedOut.into()
+            )))
+        }
+    );
+    // The stream was not full before the first message
+    assert!(start.elapsed() >= WRITE_TIMEOUT, "{:?}", start.elapsed());
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Exit
+    );
+}
+
+// The proxy sleeps until there is something to forward, so it doesn't wait for its next tick
+#[test]
+fn latency() {
+    use networking::proxy::ProxyMessage;
+
+    #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    enum Message {
+        Text(String),
+    }
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+    const CHECK_DELAY: std::time::Duration = std::time::Duration::from_micros(100);
+    // A tick is 100ms, the cap only matters for a proxy that loops more than that
+    const RUN_TPS: u64 = 10;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let proxy_cfg = networking::proxy::ProxyConfig {
+        run_tps: Some(RUN_TPS),
+        ..networking::proxy::ProxyConfig::new(listener.local_addr().unwrap())
+    };
+
+    let proxy_controller: networking::proxy::ProxyController<Message, Message> =
+        networking::Proxy::start_new(proxy_cfg, None);
+
+    let (stream, _) = listener.accept().unwrap();
+    stream.set_nonblocking(true).unwrap();
+    let mut socket: networking::Socket<Message, Message> = networking::Socket::new(stream);
+    socket.handshake(TIMEOUT).unwrap();
+
+    assert!(matches!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Connected { .. }
+    ));
+    assert!(proxy_controller.is_connected());
+
+    let tick = std::time::Duration::from_secs(1) / RUN_TPS as u32;
+
+    for i in 0..3 {
+        // Let the proxy go back to sleep
+        std::thread::sleep(tick * 2);
+
+        let start = std::time::Instant::now();
+        proxy_controller
+            .send(Message::Text(format!("Hi {i}")))
+            .unwrap();
+        assert_eq!(
+            socket.recv(CHECK_DELAY).unwrap().1,
+            Message::Text(format!("Hi {i}"))
+        );
+        assert!(start.elapsed() < tick / 2, "Took {:?}", start.elapsed());
+
+        std::thread::sleep(tick * 2);
+
+        let start = std::time::Instant::now();
+        socket.send(Message::Text(format!("Hello {i}"))).unwrap();
+        assert_eq!(
+            proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+            ProxyMessage::Forward(Message::Text(format!("Hello {i}")))
+        );
+        assert!(start.elapsed() < tick / 2, "Took {:?}", start.elapsed());
+    }
+}
+
+#[test]
+fn reconnect() {
+    use networking::proxy::{Backoff, DisconnectReason, ProxyError, ProxyMessage, ReconnectPolicy};
+
+    #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    enum Message {
+        Text(String),
+    }
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+    const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+
+    // Nothing listens there yet
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let proxy_cfg = networking::proxy::ProxyConfig {
+        reconnect: Some(ReconnectPolicy {
+            backoff: Backoff::Fixed(std::time::Duration::from_millis(20)),
+            max_attempts: None,
+            give_up_after: None,
+            seed: None,
+        }),
+        ..networking::proxy::ProxyConfig::new(addr)
+    };
+
+    let mut proxy_controller: networking::proxy::ProxyController<Message, Message> =
+        networking::Proxy::start_new(proxy_cfg, None);
+
+    // Every attempt says why it failed, the first one is made by start_new
+    let refused = ProxyMessage::Error(ProxyError::Connect {
+        addr,
+        kind: std::io::ErrorKind::ConnectionRefused,
+    });
+    for attempt in 1..=3 {
+        assert_eq!(proxy_controller.recv_timeout(TIMEOUT).unwrap(), refused);
+        assert_eq!(
+            proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+            ProxyMessage::Reconnecting { attempt }
+        );
+    }
+    assert!(proxy_controller.is_running());
+
+    let listener = std::net::TcpListener::bind(addr).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    stream.set_nonblocking(true).unwrap();
+    let mut socket: networking::Socket<Message, Message> = networking::Socket::new(stream);
+    socket.handshake(TIMEOUT).unwrap();
+
+    // A few more attempts might have started before the listener was there
+    loop {
+        match proxy_controller.recv_timeout(TIMEOUT).unwrap() {
+            ProxyMessage::Reconnecting { .. } | ProxyMessage::Error(ProxyError::Connect { .. }) => {
+            }
+            ProxyMessage::Connected { addr: connected } => {
+                assert_eq!(connected, addr);
+                break;
+            }
+            msg => panic!("Expected the proxy to reconnect, got {msg:?}"),
+        }
+    }
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Reconnected
+    );
+
+    proxy_controller
+        .send(Message::Text(String::from("Hi")))
+        .unwrap();
+    assert_eq!(
+        socket.recv(CHECK_DELAY).unwrap().1,
+        Message::Text(String::from("Hi"))
+    );
+    assert_eq!(proxy_controller.stats().reconnects(), 1);
+
+    // The attempts are counted again from the start after a new disconnection
+    drop(listener);
+    drop(socket);
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Disconnected {
+            reason: DisconnectReason::Closed
+        }
+    );
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Reconnecting { attempt: 1 }
+    );
+}
+
+#[test]
+fn reconnect_give_up() {
+    use networking::proxy::{Backoff, ProxyError, ProxyMessage, ReconnectPolicy};
+
+    #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    enum Message {
+        Text(String),
+    }
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let proxy_cfg = |reconnect| networking::proxy::ProxyConfig {
+        reconnect: Some(reconnect),
+        ..networking::proxy::ProxyConfig::new(addr)
+    };
+
+    let proxy_controller: networking::proxy::ProxyController<Message, Message> =
+        networking::Proxy::start_new(
+            proxy_cfg(ReconnectPolicy {
+                backoff: Backoff::Fixed(std::time::Duration::from_millis(10)),
+                max_attempts: Some(3),
+                give_up_after: None,
+                seed: None,
+            }),
+            None,
+        );
+
+    // The attempts that failed are in between
+    let recv = |proxy_controller: &networking::proxy::ProxyController<Message, Message>| loop {
+        match proxy_controller.recv_timeout(TIMEOUT).unwrap() {
+            ProxyMessage::Error(ProxyError::Connect { .. }) => (),
+            msg => break msg,
+        }
+    };
+
+    for attempt in 1..=3 {
+        assert_eq!(
+            recv(&proxy_controller),
+            ProxyMessage::Reconnecting { attempt }
+        );
+    }
+    assert_eq!(recv(&proxy_controller), ProxyMessage::GaveUp);
+    assert_eq!(recv(&proxy_controller), ProxyMessage::Exit);
+
+    let start = std::time::Instant::now();
+    let proxy_controller: networking::proxy::ProxyController<Message, Message> =
+        networking::Proxy::start_new(
+            proxy_cfg(ReconnectPolicy {
+                backoff: Backoff::Fixed(std::time::Duration::from_millis(40)),
+                max_attempts: None,
+                give_up_after: Some(std::time::Duration::from_millis(100)),
+                seed: None,
+            }),
+            None,
+        );
+
+    // The third attempt would start after the timeout, it's not made
+    for attempt in 1..=2 {
+        assert_eq!(
+            recv(&proxy_controller),
+            ProxyMessage::Reconnecting { attempt }
+        );
+    }
+    assert_eq!(recv(&proxy_controller), ProxyMessage::GaveUp);
+    assert!(start.elapsed() < std::time::Duration::from_millis(300));
+}
+
+#[test]
+fn backoff() {
+    use networking::proxy::{Backoff, ReconnectPolicy};
+
+    let ms = std::time::Duration::from_millis;
+
+    let mut policy = ReconnectPolicy {
+        backoff: Backoff::Exponential {
+            initial: ms(100),
+            max: ms(1000),
+            jitter: 0.,
+        },
+        ..Default::default()
+    };
+
+    assert_eq!(
+        (1..=6)
+            .map(|attempt| policy.delay(attempt))
+            .collect::<Vec<_>>(),
+        vec![ms(100), ms(200), ms(400), ms(800), ms(1000), ms(1000)]
+    );
+    // No overflow after a long time
+    assert_eq!(policy.delay(u32::MAX), ms(1000));
+
+    // The jitter only shortens the delay
+    policy.backoff = Backoff::Exponential {
+        initial: ms(100),
+        max: ms(1000),
+        jitter: 0.5,
+    };
+    for attempt in 1..=10 {
+        let delay = policy.delay(attempt);
+        let max = ms(100 * 2u64.pow(attempt - 1)).min(ms(1000));
+        assert!(delay >= max / 2 && delay <= max, "{delay:?} for {max:?}");
+    }
+
+    // The same seed gives the same delays
+    let delays = |seed| {
+        let policy = ReconnectPolicy {
+            seed: Some(seed),
+            ..policy.clone()
+        };
+        (1..=10)
+            .map(|attempt| policy.delay(attempt))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(delays(42), delays(42));
+    assert_ne!(delays(42), delays(7));
+
+    // The rng of the calling thread goes on as if nothing had happened
+    random::set_seed(3);
+    let expected = random::get(0u64, u64::MAX);
+    random::set_seed(3);
+    delays(42);
+    assert_eq!(random::get(0u64, u64::MAX), expected);
+}
+
+// The proxy is stuck in its handshake until the peer answers, so the messages stay in the queue
+fn queued_proxy<M: networking::Message + 'static>(
+    queue: networking::proxy::QueueConfig,
+) -> (
+    networking::proxy::ProxyController<M, M>,
+    std::net::TcpListener,
+) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let proxy_cfg = networking::proxy::ProxyConfig {
+        // The messages sent before the handshake are kept
+        keep_msg_while_disconnected: true,
+        queue,
+        ..networking::proxy::ProxyConfig::new(listener.local_addr().unwrap())
+    };
+
+    (networking::Proxy::start_new(proxy_cfg, None), listener)
+}
+
+fn accept<M: networking::Message>(listener: &std::net::TcpListener) -> networking::Socket<M, M> {
+    let (stream, _) = listener.accept().unwrap();
+    stream.set_nonblocking(true).unwrap();
+    let mut socket = networking::Socket::new(stream);
+    socket.handshake(std::time::Duration::from_secs(5)).unwrap();
+    socket
+}
+
+#[test]
+fn queue() {
+    use networking::proxy::{OverflowPolicy, QueueConfig, SendError};
+
+    const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+
+    let (proxy_controller, listener) = queued_proxy::<u32>(QueueConfig {
+        capacity: 2,
+        overflow: OverflowPolicy::Error,
+    });
+    proxy_controller.send(0).unwrap();
+    proxy_controller.send(1).unwrap();
+    assert_eq!(proxy_controller.send(2), Err(SendError::Full(2)));
+    assert_eq!(proxy_controller.try_send(2), Err(SendError::Full(2)));
+    assert_eq!(proxy_controller.queue_len(), 2);
+
+    let mut socket = accept::<u32>(&listener);
+    assert_eq!(socket.recv(CHECK_DELAY).unwrap().1, 0);
+    assert_eq!(socket.recv(CHECK_DELAY).unwrap().1, 1);
+
+    let (mut proxy_controller, listener) = queued_proxy::<u32>(QueueConfig {
+        capacity: 2,
+        overflow: OverflowPolicy::DropOldest,
+    });
+    for i in 0..5 {
+        proxy_controller.send(i).unwrap();
+    }
+    // try_send doesn't drop anything
+    assert_eq!(proxy_controller.try_send(5), Err(SendError::Full(5)));
+
+    let mut socket = accept::<u32>(&listener);
+    assert_eq!(socket.recv(CHECK_DELAY).unwrap().1, 3);
+    assert_eq!(socket.recv(CHECK_DELAY).unwrap().1, 4);
+
+    // The stats are written at the end of the proxy's loop
+    let start = std::time::Instant::now();
+    while proxy_controller.stats().dropped_messages() != 3 {
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        std::thread::sleep(CHECK_DELAY);
+    }
+    let stats = proxy_controller.stats();
+    assert_eq!(stats.queue_peak_depth(), 2);
+    assert_eq!(stats.queue_depth(), 0);
+
+    let (proxy_controller, listener) = queued_proxy::<u32>(QueueConfig {
+        capacity: 2,
+        overflow: OverflowPolicy::DropNewest,
+    });
+    for i in 0..5 {
+        proxy_controller.send(i).unwrap();
+    }
+
+    let mut socket = accept::<u32>(&listener);
+    assert_eq!(socket.recv(CHECK_DELAY).unwrap().1, 0);
+    assert_eq!(socket.recv(CHECK_DELAY).unwrap().1, 1);
+
+    let (proxy_controller, listener) = queued_proxy::<u32>(QueueConfig {
+        capacity: 1,
+        overflow: OverflowPolicy::Block,
+    });
+    proxy_controller.send(0).unwrap();
+
+    const PEER_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+    let peer = std::thread::spawn(move || {
+        std::thread::sleep(PEER_DELAY);
+        let mut socket = accept::<u32>(&listener);
+        (0..2)
+            .map(|_| socket.recv(CHECK_DELAY).unwrap().1)
+            .collect::<Vec<_>>()
+    });
+
+    // Waits for the proxy to be connected and to take the first one
+    let start = std::time::Instant::now();
+    proxy_controller.send(1).unwrap();
+    assert!(start.elapsed() >= PEER_DELAY);
+    assert_eq!(peer.join().unwrap(), vec![0, 1]);
+
+    // Nothing could ever fit, the proxy refuses to start and a blocked send gets its message back
+    let (proxy_controller, _listener) = queued_proxy::<u32>(QueueConfig {
+        capacity: 0,
+        overflow: OverflowPolicy::Block,
+    });
+    assert_eq!(proxy_controller.send(0), Err(SendError::Disconnected(0)));
+    assert!(matches!(
+        proxy_controller.recv_timeout(std::time::Duration::from_secs(5)),
+        Ok(networking::proxy::ProxyMessage::Error(
+            networking::proxy::ProxyError::Config(_)
+        ))
+    ));
+    assert_eq!(
+        proxy_controller.recv_timeout(std::time::Duration::from_secs(5)),
+        Ok(networking::proxy::ProxyMessage::Exit)
+    );
+}
+
+// A peer that doesn't read fills the os buffers, then the proxy stops taking from the queue
+#[test]
+fn backpressure() {
+    use networking::proxy::{OverflowPolicy, QueueConfig, SendError};
+
+    const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+    const CAPACITY: usize = 8;
+
+    let (mut proxy_controller, listener) = queued_proxy::<Vec<u8>>(QueueConfig {
+        capacity: CAPACITY,
+        overflow: OverflowPolicy::Error,
+    });
+    let mut socket = accept::<Vec<u8>>(&listener);
+
+    while !proxy_controller.is_connected() {
+        std::thread::sleep(CHECK_DELAY);
+    }
+
+    let start = std::time::Instant::now();
+    let mut sent = 0;
+    loop {
+        assert!(start.elapsed() < TIMEOUT, "The queue never filled up");
+        match proxy_controller.try_send(vec![42; 64 * 1024]) {
+            Ok(()) => sent += 1,
+            Err(SendError::Full(_)) => break,
+            Err(e) => panic!("{e}"),
+        }
+    }
+    assert_eq!(proxy_controller.queue_len(), CAPACITY);
+
+    // Once the peer reads, everything goes through
+    for _ in 0..sent {
+        assert_eq!(socket.recv(CHECK_DELAY).unwrap().1.len(), 64 * 1024);
+    }
+    assert_eq!(proxy_controller.queue_len(), 0);
+    assert!(proxy_controller.stats().queue_peak_depth() > 0);
+}
+
+// A loop handles a whole batch in each direction, not one message
+#[test]
+fn throughput() {
+    use networking::proxy::ProxyMessage;
+
+    const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+    const RUN_TPS: u64 = 10;
+    const COUNT: u32 = 5_000;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let proxy_cfg = networking::proxy::ProxyConfig {
+        run_tps: Some(RUN_TPS),
+        ..networking::proxy::ProxyConfig::new(listener.local_addr().unwrap())
+    };
+
+    let proxy_controller: networking::proxy::ProxyController<u32, u32> =
+        networking::Proxy::start_new(proxy_cfg, None);
+
+    let mut socket = accept::<u32>(&listener);
+    assert!(matches!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Connected { .. }
+    ));
+
+    // Echoes everything once it has received it all
+    let peer = std::thread::spawn(move || {
+        for i in 0..COUNT {
+            assert_eq!(socket.recv(CHECK_DELAY).unwrap().1, i);
+        }
+        for i in 0..COUNT {
+            socket.queue(i).unwrap();
+        }
+        while socket.pending_bytes() != 0 {
+            socket.flush().unwrap();
+            std::thread::sleep(CHECK_DELAY);
+        }
+        socket
+    });
+
+    let start = std::time::Instant::now();
+    for i in 0..COUNT {
+        proxy_controller.send(i).unwrap();
+    }
+    for i in 0..COUNT {
+        assert_eq!(
+            proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+            ProxyMessage::Forward(i)
+        );
+    }
+    let elapsed = start.elapsed();
+
+    // 10 loops per second, it would take 1000 seconds one message at a time
+    assert!(
+        elapsed < std::time::Duration::from_secs(3),
+        "{} messages per second",
+        (COUNT * 2) as f64 / elapsed.as_secs_f64()
+    );
+
+    drop(peer.join().unwrap());
+}
+
+#[test]
+fn shutdown() {
+    use networking::{
+        proxy::{OverflowPolicy, QueueConfig, ShutdownSummary},
+        socket::SocketError,
+    };
+
+    const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    // Everything that was queued goes through before the close frame
+    let (proxy_controller, listener) = queued_proxy::<u32>(QueueConfig {
+        capacity: 3,
+        overflow: OverflowPolicy::Error,
+    });
+    for i in 0..3 {
+        proxy_controller.send(i).unwrap();
+    }
+
+    let peer = std::thread::spawn(move || {
+        let mut socket = accept::<u32>(&listener);
+        let received = (0..3)
+            .map(|_| socket.recv(CHECK_DELAY).unwrap().1)
+            .collect::<Vec<_>>();
+        assert!(matches!(socket.recv(CHECK_DELAY), Err(SocketError::Exited)));
+        // Dropping the socket closes it on this side too
+        received
+    });
+
+    assert_eq!(
+        proxy_controller.shutdown(TIMEOUT),
+        ShutdownSummary {
+            flushed: 3,
+            dropped: 0,
+            acknowledged: true
+        }
+    );
+    assert_eq!(peer.join().unwrap(), vec![0, 1, 2]);
+
+    // A peer that doesn't read, what doesn't fit in the os buffers is dropped
+    const COUNT: usize = 32;
+    let (proxy_controller, listener) = queued_proxy::<Vec<u8>>(QueueConfig {
+        capacity: COUNT,
+        overflow: OverflowPolicy::Error,
+    });
+    for _ in 0..COUNT {
+        proxy_controller.send(vec![42; 1024 * 1024]).unwrap();
+    }
+    let _socket = accept::<Vec<u8>>(&listener);
+
+    let start = std::time::Instant::now();
+    let summary = proxy_controller.shutdown(std::time::Duration::from_millis(100));
+    assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    assert_ne!(summary.dropped, 0);
+    assert!(summary.flushed + summary.dropped <= COUNT);
+    assert!(!summary.acknowledged);
+
+    // Nothing to flush nor to close, dropping doesn't hold the caller
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let proxy_controller = single_attempt_proxy::<u32>(addr, Default::default());
+    proxy_controller.send(0).unwrap();
+    while proxy_controller.is_running() {
+        std::thread::sleep(CHECK_DELAY);
+    }
+
+    let start = std::time::Instant::now();
+    drop(proxy_controller);
+    assert!(start.elapsed() < std::time::Duration::from_millis(100));
 }
@@ -0,0 +1,189 @@
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+enum Message {
+    Text(String),
+}
+
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+
+fn proxy_cfg(addr: std::net::SocketAddr) -> networking::proxy::ProxyConfig {
+    networking::proxy::ProxyConfig {
+        keep_msg_while_disconnected: true,
+        ..networking::proxy::ProxyConfig::new(addr)
+    }
+}
+
+// A proxy connected to a socket, so the tests decide when and what the other side answers
+fn connected_proxy() -> (
+    networking::proxy::ProxyController<Message, Message>,
+    networking::Socket<Message, Message>,
+) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy_controller =
+        networking::Proxy::start_new(proxy_cfg(listener.local_addr().unwrap()), None);
+
+    let (stream, _) = listener.accept().unwrap();
+    stream.set_nonblocking(true).unwrap();
+    let mut socket = networking::Socket::new(stream);
+    socket.handshake(TIMEOUT).unwrap();
+
+    assert!(matches!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        networking::proxy::ProxyMessage::Connected { .. }
+    ));
+
+    (proxy_controller, socket)
+}
+
+#[test]
+fn request() {
+    use networking::server::ServerEvent;
+
+    let server_cfg = networking::server::ServerConfig {
+        addr: std::net::SocketAddr::from_str("127.0.0.1:0").unwrap(),
+        run_tps: 100,
+        stat_cfg: Default::default(),
+        max_frame_size: networking::socket::DEFAULT_MAX_FRAME_SIZE,
+        compression: None,
+        channels: Default::default(),
+        tcp: Default::default(),
+        heartbeat: None,
+        tls: None,
+    };
+    let server: networking::server::ServerController<Message, Message> =
+        networking::Server::start_new(server_cfg).unwrap();
+
+    let client =
+        networking::Proxy::<Message, Message>::start_new(proxy_cfg(server.local_addr()), None);
+    let ServerEvent::Connected(id) = server.recv_timeout(TIMEOUT).unwrap() else {
+        panic!("Expected the client to connect")
+    };
+    assert_eq!(
+        client.recv_timeout(TIMEOUT).unwrap(),
+        networking::proxy::ProxyMessage::Connected {
+            addr: server.local_addr()
+        }
+    );
+
+    let request = client
+        .request(Message::Text(String::from("Ping")), TIMEOUT)
+        .unwrap();
+    // Unsolicited messages still go through, in order
+    client.send(Message::Text(String::from("Hi"))).unwrap();
+
+    let ServerEvent::Request(client_id, request_id, msg) = server.recv_timeout(TIMEOUT).unwrap()
+    else {
+        panic!("Expected the request first")
+    };
+    assert_eq!(client_id, id);
+    assert_eq!(request_id, request.id());
+    assert_eq!(msg, Message::Text(String::from("Ping")));
+    assert_eq!(
+        server.recv_timeout(TIMEOUT).unwrap(),
+        ServerEvent::Message(id, Message::Text(String::from("Hi")))
+    );
+
+    server
+        .send_to(id, Message::Text(String::from("Hello")))
+        .unwrap();
+    server
+        .respond(id, request_id, Message::Text(String::from("Pong")))
+        .unwrap();
+
+    // The response goes to the request, not to recv
+    assert_eq!(request.wait().unwrap(), Message::Text(String::from("Pong")));
+    assert_eq!(
+        client.recv_timeout(TIMEOUT).unwrap(),
+        networking::proxy::ProxyMessage::Forward(Message::Text(String::from("Hello")))
+    );
+    assert_eq!(client.pending_requests(), 0);
+}
+
+#[test]
+fn timeout() {
+    use networking::proxy::{ProxyMessage, RpcError};
+
+    let (proxy_controller, mut socket) = connected_proxy();
+
+    let request = proxy_controller
+        .request(
+            Message::Text(String::from("Ping")),
+            std::time::Duration::from_millis(50),
+        )
+        .unwrap();
+
+    let (header, _) = socket.recv(CHECK_DELAY).unwrap();
+    assert_eq!(header.kind, networking::socket::FrameKind::Request);
+    assert_eq!(header.request_id, request.id());
+
+    assert_eq!(request.wait(), Err(RpcError::Timeout));
+    assert_eq!(proxy_controller.pending_requests(), 0);
+
+    // The late response is dropped by the proxy, the next message comes through as usual
+    socket
+        .send_response(header.request_id, Message::Text(String::from("Pong")))
+        .unwrap();
+    socket.send(Message::Text(String::from("Hi"))).unwrap();
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Forward(Message::Text(String::from("Hi")))
+    );
+
+    // A cancelled request gets no response either
+    let request = proxy_controller
+        .request(Message::Text(String::from("Ping")), TIMEOUT)
+        .unwrap();
+    let request_id = request.id();
+    request.cancel();
+    assert_eq!(proxy_controller.pending_requests(), 0);
+
+    let (header, _) = socket.recv(CHECK_DELAY).unwrap();
+    assert_eq!(header.request_id, request_id);
+    socket
+        .send_response(request_id, Message::Text(String::from("Pong")))
+        .unwrap();
+    socket.send(Message::Text(String::from("Hi"))).unwrap();
+    assert_eq!(
+        proxy_controller.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Forward(Message::Text(String::from("Hi")))
+    );
+
+    // try_wait doesn't block
+    let mut request = proxy_controller
+        .request(
+            Message::Text(String::from("Ping")),
+            std::time::Duration::from_millis(50),
+        )
+        .unwrap();
+    assert_eq!(request.try_wait(), Ok(None));
+    loop {
+        match request.try_wait() {
+            Ok(None) => std::thread::sleep(CHECK_DELAY),
+            res => {
+                assert_eq!(res, Err(RpcError::Timeout));
+                break;
+            }
+        }
+    }
+}
+
+#[test]
+fn disconnected() {
+    use networking::proxy::RpcError;
+
+    let (proxy_controller, mut socket) = connected_proxy();
+
+    let request = proxy_controller
+        .request(Message::Text(String::from("Ping")), TIMEOUT)
+        .unwrap();
+    socket.recv(CHECK_DELAY).unwrap();
+
+    // The other side leaves without answering, the request doesn't wait for its timeout
+    drop(socket);
+
+    let start = std::time::Instant::now();
+    assert_eq!(request.wait(), Err(RpcError::Disconnected));
+    assert!(start.elapsed() < TIMEOUT);
+}
@@ -0,0 +1,203 @@
+mod common;
+
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+enum Message {
+    Text(String),
+}
+
+#[test]
+fn server() {
+    use networking::server::ServerEvent;
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    let server_cfg = networking::server::ServerConfig {
+        // Port 0 lets the os pick a free port, see ServerController::local_addr
+        addr: std::net::SocketAddr::from_str("127.0.0.1:0").unwrap(),
+        run_tps: 100,
+        stat_cfg: Default::default(),
+        max_frame_size: networking::socket::DEFAULT_MAX_FRAME_SIZE,
+        compression: None,
+        channels: Default::default(),
+        tcp: Default::default(),
+        heartbeat: None,
+        tls: None,
+    };
+
+    // Generics: What you recv, what you send
+    let server: networking::server::ServerController<Message, Message> =
+        networking::Server::start_new(server_cfg).unwrap();
+
+    let client1 = networking::Proxy::<Message, Message>::start_new(
+        networking::proxy::ProxyConfig::new(server.local_addr()),
+        None,
+    );
+    let ServerEvent::Connected(id1) = server.recv_timeout(TIMEOUT).unwrap() else {
+        panic!("Expected the first client to connect")
+    };
+
+    let client2 = networking::Proxy::<Message, Message>::start_new(
+        networking::proxy::ProxyConfig::new(server.local_addr()),
+        None,
+    );
+    let ServerEvent::Connected(id2) = server.recv_timeout(TIMEOUT).unwrap() else {
+        panic!("Expected the second client to connect")
+    };
+
+    assert_ne!(id1, id2);
+
+    // The proxies say it too, once their handshake is done
+    for client in [&client1, &client2] {
+        assert!(matches!(
+            client.recv_timeout(TIMEOUT).unwrap(),
+            networking::proxy::ProxyMessage::Connected { .. }
+        ));
+    }
+
+    client1.send(Message::Text(String::from("Hi"))).unwrap();
+    assert_eq!(
+        server.recv_timeout(TIMEOUT).unwrap(),
+        ServerEvent::Message(id1, Message::Text(String::from("Hi")))
+    );
+
+    server
+        .send_to(id2, Message::Text(String::from("Hello 2")))
+        .unwrap();
+    assert_eq!(
+        client2.recv_timeout(TIMEOUT).unwrap(),
+        networking::proxy::ProxyMessage::Forward(Message::Text(String::from("Hello 2")))
+    );
+
+    server
+        .broadcast(Message::Text(String::from("Hello everyone")))
+        .unwrap();
+    for client in [&client1, &client2] {
+        assert_eq!(
+            client.recv_timeout(TIMEOUT).unwrap(),
+            networking::proxy::ProxyMessage::Forward(Message::Text(String::from("Hello everyone")))
+        );
+    }
+
+    // Dropping the controller stops the proxy, which closes the connection
+    drop(client1);
+    assert_eq!(
+        server.recv_timeout(TIMEOUT).unwrap(),
+        ServerEvent::Disconnected(id1)
+    );
+
+    assert!(server.is_running());
+}
+
+#[test]
+fn handshake_mismatch() {
+    use networking::server::ServerEvent;
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    let server_cfg = networking::server::ServerConfig {
+        addr: std::net::SocketAddr::from_str("127.0.0.1:0").unwrap(),
+        run_tps: 100,
+        stat_cfg: Default::default(),
+        max_frame_size: networking::socket::DEFAULT_MAX_FRAME_SIZE,
+        compression: None,
+        channels: Default::default(),
+        tcp: Default::default(),
+        heartbeat: None,
+        tls: None,
+    };
+
+    let server: networking::server::ServerController<Message, Message> =
+        networking::Server::start_new(server_cfg).unwrap();
+
+    // Expects strings from the server
+    let client = networking::Proxy::<String, Message>::start_new(
+        networking::proxy::ProxyConfig::new(server.local_addr()),
+        None,
+    );
+
+    // The connection is refused by both sides, before any message
+    assert!(matches!(
+        client.recv_timeout(TIMEOUT).unwrap(),
+        networking::proxy::ProxyMessage::Error(networking::proxy::ProxyError::Handshake {
+            error: networking::socket::SocketError::Handshake(
+                networking::socket::HandshakeError::Messages { .. }
+            ),
+            ..
+        })
+    ));
+    assert_eq!(
+        client.recv_timeout(TIMEOUT).unwrap(),
+        networking::proxy::ProxyMessage::Exit
+    );
+    assert!(!client.is_connected());
+
+    let ServerEvent::Connected(id) = server.recv_timeout(TIMEOUT).unwrap() else {
+        panic!("Expected the client to connect")
+    };
+    assert_eq!(
+        server.recv_timeout(TIMEOUT).unwrap(),
+        ServerEvent::Disconnected(id)
+    );
+}
+
+// The clients are all asked to stop at once, the ones that don't answer don't add up
+#[test]
+fn shutdown() {
+    use networking::{server::ServerEvent, socket::SocketError};
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+    const CLIENTS: usize = 5;
+
+    let server_cfg = networking::server::ServerConfig {
+        addr: std::net::SocketAddr::from_str("127.0.0.1:0").unwrap(),
+        run_tps: 100,
+        stat_cfg: Default::default(),
+        max_frame_size: networking::socket::DEFAULT_MAX_FRAME_SIZE,
+        compression: None,
+        channels: Default::default(),
+        tcp: Default::default(),
+        heartbeat: None,
+        tls: None,
+    };
+    let server: networking::server::ServerController<Message, Message> =
+        networking::Server::start_new(server_cfg).unwrap();
+
+    // They never answer the close frame, each proxy waits until its timeout
+    let mut sockets = (0..CLIENTS)
+        .map(|_| {
+            let stream = std::net::TcpStream::connect(server.local_addr()).unwrap();
+            stream.set_nonblocking(true).unwrap();
+            let mut socket: networking::Socket<Message, Message> = networking::Socket::new(stream);
+            socket.handshake(TIMEOUT).unwrap();
+            assert!(matches!(
+                server.recv_timeout(TIMEOUT).unwrap(),
+                ServerEvent::Connected(_)
+            ));
+            socket
+        })
+        .collect::<Vec<_>>();
+
+    let start = std::time::Instant::now();
+    drop(server);
+
+    for socket in sockets.iter_mut() {
+        loop {
+            match socket.try_recv() {
+                Err(SocketError::Exited) => break,
+                Err(e) if common::is_would_block(&e) => {
+                    assert!(start.elapsed() < TIMEOUT);
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                }
+                res => panic!("Expected a close frame, got {res:?}"),
+            }
+        }
+    }
+    // One by one, the last one would only get it after the timeouts of the others
+    assert!(
+        start.elapsed() < std::time::Duration::from_millis(300),
+        "{:?}",
+        start.elapsed()
+    );
+}
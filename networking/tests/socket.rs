@@ -1,3 +1,5 @@
+mod common;
+
 #[test]
 fn socket() {
     #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -5,9 +7,7 @@ fn socket() {
         Text(String),
         // ..
     }
-    impl networking::Message for Message {
-        // Methods of this trait are used for stat calculation (Used by networking::Proxy)
-    }
+    // networking::Message is implemented for every type that has the right bounds
 
     // Assuming there is a std::net::TcpListener at this address
     let stream = std::net::TcpStream::connect("127.0.0.1:42069").unwrap();
@@ -30,3 +30,299 @@ fn socket() {
     let _sent_res: Result<networking::socket::Header, networking::socket::SocketError> =
         socket.send(Message::Text(String::from("Hellow")));
 }
+
+#[test]
+fn control_frames() {
+    use networking::socket::{Frame, FrameKind, SocketError};
+
+    #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    enum Message {
+        Text(String),
+    }
+
+    const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let client_stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server_stream, _) = listener.accept().unwrap();
+    client_stream.set_nonblocking(true).unwrap();
+    server_stream.set_nonblocking(true).unwrap();
+
+    let mut client: networking::Socket<Message, Message> = networking::Socket::new(client_stream);
+    let mut server: networking::Socket<Message, Message> = networking::Socket::new(server_stream);
+
+    // Control frames have no payload
+    let header = client.ping().unwrap();
+    assert_eq!(header.kind, FrameKind::Ping);
+    assert_eq!(header.size, 0);
+
+    client.send(Message::Text(String::from("Hi"))).unwrap();
+
+    // try_recv answers the ping and only gives back data
+    let (header, message) = server.recv(CHECK_DELAY).unwrap();
+    assert_eq!(header.kind, FrameKind::Data);
+    assert_eq!(message, Message::Text(String::from("Hi")));
+
+    let frame = loop {
+        match client.try_recv_frame() {
+            Ok((_, frame)) => break frame,
+            Err(SocketError::StreamRead(e)) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(CHECK_DELAY)
+            }
+            Err(e) => panic!("{e}"),
+        }
+    };
+    assert_eq!(frame, Frame::Pong);
+
+    // Dropping a socket sends a close frame
+    drop(client);
+    assert!(matches!(server.recv(CHECK_DELAY), Err(SocketError::Exited)));
+}
+
+#[test]
+fn large_message() {
+    // Way bigger than the os buffers, the frame arrives in many pieces
+    const SIZE: usize = 8 * 1024 * 1024;
+
+    let (mut client, mut server) = common::loopback_pair::<(), Vec<u8>>();
+
+    let message = (0..SIZE).map(|i| i as u8).collect::<Vec<u8>>();
+
+    let (received, duration) = time::timeit_mut(|| {
+        client.send(message.clone()).unwrap();
+        assert_ne!(client.pending_bytes(), 0);
+
+        loop {
+            client.flush().unwrap();
+            match server.try_recv() {
+                Ok((_header, received)) => break received,
+                Err(e) if common::is_would_block(&e) => (),
+                Err(e) => panic!("{e}"),
+            }
+        }
+    });
+
+    assert_eq!(client.pending_bytes(), 0);
+    assert_eq!(received, message);
+
+    println!(
+        "Received a {}MiB message in {}",
+        SIZE / 1024 / 1024,
+        time::format(&duration, 2)
+    );
+}
+
+#[test]
+fn throughput() {
+    const COUNT: usize = 100_000;
+
+    #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Position {
+        id: u32,
+        x: f32,
+        y: f32,
+    }
+
+    let (mut client, mut server) = common::loopback_pair::<(), Position>();
+
+    let (_, duration) = time::timeit_mut(|| {
+        let mut received = 0;
+        for id in 0..COUNT as u32 {
+            client.send(Position { id, x: 0., y: 1. }).unwrap();
+        }
+
+        while received != COUNT {
+            client.flush().unwrap();
+            match server.try_recv() {
+                Ok((_header, position)) => {
+                    assert_eq!(position.id, received as u32);
+                    received += 1;
+                }
+                Err(e) if common::is_would_block(&e) => (),
+                Err(e) => panic!("{e}"),
+            }
+        }
+    });
+
+    println!(
+        "Received {COUNT} messages in {} ({:.0} msg/s)",
+        time::format(&duration, 2),
+        COUNT as f64 / duration.as_secs_f64()
+    );
+}
+
+fn assert_closed(mut raw: std::net::TcpStream) {
+    use std::io::Read as _;
+
+    // Once the socket has shut the connection down, the reads only give EOF
+    let mut buffer = [0; 64];
+    assert!(matches!(raw.read(&mut buffer), Ok(0)))
+}
+
+#[test]
+fn frame_too_large() {
+    use {
+        networking::socket::{FrameKind, Header, SocketError},
+        std::io::Write as _,
+    };
+
+    const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+
+    let (mut raw, mut socket) = common::raw_pair::<Vec<u8>, ()>();
+    socket.set_max_frame_size(1024);
+
+    // Would make the socket allocate a whole TiB
+    let header = Header::new(FrameKind::Data, 1 << 40);
+    raw.write_all(&bincode::serialize(&header).unwrap())
+        .unwrap();
+
+    assert!(matches!(
+        socket.recv(CHECK_DELAY),
+        Err(SocketError::FrameTooLarge {
+            size: 1_099_511_627_776,
+            max: 1024
+        })
+    ));
+
+    assert_closed(raw);
+}
+
+#[test]
+fn invalid_header() {
+    use {networking::socket::SocketError, std::io::Write as _};
+
+    const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+
+    let (mut raw, mut socket) = common::raw_pair::<Vec<u8>, ()>();
+
+    // A size of 0 and a frame kind that doesn't exist, the rest of the header is left to 0
+    let mut header = 0u64.to_le_bytes().to_vec();
+    header.extend_from_slice(&42u32.to_le_bytes());
+    header.resize(networking::socket::HEADER_SIZE as usize, 0);
+    raw.write_all(&header).unwrap();
+
+    assert!(matches!(
+        socket.recv(CHECK_DELAY),
+        Err(SocketError::Deserialization(_))
+    ));
+
+    assert_closed(raw);
+}
+
+#[test]
+fn frame_size_limit() {
+    use networking::socket::SocketError;
+
+    const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+
+    let (mut client, mut server) = common::loopback_pair::<(), Vec<u8>>();
+    server.set_max_frame_size(1024);
+
+    // bincode adds the length of the vec (8 bytes)
+    client.send(vec![1; 1016]).unwrap();
+    let (header, message) = server.recv(CHECK_DELAY).unwrap();
+    assert_eq!(header.size, 1024);
+    assert_eq!(message, vec![1; 1016]);
+
+    client.send(vec![1; 1017]).unwrap();
+    assert!(matches!(
+        server.recv(CHECK_DELAY),
+        Err(SocketError::FrameTooLarge {
+            size: 1025,
+            max: 1024
+        })
+    ));
+
+    // The sending side also checks it
+    client.set_max_frame_size(16);
+    assert!(matches!(
+        client.send(vec![1; 100]),
+        Err(SocketError::FrameTooLarge { size: 108, max: 16 })
+    ));
+    assert_eq!(client.pending_bytes(), 0);
+}
+
+#[test]
+fn handshake() {
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    // Both sides wait for the other, so one of them is in another thread
+    let (mut client, mut server) = common::loopback_pair::<u32, String>();
+    let server_thread = std::thread::spawn(move || {
+        server.handshake(TIMEOUT).unwrap();
+        server
+    });
+    client.handshake(TIMEOUT).unwrap();
+    let mut server = server_thread.join().unwrap();
+
+    // Nothing is left in the way of the frames
+    client.send(String::from("Hi")).unwrap();
+    let (_, message) = server.recv(std::time::Duration::from_millis(1)).unwrap();
+    assert_eq!(message, "Hi");
+}
+
+#[test]
+fn handshake_mismatch() {
+    use {
+        networking::socket::{Handshake, HandshakeError, SocketError, PROTOCOL_VERSION},
+        std::io::Write as _,
+    };
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    // Both sides think they receive u32
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let client_stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server_stream, _) = listener.accept().unwrap();
+    client_stream.set_nonblocking(true).unwrap();
+    server_stream.set_nonblocking(true).unwrap();
+
+    let mut client: networking::Socket<u32, String> = networking::Socket::new(client_stream);
+    let mut server: networking::Socket<u32, u32> = networking::Socket::new(server_stream);
+
+    let server_thread = std::thread::spawn(move || server.handshake(TIMEOUT));
+    assert!(matches!(
+        client.handshake(TIMEOUT),
+        Err(SocketError::Handshake(HandshakeError::Messages {
+            read: "u32",
+            write: "alloc::string::String"
+        }))
+    ));
+    assert!(matches!(
+        server_thread.join().unwrap(),
+        Err(SocketError::Handshake(HandshakeError::Messages {
+            read: "u32",
+            write: "u32"
+        }))
+    ));
+
+    // An older version
+    let (mut raw, mut socket) = common::raw_pair::<u32, u32>();
+    let mut handshake = Handshake::new::<u32, u32>();
+    handshake.version = 0;
+    raw.write_all(&bincode::serialize(&handshake).unwrap())
+        .unwrap();
+    assert!(matches!(
+        socket.handshake(TIMEOUT),
+        Err(SocketError::Handshake(HandshakeError::Version {
+            local: PROTOCOL_VERSION,
+            remote: 0
+        }))
+    ));
+
+    // Not even a networking socket
+    let (mut raw, mut socket) = common::raw_pair::<u32, u32>();
+    raw.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    assert!(matches!(
+        socket.handshake(TIMEOUT),
+        Err(SocketError::Handshake(HandshakeError::Magic))
+    ));
+
+    // No answer
+    let (_raw, mut socket) = common::raw_pair::<u32, u32>();
+    assert!(matches!(
+        socket.handshake(std::time::Duration::from_millis(50)),
+        Err(SocketError::Handshake(HandshakeError::Timeout))
+    ));
+}
@@ -0,0 +1,147 @@
+fn stats(window: std::time::Duration) -> networking::NetworkStats<u32, u32> {
+    use networking::stats::{
+        config::{BpsConfig, RttConfig},
+        StatConfig,
+    };
+
+    networking::NetworkStats::new(StatConfig {
+        bps: BpsConfig {
+            enabled: true,
+            window,
+        },
+        rtt: RttConfig {
+            enabled: true,
+            window,
+            ..Default::default()
+        },
+        message_types: networking::stats::config::MessageTypeConfig { enabled: true },
+    })
+}
+
+#[test]
+fn rtt() {
+    const MS: std::time::Duration = std::time::Duration::from_millis(1);
+
+    let mut stats = stats(std::time::Duration::from_secs(10));
+    assert_eq!(stats.smoothed_rtt(), std::time::Duration::ZERO);
+    assert_eq!(stats.rtt_percentile(50.), std::time::Duration::ZERO);
+
+    // The first sample is the smoothed rtt, and half of it the jitter
+    stats.set_rtt(100 * MS);
+    assert_eq!(stats.smoothed_rtt(), 100 * MS);
+    assert_eq!(stats.rtt_jitter(), 50 * MS);
+
+    for ms in (10..=90).step_by(10) {
+        stats.set_rtt(ms * MS);
+    }
+    assert_eq!(stats.get_rtt(), 90 * MS);
+    assert_eq!(stats.rtt_sample_count(), 10);
+    assert_eq!(stats.min_rtt(), 10 * MS);
+    assert_eq!(stats.max_rtt(), 100 * MS);
+    assert_eq!(stats.rtt_percentile(50.), 50 * MS);
+    assert_eq!(stats.rtt_percentile(95.), 100 * MS);
+    assert_eq!(stats.rtt_percentile(99.), 100 * MS);
+
+    // The smoothed rtt lags behind the samples
+    assert!(stats.smoothed_rtt() > 50 * MS && stats.smoothed_rtt() < 100 * MS);
+    assert!(stats.rtt_jitter() > std::time::Duration::ZERO);
+}
+
+#[test]
+fn window() {
+    use networking::socket::{FrameKind, Header, HEADER_SIZE};
+
+    const WINDOW: std::time::Duration = std::time::Duration::from_millis(100);
+
+    let mut stats = stats(WINDOW);
+
+    stats.set_rtt(std::time::Duration::from_millis(500));
+    stats.on_bytes_send(&Header::new(FrameKind::Data, 100));
+    assert_eq!(stats.sent_in_window(), 100 + HEADER_SIZE);
+    // Spread over the window
+    assert_eq!(stats.bps_sent(), (100 + HEADER_SIZE) * 10);
+
+    std::thread::sleep(WINDOW * 2);
+
+    // Only the samples of the window are left
+    stats.set_rtt(std::time::Duration::from_millis(20));
+    assert_eq!(stats.rtt_sample_count(), 1);
+    assert_eq!(stats.max_rtt(), std::time::Duration::from_millis(20));
+    stats.on_bytes_recv(&Header::new(FrameKind::Data, 10));
+    assert_eq!(stats.total_sent(), 100 + HEADER_SIZE);
+    assert_eq!(stats.total_received(), 10);
+}
+
+#[test]
+fn packets() {
+    use networking::socket::{FrameKind, Header};
+
+    let mut stats = stats(std::time::Duration::from_secs(10));
+
+    stats.on_bytes_send(&Header::new(FrameKind::Data, 4));
+    stats.on_bytes_send(&Header::new(FrameKind::Request, 4));
+    stats.on_bytes_send(&Header::new(FrameKind::Ping, 0));
+    stats.on_bytes_recv(&Header::new(FrameKind::Pong, 0));
+    stats.on_bytes_recv(&Header::new(FrameKind::Response, 4));
+
+    // The pings and pongs are packets, not messages
+    assert_eq!(stats.messages_sent(), 2);
+    assert_eq!(stats.packets_sent(), 3);
+    assert_eq!(stats.messages_received(), 1);
+    assert_eq!(stats.packets_received(), 2);
+}
+
+#[test]
+fn message_types() {
+    use networking::{
+        socket::{FrameKind, Header},
+        stats::message_type_name,
+    };
+
+    #[derive(serde::Serialize)]
+    enum Message {
+        Chat(String),
+        Position { x: f32, y: f32 },
+        Ping,
+    }
+    #[derive(serde::Serialize)]
+    struct Snapshot(Vec<u8>);
+
+    assert_eq!(message_type_name(&Message::Chat(String::new())), "Chat");
+    assert_eq!(
+        message_type_name(&Message::Position { x: 0., y: 0. }),
+        "Position"
+    );
+    assert_eq!(message_type_name(&Message::Ping), "Ping");
+    assert_eq!(message_type_name(&Snapshot(Vec::new())), "Snapshot");
+    assert_eq!(message_type_name(&42u32), "u32");
+
+    let mut stats = stats(std::time::Duration::from_secs(10));
+    let name_opt = stats.message_type_name_opt(&7u32);
+    assert_eq!(name_opt, Some("u32"));
+
+    stats.on_message_send(Some("Position"), &Header::new(FrameKind::Data, 8));
+    stats.on_message_send(Some("Position"), &Header::new(FrameKind::Data, 8));
+    stats.on_message_send(Some("Chat"), &Header::new(FrameKind::Data, 100));
+    stats.on_message_recv(Some("Position"), &Header::new(FrameKind::Data, 8));
+    stats.on_message_recv(Some("Ping"), &Header::new(FrameKind::Data, 4));
+
+    let position = stats.message_type("Position");
+    assert_eq!(position.messages_sent, 2);
+    assert_eq!(position.messages_received, 1);
+    assert_eq!(position.bytes(), 24);
+
+    // The chatty one first
+    let table = stats.message_types();
+    let names = table.rows.iter().map(|row| row.name).collect::<Vec<_>>();
+    assert_eq!(names, ["Chat", "Position", "Ping"]);
+
+    let text = table.to_string();
+    assert_eq!(text.lines().count(), 4);
+    assert!(text.lines().nth(1).unwrap().starts_with("Chat"));
+
+    // Nothing is tracked by default
+    let stats = networking::NetworkStats::<u32, u32>::new(Default::default());
+    assert_eq!(stats.message_type_name_opt(&7u32), None);
+    assert!(stats.message_types().rows.is_empty());
+}
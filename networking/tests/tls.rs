@@ -0,0 +1,225 @@
+#![cfg(feature = "tls")]
+
+use {
+    networking::{
+        proxy::{DisconnectReason, ProxyError, ProxyMessage},
+        rustls::{
+            pki_types::{CertificateDer, PrivateKeyDer},
+            RootCertStore,
+        },
+        server::ServerEvent,
+        tls::TlsConfig,
+    },
+    std::str::FromStr as _,
+};
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+enum Message {
+    Text(String),
+}
+
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+struct Authority {
+    cert: rcgen::Certificate,
+    key: rcgen::KeyPair,
+}
+
+impl Authority {
+    fn new() -> Self {
+        let mut params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+
+        Self { cert, key }
+    }
+
+    fn roots(&self) -> RootCertStore {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.cert.der().clone()).unwrap();
+        roots
+    }
+
+    fn sign(&self, name: &str) -> (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>) {
+        let params = rcgen::CertificateParams::new(vec![name.to_string()]).unwrap();
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = params.signed_by(&key, &self.cert, &self.key).unwrap();
+
+        (
+            vec![cert.der().clone()],
+            PrivateKeyDer::Pkcs8(key.serialize_der().into()),
+        )
+    }
+}
+
+fn start_server(tls: TlsConfig) -> networking::server::ServerController<Message, Message> {
+    let server_cfg = networking::server::ServerConfig {
+        addr: std::net::SocketAddr::from_str("127.0.0.1:0").unwrap(),
+        run_tps: 100,
+        stat_cfg: Default::default(),
+        max_frame_size: networking::socket::DEFAULT_MAX_FRAME_SIZE,
+        compression: None,
+        channels: Default::default(),
+        tcp: Default::default(),
+        heartbeat: None,
+        tls: Some(tls),
+    };
+
+    networking::Server::start_new(server_cfg).unwrap()
+}
+
+fn start_client(
+    addr: std::net::SocketAddr,
+    tls: TlsConfig,
+) -> networking::proxy::ProxyController<Message, Message> {
+    let proxy_cfg = networking::proxy::ProxyConfig {
+        tls: Some(tls),
+        ..networking::proxy::ProxyConfig::new(addr)
+    };
+
+    networking::Proxy::start_new(proxy_cfg, None)
+}
+
+// Messages sent before the end of the handshakes would be dropped, see ProxyConfig::keep_msg_while_disconnected
+fn wait_connected(client: &networking::proxy::ProxyController<Message, Message>) {
+    assert!(matches!(
+        client.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Connected { .. }
+    ));
+}
+
+// The tls session is set up by the handshake, without a reconnect policy the proxy doesn't retry it
+fn assert_rejected(client: &networking::proxy::ProxyController<Message, Message>) {
+    assert!(matches!(
+        client.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Disconnected {
+            reason: DisconnectReason::Error(ProxyError::Handshake { .. })
+        }
+    ));
+    assert_eq!(client.recv_timeout(TIMEOUT).unwrap(), ProxyMessage::Exit);
+}
+
+#[test]
+fn tls() {
+    let authority = Authority::new();
+    let (cert_chain, key) = authority.sign("localhost");
+
+    let server = start_server(TlsConfig::server(cert_chain, key).unwrap());
+    let client = start_client(
+        server.local_addr(),
+        TlsConfig::client(authority.roots(), "localhost").unwrap(),
+    );
+
+    let ServerEvent::Connected(id) = server.recv_timeout(TIMEOUT).unwrap() else {
+        panic!("Expected the client to connect")
+    };
+    wait_connected(&client);
+
+    client.send(Message::Text(String::from("Hi"))).unwrap();
+    assert_eq!(
+        server.recv_timeout(TIMEOUT).unwrap(),
+        ServerEvent::Message(id, Message::Text(String::from("Hi")))
+    );
+
+    server
+        .send_to(id, Message::Text(String::from("Hello")))
+        .unwrap();
+    assert_eq!(
+        client.recv_timeout(TIMEOUT).unwrap(),
+        ProxyMessage::Forward(Message::Text(String::from("Hello")))
+    );
+}
+
+#[test]
+fn untrusted_server() {
+    let authority = Authority::new();
+    let (cert_chain, key) = authority.sign("localhost");
+
+    let server = start_server(TlsConfig::server(cert_chain, key).unwrap());
+
+    // Signed by the right authority, but not for that name
+    let client = start_client(
+        server.local_addr(),
+        TlsConfig::client(authority.roots(), "example.com").unwrap(),
+    );
+    assert_rejected(&client);
+
+    // Doesn't know the authority
+    let client = start_client(
+        server.local_addr(),
+        TlsConfig::client(Authority::new().roots(), "localhost").unwrap(),
+    );
+    assert_rejected(&client);
+}
+
+#[test]
+fn client_auth() {
+    let authority = Authority::new();
+    let (cert_chain, key) = authority.sign("localhost");
+
+    let server = start_server(
+        TlsConfig::server_with_client_auth(cert_chain, key, authority.roots()).unwrap(),
+    );
+
+    // Without certificate
+    let client = start_client(
+        server.local_addr(),
+        TlsConfig::client(authority.roots(), "localhost").unwrap(),
+    );
+    assert_rejected(&client);
+
+    let ServerEvent::Connected(id) = server.recv_timeout(TIMEOUT).unwrap() else {
+        panic!("Expected the client to connect")
+    };
+    assert_eq!(
+        server.recv_timeout(TIMEOUT).unwrap(),
+        ServerEvent::Disconnected(id)
+    );
+
+    // With one
+    let (client_cert_chain, client_key) = authority.sign("client");
+    let client = start_client(
+        server.local_addr(),
+        TlsConfig::client_with_cert(
+            authority.roots(),
+            "localhost",
+            client_cert_chain,
+            client_key,
+        )
+        .unwrap(),
+    );
+
+    let ServerEvent::Connected(id) = server.recv_timeout(TIMEOUT).unwrap() else {
+        panic!("Expected the client to connect")
+    };
+    wait_connected(&client);
+
+    client.send(Message::Text(String::from("Hi"))).unwrap();
+    assert_eq!(
+        server.recv_timeout(TIMEOUT).unwrap(),
+        ServerEvent::Message(id, Message::Text(String::from("Hi")))
+    );
+}
+
+#[test]
+fn wrong_side() {
+    let authority = Authority::new();
+
+    let server_cfg = networking::server::ServerConfig {
+        addr: std::net::SocketAddr::from_str("127.0.0.1:0").unwrap(),
+        run_tps: 100,
+        stat_cfg: Default::default(),
+        max_frame_size: networking::socket::DEFAULT_MAX_FRAME_SIZE,
+        compression: None,
+        channels: Default::default(),
+        tcp: Default::default(),
+        heartbeat: None,
+        tls: Some(TlsConfig::client(authority.roots(), "localhost").unwrap()),
+    };
+
+    assert!(matches!(
+        networking::Server::<Message, Message>::start_new(server_cfg),
+        Err(networking::server::ServerError::Config(_))
+    ));
+}
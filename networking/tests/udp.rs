@@ -0,0 +1,291 @@
+use networking::udp::{Delivery, PacketHeader, PacketKind, UdpError};
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+enum Message {
+    Text(String),
+    Number(u32),
+}
+
+const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+fn connected_pair() -> (std::net::UdpSocket, std::net::UdpSocket) {
+    let a = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let b = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    a.connect(b.local_addr().unwrap()).unwrap();
+    b.connect(a.local_addr().unwrap()).unwrap();
+    a.set_nonblocking(true).unwrap();
+    b.set_nonblocking(true).unwrap();
+    (a, b)
+}
+
+fn stat_cfg() -> networking::stats::StatConfig {
+    networking::stats::StatConfig {
+        bps: networking::stats::config::BpsConfig {
+            enabled: true,
+            ..Default::default()
+        },
+        rtt: networking::stats::config::RttConfig {
+            enabled: true,
+            ..Default::default()
+        },
+        message_types: Default::default(),
+    }
+}
+
+fn pair() -> (
+    networking::UdpSocket<Message, Message>,
+    networking::UdpSocket<Message, Message>,
+) {
+    let (a, b) = connected_pair();
+    (
+        networking::UdpSocket::new(a, stat_cfg()),
+        networking::UdpSocket::new(b, stat_cfg()),
+    )
+}
+
+// A raw socket on one side, so the test can drop, reorder and craft packets
+fn raw_pair() -> (std::net::UdpSocket, networking::UdpSocket<Message, Message>) {
+    let (raw, socket) = connected_pair();
+    (raw, networking::UdpSocket::new(socket, stat_cfg()))
+}
+
+fn packet(kind: PacketKind, sequence: u32, message: Option<&Message>) -> Vec<u8> {
+    let mut bytes = bincode::serialize(&PacketHeader { kind, sequence }).unwrap();
+    if let Some(message) = message {
+        bytes.extend(bincode::serialize(message).unwrap());
+    }
+    bytes
+}
+
+fn raw_recv(raw: &std::net::UdpSocket) -> Vec<u8> {
+    let start = std::time::Instant::now();
+    let mut buffer = vec![0; 65_535];
+    loop {
+        match raw.recv(&mut buffer) {
+            Ok(len) => return buffer[..len].to_vec(),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                assert!(start.elapsed() < TIMEOUT, "Nothing was received");
+                std::thread::sleep(CHECK_DELAY);
+            }
+            Err(e) => panic!("{e}"),
+        }
+    }
+}
+
+// Reads what was received (acks, dropped packets) and expects no message
+fn assert_nothing_left(socket: &mut networking::UdpSocket<Message, Message>) {
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    match socket.try_recv() {
+        Err(UdpError::Recv(e)) if e.kind() == std::io::ErrorKind::WouldBlock => (),
+        other => panic!("Expected only acks, got {other:?}"),
+    }
+}
+
+#[test]
+fn delivery() {
+    let (mut client, mut server) = pair();
+
+    for delivery in [
+        Delivery::Unreliable,
+        Delivery::UnreliableSequenced,
+        Delivery::ReliableOrdered,
+    ] {
+        let message = Message::Text(format!("{delivery:?}"));
+        let sent_header = client.send(message.clone(), delivery).unwrap();
+        assert_eq!(sent_header.kind, PacketKind::from(delivery));
+
+        let (header, received) = server.recv(CHECK_DELAY).unwrap();
+        assert_eq!(header, sent_header);
+        assert_eq!(received, message);
+    }
+
+    assert_eq!(client.pending_reliable(), 1);
+    assert_nothing_left(&mut client);
+    assert_eq!(client.pending_reliable(), 0);
+}
+
+#[test]
+fn reliable_ordered() {
+    let (raw, mut socket) = raw_pair();
+
+    raw.send(&packet(PacketKind::Reliable, 1, Some(&Message::Number(1))))
+        .unwrap();
+    raw.send(&packet(PacketKind::Reliable, 0, Some(&Message::Number(0))))
+        .unwrap();
+    raw.send(&packet(PacketKind::Reliable, 2, Some(&Message::Number(2))))
+        .unwrap();
+    // Sent again, as if the ack was lost
+    raw.send(&packet(PacketKind::Reliable, 0, Some(&Message::Number(0))))
+        .unwrap();
+    raw.send(&packet(PacketKind::Reliable, 3, Some(&Message::Number(3))))
+        .unwrap();
+
+    for i in 0..4 {
+        let (header, message) = socket.recv(CHECK_DELAY).unwrap();
+        assert_eq!(header.sequence, i);
+        assert_eq!(message, Message::Number(i));
+    }
+
+    // Every copy is acked
+    let acks = (0..5)
+        .map(|_| bincode::deserialize::<PacketHeader>(&raw_recv(&raw)).unwrap())
+        .collect::<Vec<_>>();
+    assert!(acks.iter().all(|ack| ack.kind == PacketKind::Ack));
+    assert_eq!(
+        acks.iter().map(|ack| ack.sequence).collect::<Vec<_>>(),
+        vec![1, 0, 2, 0, 3]
+    );
+}
+
+#[test]
+fn resend() {
+    let (raw, mut socket) = raw_pair();
+
+    socket
+        .send(Message::Text(String::from("Hi")), Delivery::ReliableOrdered)
+        .unwrap();
+    let first = raw_recv(&raw);
+
+    // Not acked, so it's sent again once the resend delay is over
+    std::thread::sleep(std::time::Duration::from_millis(150));
+    socket.update().unwrap();
+    assert_eq!(raw_recv(&raw), first);
+    assert_eq!(socket.pending_reliable(), 1);
+
+    raw.send(&packet(PacketKind::Ack, 0, None)).unwrap();
+    assert_nothing_left(&mut socket);
+    assert_eq!(socket.pending_reliable(), 0);
+
+    // The rtt can't be known from a packet sent twice
+    assert_eq!(socket.rtt(), None);
+}
+
+// The other side is considered gone once a packet has been resent for too long
+#[test]
+fn ack_timeout() {
+    const ACK_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(300);
+
+    let (raw, mut socket) = raw_pair();
+    socket.set_ack_timeout(ACK_TIMEOUT);
+
+    let start = std::time::Instant::now();
+    socket
+        .send(Message::Text(String::from("Hi")), Delivery::ReliableOrdered)
+        .unwrap();
+
+    let error = loop {
+        assert!(start.elapsed() < TIMEOUT, "The packet was resent forever");
+        match socket.update() {
+            Ok(()) => std::thread::sleep(CHECK_DELAY),
+            Err(e) => break e,
+        }
+    };
+    assert!(matches!(
+        error,
+        UdpError::AckTimeout {
+            sequence: 0,
+            timeout: ACK_TIMEOUT
+        }
+    ));
+    assert!(start.elapsed() >= ACK_TIMEOUT);
+
+    // It was resent until then
+    let sends = std::iter::from_fn(|| {
+        let mut buffer = [0; 64];
+        raw.recv(&mut buffer).ok()
+    })
+    .count();
+    assert!(sends > 1, "{sends}");
+}
+
+// A packet too far ahead isn't kept, nor acked, the sender resends it later
+#[test]
+fn reliable_window() {
+    let (raw, mut socket) = raw_pair();
+
+    raw.send(&packet(
+        PacketKind::Reliable,
+        5000,
+        Some(&Message::Number(5000)),
+    ))
+    .unwrap();
+    raw.send(&packet(PacketKind::Reliable, 1, Some(&Message::Number(1))))
+        .unwrap();
+    assert_nothing_left(&mut socket);
+
+    // Only the one within the window is acked
+    let ack = bincode::deserialize::<PacketHeader>(&raw_recv(&raw)).unwrap();
+    assert_eq!(ack.sequence, 1);
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    assert!(raw.recv(&mut [0; 64]).is_err());
+
+    raw.send(&packet(PacketKind::Reliable, 0, Some(&Message::Number(0))))
+        .unwrap();
+    assert_eq!(socket.recv(CHECK_DELAY).unwrap().1, Message::Number(0));
+    assert_eq!(socket.recv(CHECK_DELAY).unwrap().1, Message::Number(1));
+    assert_nothing_left(&mut socket);
+}
+
+#[test]
+fn sequenced() {
+    let (raw, mut socket) = raw_pair();
+
+    for i in [5, 3, 6, 6, 2] {
+        raw.send(&packet(PacketKind::Sequenced, i, Some(&Message::Number(i))))
+            .unwrap();
+    }
+
+    assert_eq!(socket.recv(CHECK_DELAY).unwrap().1, Message::Number(5));
+    assert_eq!(socket.recv(CHECK_DELAY).unwrap().1, Message::Number(6));
+    assert_nothing_left(&mut socket);
+
+    // Sequences wrap around, u32::MAX comes right before 0 so it's older than 6
+    raw.send(&packet(
+        PacketKind::Sequenced,
+        u32::MAX,
+        Some(&Message::Number(u32::MAX)),
+    ))
+    .unwrap();
+    assert!(matches!(socket.try_recv(), Err(UdpError::Recv(_))));
+}
+
+#[test]
+fn rtt() {
+    let (mut client, mut server) = pair();
+
+    client
+        .send(Message::Text(String::from("Hi")), Delivery::ReliableOrdered)
+        .unwrap();
+    server.recv(CHECK_DELAY).unwrap();
+
+    let start = std::time::Instant::now();
+    while client.rtt().is_none() {
+        assert!(start.elapsed() < TIMEOUT, "The packet was never acked");
+        let _ = client.try_recv();
+        std::thread::sleep(CHECK_DELAY);
+    }
+
+    let rtt = client.rtt().unwrap();
+    assert_eq!(client.stats().get_rtt(), rtt);
+
+    // The message and its ack
+    assert_eq!(client.stats().total_sent(), server.stats().total_received());
+    assert_eq!(client.stats().total_received(), server.stats().total_sent());
+    assert_eq!(client.stats().total_received(), 8);
+}
+
+#[test]
+fn packet_too_large() {
+    let (mut client, _server) = pair();
+    client.set_max_datagram_size(64);
+
+    assert!(matches!(
+        client.send(
+            Message::Text(String::from_utf8(vec![b'a'; 64]).unwrap()),
+            Delivery::ReliableOrdered
+        ),
+        Err(UdpError::PacketTooLarge { max: 64, .. })
+    ));
+    assert_eq!(client.pending_reliable(), 0);
+}